@@ -11,38 +11,239 @@ use std::fs::{File, OpenOptions};
 use std::os::unix::fs::FileExt;
 use std::mem::size_of;
 use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
-static META_DATA_FILE_NAME: &'static str = "DB_META_FILE";
+use crate::page_management::page_file::{PageFileManager, PAGE_SIZE};
+use crate::record_management::record_file_manager::RecordFileManager;
+use crate::record_management::record_file_handle::RecordFileHandle;
+use crate::indexing::AttrType;
+use crate::indexing::file_manager::IndexFileManager;
+use crate::indexing::index_handle::IndexHandle;
+use crate::errors::Error;
+use crate::types::{PageNum, TableSchema};
+use crate::wal::{WalRecord, WalReader, WalWriter};
 
+static META_DATA_FILE_NAME: &str = "DB_META_FILE";
+static WAL_FILE_NAME: &str = "DB_WAL_FILE";
+
+const MAX_TABLE_NAME_LEN: usize = 64;
+const MAX_INDEXES: usize = 4;//max number of indexes on a table.
+//index_num: u32|attr_type: u8|attr_length: u64|is_active: u8
+const INDEX_ENTRY_SIZE: usize = 4 + 1 + 8 + 1;
+//name|file_num: u16|record_size: u64|is_active: u8|indexes
+const TABLE_ENTRY_SIZE: usize = MAX_TABLE_NAME_LEN + 2 + 8 + 1 + MAX_INDEXES * INDEX_ENTRY_SIZE;
+const MAX_TABLES: usize = PAGE_SIZE / TABLE_ENTRY_SIZE;
+const CATALOG_OFFSET: u64 = PAGE_SIZE as u64;//the catalog is stored in the second page of the metadata file.
+const SCHEMA_OFFSET: u64 = CATALOG_OFFSET + PAGE_SIZE as u64;//schema of the i-th catalog entry is stored in the page at SCHEMA_OFFSET + i * PAGE_SIZE.
+
+//all fields are kept in the metadata file, even those not read yet.
+#[allow(dead_code)]
 #[derive(Debug, Copy, Clone)]
 struct DBMetaData {
     num_record_files: u16,
-    num_indexing_files: u16
+    num_indexing_files: u16,
+    num_files: u16,//num_files of the PageFileManager, next file number to assign.
+}
+
+/*
+ * An entry of the catalog, describing a table.
+ * A table is stored in a record file named by the table name.
+ * Entries of dropped tables are not active and can be reused.
+ *
+ * Entries are stored field by field in TABLE_ENTRY_SIZE bytes, 
+ * little-endian, see serialize.
+ */
+#[derive(Debug, Copy, Clone)]
+struct TableEntry {
+    name: [u8; MAX_TABLE_NAME_LEN],//null-padded
+    file_num: u16,
+    record_size: usize,
+    is_active: bool,
+    indexes: [IndexEntry; MAX_INDEXES],
+}
+
+/*
+ * An index on a table, index files of a table are named by the 
+ * table name with suffix ".idx" followed by index_num.
+ */
+#[derive(Debug, Copy, Clone)]
+struct IndexEntry {
+    index_num: u32,
+    attr_type: AttrType,
+    attr_length: usize,
+    is_active: bool,
+}
+
+/*
+ * A transaction is identified by its id in the write ahead log.
+ * Ids keep growing across sessions: the first id of a session 
+ * follows the largest one in the log, so records of transactions 
+ * from earlier sessions are never mistaken for a new one's.
+ */
+#[derive(Debug)]
+pub struct Transaction {
+    pub id: u64
 }
 
 pub struct DatabaseManager {
+    #[allow(dead_code)]
     meta_data: DBMetaData,
-    fp: File
+    dir: PathBuf,//all files of the database are in this directory.
+    fp: File,
+    pfm: PageFileManager,
+    catalog: [TableEntry; MAX_TABLES],
+    wal: WalWriter,
+    next_txn_id: u64
+}
+
+impl TableEntry {
+    fn empty() -> Self {
+        Self {
+            name: [0; MAX_TABLE_NAME_LEN],
+            file_num: 0,
+            record_size: 0,
+            is_active: false,
+            indexes: [IndexEntry {
+                index_num: 0,
+                attr_type: AttrType::INT,
+                attr_length: 0,
+                is_active: false,
+            }; MAX_INDEXES],
+        }
+    }
+
+    fn serialize(&self) -> [u8; TABLE_ENTRY_SIZE] {
+        let mut b = [0u8; TABLE_ENTRY_SIZE];
+        let mut off = MAX_TABLE_NAME_LEN;
+        b[..off].copy_from_slice(&self.name);
+        b[off..off+2].copy_from_slice(&self.file_num.to_le_bytes());
+        b[off+2..off+10].copy_from_slice(&(self.record_size as u64).to_le_bytes());
+        b[off+10] = self.is_active as u8;
+        off += 11;
+        for index_entry in self.indexes.iter() {
+            b[off..off+INDEX_ENTRY_SIZE].copy_from_slice(&index_entry.serialize());
+            off += INDEX_ENTRY_SIZE;
+        }
+        b
+    }
+
+    fn deserialize(b: &[u8]) -> Result<Self, Error> {
+        let mut entry = Self::empty();
+        let mut off = MAX_TABLE_NAME_LEN;
+        entry.name.copy_from_slice(&b[..off]);
+        entry.file_num = u16::from_le_bytes([b[off], b[off+1]]);
+        let mut record_size = [0u8; 8];
+        record_size.copy_from_slice(&b[off+2..off+10]);
+        entry.record_size = u64::from_le_bytes(record_size) as usize;
+        entry.is_active = decode_bool(b[off+10])?;
+        off += 11;
+        for index_entry in entry.indexes.iter_mut() {
+            *index_entry = IndexEntry::deserialize(&b[off..off+INDEX_ENTRY_SIZE])?;
+            off += INDEX_ENTRY_SIZE;
+        }
+        Ok(entry)
+    }
+
+    fn get_index_base_name(&self) -> String {
+        let mut base_name = self.get_name();
+        base_name.push_str(".idx");
+        base_name
+    }
+
+    fn find_index(&self, index_num: u32) -> Option<usize> {
+        self.indexes.iter().position(|e| e.is_active && e.index_num == index_num)
+    }
+
+    fn get_name(&self) -> String {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(MAX_TABLE_NAME_LEN);
+        String::from_utf8_lossy(&self.name[..len]).into_owned()
+    }
+
+    fn name_equals(&self, name: &str) -> bool {
+        self.is_active && self.get_name() == name
+    }
+}
+
+impl IndexEntry {
+    fn serialize(&self) -> [u8; INDEX_ENTRY_SIZE] {
+        let mut b = [0u8; INDEX_ENTRY_SIZE];
+        b[0..4].copy_from_slice(&self.index_num.to_le_bytes());
+        b[4] = match self.attr_type {
+            AttrType::INT => 0,
+            AttrType::FLOAT => 1,
+            AttrType::STRING => 2,
+            AttrType::BIGINT => 3,
+            AttrType::DOUBLE => 4,
+        };
+        b[5..13].copy_from_slice(&(self.attr_length as u64).to_le_bytes());
+        b[13] = self.is_active as u8;
+        b
+    }
+
+    fn deserialize(b: &[u8]) -> Result<Self, Error> {
+        let attr_type = match b[4] {
+            0 => AttrType::INT,
+            1 => AttrType::FLOAT,
+            2 => AttrType::STRING,
+            3 => AttrType::BIGINT,
+            4 => AttrType::DOUBLE,
+            other => {
+                dbg!(other);
+                return Err(Error::CatalogCorrupted);
+            }
+        };
+        let mut attr_length = [0u8; 8];
+        attr_length.copy_from_slice(&b[5..13]);
+        Ok(Self {
+            index_num: u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            attr_type,
+            attr_length: u64::from_le_bytes(attr_length) as usize,
+            is_active: decode_bool(b[13])?
+        })
+    }
+}
+
+fn decode_bool(b: u8) -> Result<bool, Error> {
+    match b {
+        0 => Ok(false),
+        1 => Ok(true),
+        other => {
+            dbg!(other);
+            Err(Error::CatalogCorrupted)
+        }
+    }
 }
 
 impl DatabaseManager {
+    //opens the database files in the working directory, so there's no Default.
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Self::open(Path::new(""))
+    }
+
+    /*
+     * Open the database in dir, the metadata file and the write 
+     * ahead log are created if not found.
+     */
+    pub fn open(dir: &Path) -> Self {
         let mut meta_data = DBMetaData {
             num_record_files: 0,
-            num_indexing_files: 0
+            num_indexing_files: 0,
+            num_files: 1
         };
 
-        let mut fp: File;
-        match File::open(&META_DATA_FILE_NAME) {
+        let meta_data_path = dir.join(META_DATA_FILE_NAME);
+        let fp: File;
+        match OpenOptions::new().read(true).write(true).open(&meta_data_path) {
             Ok(v) => {
                 fp = v;
             },
             Err(e) => match e.kind() {
                 ErrorKind::NotFound => {
-                    fp = Self::create(&meta_data);
+                    fp = Self::create(&meta_data_path, &meta_data);
                 },
                 other_error => {
-                    panic!(String::from(format!("Database Metadata File Open Error: {:?}", other_error)));
+                    panic!("Database Metadata File Open Error: {:?}", other_error);
                 }
             }
         }
@@ -53,21 +254,475 @@ impl DatabaseManager {
         let read_bytes = fp.read_at(sli, 0).expect("Unix Read Error");
         if read_bytes < size_of::<DBMetaData>() {
             dbg!(read_bytes);
-            panic!("Database Metadata Incomplete Read: {}");
+            panic!("Database Metadata Incomplete Read: {}", read_bytes);
         }
 
         dbg!(&meta_data);
 
+        //a newly created metadata file has no catalog, all entries are inactive.
+        let mut catalog = [TableEntry::empty(); MAX_TABLES];
+        let mut buf = vec![0u8; MAX_TABLES * TABLE_ENTRY_SIZE];
+        let read_bytes = fp.read_at(&mut buf, CATALOG_OFFSET).expect("Unix Read Error");
+        if read_bytes != 0 && read_bytes < buf.len() {
+            dbg!(read_bytes);
+            panic!("Database Catalog Incomplete Read");
+        }
+        if read_bytes != 0 {
+            for (entry, b) in catalog.iter_mut().zip(buf.chunks_exact(TABLE_ENTRY_SIZE)) {
+                *entry = TableEntry::deserialize(b).expect("Database Catalog Corrupted");
+            }
+        }
+
+        let wal_path = dir.join(WAL_FILE_NAME).to_string_lossy().into_owned();
+        let wal = WalWriter::open(&wal_path).expect("Write Ahead Log Open Error");
+        let max_txn_id = WalReader::open(&wal_path)
+            .and_then(|mut reader| reader.max_txn_id())
+            .expect("Write Ahead Log Read Error");
+
         Self {
-            meta_data: meta_data,
-            fp: fp.try_clone().unwrap()
+            meta_data,
+            dir: dir.to_path_buf(),
+            fp: fp.try_clone().unwrap(),
+            pfm: PageFileManager::with_num_files(meta_data.num_files),
+            catalog,
+            wal,
+            next_txn_id: max_txn_id + 1
+        }
+    }
+
+    /*
+     * Create a table with a record file to store its records.
+     * The record size comes from the schema, and the schema is 
+     * stored in the metadata file with the catalog entry.
+     */
+    pub fn create_table(&mut self, name: &str, schema: &TableSchema) -> Result<(), Error> {
+        let schema_bytes = schema.serialize();
+        let record_size = schema.record_size();
+        if schema.columns.is_empty() || record_size == 0 || schema_bytes.len() > PAGE_SIZE {
+            dbg!((schema.columns.len(), record_size, schema_bytes.len()));
+            return Err(Error::InvalidSchema);
+        }
+        if name.is_empty() || name.len() > MAX_TABLE_NAME_LEN || name.as_bytes().contains(&0) {
+            dbg!(name);
+            return Err(Error::InvalidTableName);
+        }
+        if self.find_table(name).is_some() {
+            dbg!(name);
+            return Err(Error::TableExists);
+        }
+        let index = match self.catalog.iter().position(|e| !e.is_active) {
+            None => {
+                return Err(Error::CatalogFull);
+            },
+            Some(v) => v
+        };
+
+        let file_num = self.pfm.get_num_files();
+        if let Err(e) = RecordFileManager::create_file(&self.get_path(name), &mut self.pfm, record_size) {
+            dbg!(&e);
+            return Err(e);
+        }
+
+        self.write_schema(index, &schema_bytes)?;
+        let entry = &mut self.catalog[index];
+        *entry = TableEntry::empty();
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+        entry.file_num = file_num;
+        entry.record_size = record_size;
+        entry.is_active = true;
+        self.write_catalog()
+    }
+
+    /*
+     * Rename a table, its record file and index files are renamed
+     * along with it.
+     */
+    pub fn rename_table(&mut self, name: &str, new_name: &str) -> Result<(), Error> {
+        if new_name.is_empty() || new_name.len() > MAX_TABLE_NAME_LEN || new_name.as_bytes().contains(&0) {
+            dbg!(new_name);
+            return Err(Error::InvalidTableName);
+        }
+        let index = match self.find_table(name) {
+            None => {
+                dbg!(name);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+        if self.find_table(new_name).is_some() {
+            dbg!(new_name);
+            return Err(Error::TableExists);
+        }
+
+        let entry = self.catalog[index];
+        let mut renamed = TableEntry::empty();
+        renamed.name[..new_name.len()].copy_from_slice(new_name.as_bytes());
+        let mut pfh = self.pfm.open_file(&self.get_path(&entry.get_name()))?;
+        pfh.rename(&self.get_path(new_name))?;
+        for index_entry in entry.indexes.iter().filter(|e| e.is_active) {
+            let mut pfh = self.pfm.open_file(&self.get_path(&IndexFileManager::index_file_name(&entry.get_index_base_name(), index_entry.index_num)))?;
+            pfh.rename(&self.get_path(&IndexFileManager::index_file_name(&renamed.get_index_base_name(), index_entry.index_num)))?;
+        }
+
+        self.catalog[index].name = renamed.name;
+        self.write_catalog()
+    }
+
+    /*
+     * Drop a table and remove its record file and index files.
+     * Files are removed before the catalog entry is cleared, so a 
+     * failed drop leaves the table in the catalog and it can be 
+     * dropped again.
+     */
+    pub fn drop_table(&mut self, name: &str) -> Result<(), Error> {
+        let index = match self.find_table(name) {
+            None => {
+                dbg!(name);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+
+        let entry = self.catalog[index];
+        for (slot, index_entry) in entry.indexes.iter().enumerate().filter(|(_, e)| e.is_active) {
+            if let Err(e) = IndexFileManager::drop_index(&self.get_path(&entry.get_index_base_name()), index_entry.index_num, &mut self.pfm) {
+                dbg!(&e);
+                //indexes dropped so far are gone from the catalog as well.
+                self.write_catalog()?;
+                return Err(Error::from(e));
+            }
+            self.catalog[index].indexes[slot].is_active = false;
         }
+        if let Err(e) = RecordFileManager::drop_file(&self.get_path(name), &mut self.pfm) {
+            dbg!(&e);
+            self.write_catalog()?;
+            return Err(e);
+        }
+
+        self.catalog[index] = TableEntry::empty();
+        self.write_catalog()
+    }
+
+    pub fn open_table(&mut self, name: &str) -> Result<RecordFileHandle, Error> {
+        let index = match self.find_table(name) {
+            None => {
+                dbg!(name);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+        RecordFileManager::open_file(&self.get_path(name), &mut self.pfm, self.catalog[index].record_size)
     }
 
+    /*
+     * Create an index on a table, the index is recorded in the 
+     * catalog entry of the table.
+     */
+    pub fn create_index(&mut self, table: &str, index_num: u32, attr_type: AttrType, attr_length: usize) -> Result<IndexHandle, Error> {
+        let index = match self.find_table(table) {
+            None => {
+                dbg!(table);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+        let entry = self.catalog[index];
+        if entry.find_index(index_num).is_some() {
+            dbg!(index_num);
+            return Err(Error::IndexExists);
+        }
+        let slot = match entry.indexes.iter().position(|e| !e.is_active) {
+            None => {
+                return Err(Error::TooManyIndexes);
+            },
+            Some(v) => v
+        };
+
+        let ih = match IndexFileManager::create_file(&self.get_path(&entry.get_index_base_name()), index_num, &mut self.pfm, attr_type, attr_length) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(Error::from(e));
+            },
+            Ok(v) => v
+        };
+
+        self.catalog[index].indexes[slot] = IndexEntry {
+            index_num,
+            attr_type,
+            attr_length,
+            is_active: true,
+        };
+        self.write_catalog()?;
+        Ok(ih)
+    }
+
+    pub fn open_index(&mut self, table: &str, index_num: u32) -> Result<IndexHandle, Error> {
+        let index = match self.find_table(table) {
+            None => {
+                dbg!(table);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+        let entry = self.catalog[index];
+        let index_entry = match entry.find_index(index_num) {
+            None => {
+                dbg!(index_num);
+                return Err(Error::IndexNotFound);
+            },
+            Some(v) => entry.indexes[v]
+        };
+
+        match IndexFileManager::open_file(&self.get_path(&entry.get_index_base_name()), index_num, &mut self.pfm, index_entry.attr_type, index_entry.attr_length) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::from(e))
+            },
+            Ok(v) => Ok(v)
+        }
+    }
+
+    pub fn list_tables(&mut self) -> Result<Vec<String>, Error> {
+        Ok(self.catalog.iter().filter(|e| e.is_active).map(|e| e.get_name()).collect())
+    }
+
+    /*
+     * Copy all database files into dest_path: the record file and index
+     * files of every table, then the metadata file.
+     * Dirty pages are written back first, but pinned pages are not, 
+     * and nothing is synced to disk. Callers that need a durable backup
+     * should make sure no page is pinned and sync the files.
+     * A failed copy returns at once, files already copied are kept.
+     * Returns the number of files copied.
+     */
+    pub fn backup(&mut self, dest_path: &Path) -> Result<usize, Error> {
+        self.pfm.checkpoint()?;
+
+        let mut file_names = self.page_file_names();
+        file_names.push(String::from(META_DATA_FILE_NAME));
+
+        if let Err(e) = std::fs::create_dir_all(dest_path) {
+            dbg!(&e);
+            return Err(Error::BackupError);
+        }
+        for file_name in file_names.iter() {
+            if let Err(e) = std::fs::copy(self.dir.join(file_name), dest_path.join(file_name)) {
+                dbg!(file_name, &e);
+                return Err(Error::BackupError);
+            }
+        }
+        Ok(file_names.len())
+    }
+
+    /*
+     * Transactions are committed in two phases. A transaction is 
+     * begun, its page writes are logged with log_page_write, then it's
+     * prepared and at last committed. It can be rolled back any time 
+     * before commit.
+     * Every step writes a record to the write ahead log, and prepare 
+     * and commit sync the log and the page files, so a prepared 
+     * transaction survives a crash.
+     */
+    pub fn begin_transaction(&mut self) -> Transaction {
+        let txn = Transaction {
+            id: self.next_txn_id
+        };
+        self.next_txn_id += 1;
+        self.wal.write(&WalRecord::Begin { txn_id: txn.id }).expect("Write Ahead Log Write Error");
+        txn
+    }
+
+    //log a page write of a transaction, images are the page data after the page header.
+    pub fn log_page_write(&mut self, txn: &Transaction, page_num: PageNum, before: &[u8], after: &[u8]) -> Result<(), Error> {
+        let rec = WalRecord::PageWrite {
+            txn_id: txn.id,
+            page_num,
+            before: before.to_vec(),
+            after: after.to_vec()
+        };
+        self.write_wal(&rec)
+    }
+
+    /*
+     * Records are written to the file as they are logged, so the log
+     * is only synced, then the page files are forced to disk.
+     * The log goes first, so no page write reaches the disk before 
+     * its record.
+     */
+    pub fn prepare_transaction(&mut self, _txn: &Transaction) -> Result<(), Error> {
+        self.sync_wal()?;
+        self.force_sync()
+    }
+
+    //the commit record is synced along with the page files.
+    pub fn commit_transaction(&mut self, txn: Transaction) -> Result<(), Error> {
+        self.write_wal(&WalRecord::Commit { txn_id: txn.id })?;
+        self.sync_wal()?;
+        self.force_sync()
+    }
+
+    /*
+     * Undo all page writes of a transaction by writing their before 
+     * images back, the latest write is undone first.
+     * A page is found by the file number in its page number, so every 
+     * page file of the database is checked for the file number.
+     */
+    pub fn rollback_transaction(&mut self, txn: Transaction) -> Result<(), Error> {
+        let mut writes: Vec<(PageNum, Vec<u8>)> = Vec::new();
+        {
+            let mut reader = match WalReader::open(&self.get_path(WAL_FILE_NAME)) {
+                Err(e) => {
+                    dbg!(&e);
+                    return Err(Error::WalError);
+                },
+                Ok(v) => v
+            };
+            for res in reader.iter() {
+                match res {
+                    Err(e) => {
+                        dbg!(&e);
+                        return Err(Error::WalError);
+                    },
+                    Ok(WalRecord::PageWrite { txn_id, page_num, before, .. }) if txn_id == txn.id => {
+                        writes.push((page_num, before));
+                    },
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        if !writes.is_empty() {
+            for file_name in self.page_file_names() {
+                let mut pfh = self.pfm.open_file(&self.get_path(&file_name))?;
+                let file_num = pfh.get_file_num();
+                for (page_num, before) in writes.iter().rev().filter(|(page_num, _)| (page_num >> 16) as u16 == file_num) {
+                    let ph = pfh.get_page(*page_num)?;
+                    let len = before.len().min(PAGE_SIZE);
+                    unsafe {
+                        std::ptr::copy(before.as_ptr(), ph.get_data(), len);
+                    }
+                    pfh.unpin_dirty_page(*page_num)?;
+                }
+                pfh.close()?;
+            }
+            //restored pages must be on disk before the rollback is logged.
+            self.pfm.checkpoint()?;
+        }
+
+        self.write_wal(&WalRecord::Rollback { txn_id: txn.id })?;
+        self.sync_wal()
+    }
+
+    fn write_wal(&mut self, rec: &WalRecord) -> Result<(), Error> {
+        if let Err(e) = self.wal.write(rec) {
+            dbg!(&e);
+            return Err(Error::WalError);
+        }
+        Ok(())
+    }
+
+    fn sync_wal(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.wal.sync() {
+            dbg!(&e);
+            return Err(Error::WalError);
+        }
+        Ok(())
+    }
+
+    //write back the dirty pages of every page file and sync them to disk.
+    fn force_sync(&mut self) -> Result<(), Error> {
+        for file_name in self.page_file_names() {
+            let mut pfh = self.pfm.open_file(&self.get_path(&file_name))?;
+            pfh.force_sync()?;
+            pfh.close()?;
+        }
+        Ok(())
+    }
+
+    //path of a file of the database.
+    fn get_path(&self, file_name: &str) -> String {
+        self.dir.join(file_name).to_string_lossy().into_owned()
+    }
+
+    //record files and index files of all tables.
+    fn page_file_names(&self) -> Vec<String> {
+        let mut file_names = Vec::new();
+        for entry in self.catalog.iter().filter(|e| e.is_active) {
+            file_names.push(entry.get_name());
+            for index_entry in entry.indexes.iter().filter(|e| e.is_active) {
+                file_names.push(IndexFileManager::index_file_name(&entry.get_index_base_name(), index_entry.index_num));
+            }
+        }
+        file_names
+    }
+
+    //index of an active catalog entry with the name.
+    fn find_table(&self, name: &str) -> Option<usize> {
+        self.catalog.iter().position(|e| e.name_equals(name))
+    }
+
+    /*
+     * Get the schema of a table.
+     */
+    pub fn get_schema(&mut self, name: &str) -> Result<TableSchema, Error> {
+        let index = match self.find_table(name) {
+            None => {
+                dbg!(name);
+                return Err(Error::TableNotFound);
+            },
+            Some(v) => v
+        };
+        let mut page = vec![0u8; PAGE_SIZE];
+        if let Err(e) = self.fp.read_at(&mut page, SCHEMA_OFFSET + (index * PAGE_SIZE) as u64) {
+            dbg!(&e);
+            return Err(Error::InvalidSchema);
+        }
+        TableSchema::deserialize(&page)
+    }
+
+    //the schema page is padded with zeros.
+    fn write_schema(&mut self, index: usize, schema_bytes: &[u8]) -> Result<(), Error> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..schema_bytes.len()].copy_from_slice(schema_bytes);
+        match self.fp.write_at(&page, SCHEMA_OFFSET + (index * PAGE_SIZE) as u64) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::CatalogWriteError)
+            },
+            Ok(write_bytes) => {
+                if write_bytes < PAGE_SIZE {
+                    dbg!(write_bytes);
+                    return Err(Error::IncompleteWrite);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_catalog(&mut self) -> Result<(), Error> {
+        let sli: Vec<u8> = self.catalog.iter().flat_map(|e| e.serialize()).collect();
+        match self.fp.write_at(&sli, CATALOG_OFFSET) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::CatalogWriteError)
+            },
+            Ok(write_bytes) => {
+                if write_bytes < sli.len() {
+                    dbg!(write_bytes);
+                    return Err(Error::IncompleteWrite);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    //dirty pages in the buffer are written back before the metadata.
     pub fn close(&mut self, num_record_files: u16, num_indexing_files: u16) {
+        self.pfm.checkpoint().expect("Database Checkpoint Error");
         let meta_data = DBMetaData {
             num_record_files,
-            num_indexing_files
+            num_indexing_files,
+            num_files: self.pfm.get_num_files()
         };
         dbg!(&meta_data);
         let sli = unsafe {
@@ -80,8 +735,8 @@ impl DatabaseManager {
         }
     }
 
-    fn create(meta_data: &DBMetaData) -> File {
-        let mut fp = OpenOptions::new().read(true).write(true).create(true).open(&META_DATA_FILE_NAME).expect("Database Metadata File Creation Error");
+    fn create(path: &Path, meta_data: &DBMetaData) -> File {
+        let fp = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path).expect("Database Metadata File Creation Error");
         let sli = unsafe {
             std::slice::from_raw_parts(meta_data as *const _ as *const u8, size_of::<DBMetaData>())
         };
@@ -93,3 +748,115 @@ impl DatabaseManager {
         fp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DatabaseManager, IndexEntry, TableEntry, MAX_TABLE_NAME_LEN, TABLE_ENTRY_SIZE, META_DATA_FILE_NAME, WAL_FILE_NAME};
+    use crate::errors::Error;
+    use crate::indexing::AttrType;
+    use crate::types::{ColumnDef, ColumnType, TableSchema};
+
+    //catalog entries go through explicit bytes, malformed flags and tags are rejected.
+    #[test]
+    fn table_entry_bytes_test() {
+        let mut entry = TableEntry::empty();
+        entry.name[..5].copy_from_slice(b"users");
+        entry.file_num = 3;
+        entry.record_size = 200;
+        entry.is_active = true;
+        entry.indexes[1] = IndexEntry {
+            index_num: 7,
+            attr_type: AttrType::DOUBLE,
+            attr_length: 8,
+            is_active: true
+        };
+
+        let b = entry.serialize();
+        assert_eq!(b.len(), TABLE_ENTRY_SIZE);
+        let decoded = TableEntry::deserialize(&b).unwrap();
+        assert_eq!(decoded.get_name(), "users");
+        assert_eq!(decoded.file_num, 3);
+        assert_eq!(decoded.record_size, 200);
+        assert!(decoded.is_active);
+        assert_eq!(decoded.find_index(7), Some(1));
+        assert!(matches!(decoded.indexes[1].attr_type, AttrType::DOUBLE));
+        assert_eq!(decoded.indexes[1].attr_length, 8);
+        assert!(!decoded.indexes[0].is_active);
+
+        //is_active of the table.
+        let mut bad = b;
+        bad[MAX_TABLE_NAME_LEN + 10] = 2;
+        assert!(matches!(TableEntry::deserialize(&bad), Err(Error::CatalogCorrupted)));
+        //attr_type of the second index.
+        let mut bad = b;
+        bad[MAX_TABLE_NAME_LEN + 11 + 14 + 4] = 9;
+        assert!(matches!(TableEntry::deserialize(&bad), Err(Error::CatalogCorrupted)));
+    }
+
+    //a table and its index survive a reopen, and dropping it removes their files.
+    #[test]
+    fn table_round_trip_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = TableSchema::new(vec![
+            ColumnDef::new("id", ColumnType::Int, false),
+            ColumnDef::new("name", ColumnType::Char(16), true)
+        ]);
+        {
+            let mut db = DatabaseManager::open(dir.path());
+            db.create_table("users", &schema).unwrap();
+            assert!(matches!(db.create_table("users", &schema), Err(Error::TableExists)));
+            let mut ih = db.create_index("users", 0, AttrType::INT, 4).unwrap();
+            ih.close().unwrap();
+            drop(ih);
+            assert_eq!(db.list_tables().unwrap(), vec![String::from("users")]);
+            db.close(1, 1);
+        }
+        assert!(dir.path().join("users").exists());
+        assert!(dir.path().join("users.idx0").exists());
+
+        let mut db = DatabaseManager::open(dir.path());
+        assert_eq!(db.list_tables().unwrap(), vec![String::from("users")]);
+        assert_eq!(db.get_schema("users").unwrap().record_size(), schema.record_size());
+        //handles point into the buffer of db, so they must not outlive it.
+        let mut rfh = db.open_table("users").unwrap();
+        rfh.close().unwrap();
+        drop(rfh);
+        let mut ih = db.open_index("users", 0).unwrap();
+        ih.close().unwrap();
+        drop(ih);
+
+        db.drop_table("users").unwrap();
+        assert!(db.list_tables().unwrap().is_empty());
+        assert!(!dir.path().join("users").exists());
+        assert!(!dir.path().join("users.idx0").exists());
+        assert!(matches!(db.open_table("users"), Err(Error::TableNotFound)));
+        assert!(matches!(db.drop_table("users"), Err(Error::TableNotFound)));
+        db.close(0, 0);
+        drop(db);
+
+        let mut db = DatabaseManager::open(dir.path());
+        assert!(db.list_tables().unwrap().is_empty());
+    }
+
+    //transaction ids go on after the ones of the previous session.
+    #[test]
+    fn txn_id_reopen_test() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut db = DatabaseManager::open(dir.path());
+            let txn = db.begin_transaction();
+            assert_eq!(txn.id, 1);
+            db.commit_transaction(txn).unwrap();
+            let txn = db.begin_transaction();
+            assert_eq!(txn.id, 2);
+            db.rollback_transaction(txn).unwrap();
+        }
+        assert!(dir.path().join(META_DATA_FILE_NAME).exists());
+        assert!(dir.path().join(WAL_FILE_NAME).exists());
+
+        let mut db = DatabaseManager::open(dir.path());
+        let txn = db.begin_transaction();
+        assert_eq!(txn.id, 3);
+        db.commit_transaction(txn).unwrap();
+    }
+}
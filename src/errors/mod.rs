@@ -7,6 +7,9 @@
   > Copyright@ https://github.com/xiaoqixian
  **********************************************/
 
+use std::fmt;
+use log::error;
+
 /*
  * Define some erros enum for global usage.
  * Some errors are passed between modules, so they are normally returned by the 
@@ -22,19 +25,45 @@ pub enum Error {
     //public
     IncompleteWrite,
     FileOpenError,
+    FileNotFound,
+
 
+    //database_manager module
+    TableExists,
+    TableNotFound,
+    CatalogFull,//returns when all MAX_TABLES catalog entries are in use.
+    InvalidTableName,//returns when a table name is empty or longer than the catalog allows.
+    CatalogWriteError,
+    CatalogCorrupted,//returns when a catalog entry read from the metadata file is malformed.
+    IndexExists,
+    IndexNotFound,
+    TooManyIndexes,//returns when a table already has MAX_INDEXES indexes.
+    InvalidSchema,//returns when a table schema is empty, too large for a page or malformed.
+    BackupError,//returns when a database file fails to be copied to the backup directory.
+    WalError,//returns when the write ahead log fails to be written, synced or read.
 
     //page_file module
     UnpinPageError,
     MarkDirtyError,
     AllocatePageError,
     CreatePageFileError,
+    DestroyPageFileError,
+    FileInUse,//returns when a file to destroy still has pinned pages while a handle of it is alive.
     GetPageError,
     PageDisposed,
+    WriteHeaderError,
+    FileSyncError,//returns when a file fails to be synced to the disk.
+    ReadOnly,//returns when a read-only page file handle is asked to modify the file.
+    FreeListCycle,//returns when the list of disposed pages links back to a page already in it.
 
     //record_management module
     SetBitmapError,
     FindFreeSlotError,
+    InvalidRecordSize,//returns when the length of a record does not match the record size.
+    RecordSizeMismatch,//returns when the record size of an existing file differs from the requested one.
+    BufferTooSmall,//returns when a buffer provided by the caller is shorter than the record size.
+    BitmapInconsistency { page_num: u32, bitmap_count: usize, header_count: usize },//returns when the set bits of a page bitmap don't match num_records in the page header.
+    InvalidKeyRange,//returns when a key range goes beyond the record or doesn't match the attribute length of an index.
 
     //indexing module
     CreateNewNodeError,
@@ -42,11 +71,16 @@ pub enum Error {
     SplitNodeError,
     DeleteFromLeafError,
     DeleteFromNodeError,
+
+    //errors from inside a module.
+    PageFile(PageFileError),
+    Record(RecordError),
+    Indexing(IndexingError),
+    Lock(LockError),
 }
 
 #[derive(Debug)]
 pub enum PageFileError {
-    Okay,
     NoMemory,
     NoBuffer,//no buffer space
     NoPage, //no page in the buffer, returns when intended to free a used page.
@@ -103,6 +137,8 @@ pub enum IndexingError {
     FileExist,
     FileOpenError,
     FileCreationError,
+    FileNotFound,
+    DestroyFileError,
 
     //page_file part
     AllocatePageError,
@@ -127,4 +163,311 @@ pub enum IndexingError {
     EntryNotFoundInBucket,//returns when an entry is not found in a bucket
     ZeroKeyInBucket,//zero num_keys in a bucket, not supposed to happen, 
     NoneLastRid,
+    BufferTooSmall,//returns when a buffer provided by the caller is shorter than attr_length.
+    InvalidLeafChain,//returns when leaves are not correctly linked or keys in leaves are disordered.
+    NonEmptyIndex,//returns when bulk loading into an index that already has entries.
+    InsertEntryError,
+    DeleteEntryError,
+    KeyNotFound,//returns when no entry in the index has the key.
+    DuplicateRid,//returns when inserting an entry whose key and rid are already in the index.
+    BucketChainCycle,//returns when a bucket chain links back to a bucket already in it.
+    InvalidKeyParts,//returns when the values of a composite key don't match its parts.
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    Conflict,//returns when a latch is held in a mode that conflicts with the requested one.
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Error::IncompleteWrite => "incomplete write to file",
+            Error::FileOpenError => "failed to open file",
+            Error::FileNotFound => "file not found",
+            Error::TableExists => "table already exists",
+            Error::TableNotFound => "table not found",
+            Error::CatalogFull => "catalog is full",
+            Error::InvalidTableName => "invalid table name",
+            Error::CatalogWriteError => "failed to write catalog",
+            Error::CatalogCorrupted => "catalog is corrupted",
+            Error::IndexExists => "index already exists",
+            Error::IndexNotFound => "index not found",
+            Error::TooManyIndexes => "too many indexes on table",
+            Error::InvalidSchema => "invalid table schema",
+            Error::BackupError => "failed to copy database file for backup",
+            Error::WalError => "write ahead log error",
+            Error::UnpinPageError => "failed to unpin page",
+            Error::MarkDirtyError => "failed to mark page dirty",
+            Error::AllocatePageError => "failed to allocate page",
+            Error::CreatePageFileError => "failed to create page file",
+            Error::DestroyPageFileError => "failed to destroy page file",
+            Error::FileInUse => "file still has pinned pages",
+            Error::GetPageError => "failed to get page",
+            Error::PageDisposed => "page already disposed",
+            Error::WriteHeaderError => "failed to write file header",
+            Error::FileSyncError => "failed to sync file",
+            Error::ReadOnly => "page file opened read-only",
+            Error::FreeListCycle => "cycle in page free list",
+            Error::SetBitmapError => "failed to set record bitmap",
+            Error::FindFreeSlotError => "failed to find free slot",
+            Error::InvalidRecordSize => "record length does not match record size",
+            Error::RecordSizeMismatch => "record size differs from the existing file",
+            Error::BufferTooSmall => "buffer shorter than record size",
+            Error::InvalidKeyRange => "key range out of record or mismatching index attribute length",
+            Error::BitmapInconsistency { page_num, bitmap_count, header_count } => {
+                return write!(f, "page 0x{:08X} has {} bits set in bitmap but {} records in header", page_num, bitmap_count, header_count);
+            },
+            Error::CreateNewNodeError => "failed to create B+ tree node",
+            Error::InsertIntoNonFullNodeError => "failed to insert into B+ tree node",
+            Error::SplitNodeError => "failed to split B+ tree node",
+            Error::DeleteFromLeafError => "failed to delete from B+ tree leaf",
+            Error::DeleteFromNodeError => "failed to delete from B+ tree node",
+            Error::PageFile(e) => return write!(f, "page file error: {}", e),
+            Error::Record(e) => return write!(f, "record error: {}", e),
+            Error::Indexing(e) => return write!(f, "indexing error: {}", e),
+            Error::Lock(e) => return write!(f, "lock error: {}", e),
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl fmt::Display for PageFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            PageFileError::NoMemory => "out of memory",
+            PageFileError::NoBuffer => "no buffer space",
+            PageFileError::NoPage => "no free page in buffer",
+            PageFileError::AllPagesPinned => "all pages in buffer are pinned",
+            PageFileError::NoFilePointer => "no file pointer",
+            PageFileError::DestShort => "destination too short",
+            PageFileError::IncompleteRead => "incomplete page read from file",
+            PageFileError::IncompleteWrite => "incomplete page write to file",
+            PageFileError::ReadAtError => "failed to read page from file",
+            PageFileError::WriteAtError => "failed to write page to file",
+            PageFileError::LostFilePointer => "page lost its file pointer",
+            PageFileError::DataUnintialized => "page data uninitialized",
+            PageFileError::OutOfIndex => "buffer index out of range",
+            PageFileError::PageInBuf => "page already in buffer",
+            PageFileError::PageNotInBuf => "page not in buffer",
+            PageFileError::PageUnpinned => "page unpinned",
+            PageFileError::PagePinned => "page pinned",
+            PageFileError::PageFreed => "page already freed",
+            PageFileError::LocationError => "invalid page location",
+            PageFileError::HashNotFound => "page not found in page table",
+            PageFileError::HashPageExist => "page already in page table",
+            PageFileError::InvalidName => "invalid file name",
+            PageFileError::Unix => "unix system call error",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            RecordError::BitSet => "bitmap bit already set",
+            RecordError::BitUnset => "bitmap bit not set",
+            RecordError::NoFilePointer => "no file pointer",
+            RecordError::InvalidPageNumber => "invalid page number",
+            RecordError::InvalidSlotNumber => "invalid slot number",
+            RecordError::OffsetError => "invalid record offset",
+            RecordError::NullPointerError => "null pointer",
+            RecordError::MismatchRecordOffset => "record offset not aligned to page size",
+            RecordError::FullPage => "no free slot in page",
+            RecordError::RecordDeleted => "record already deleted",
+            RecordError::IncompleteWrite => "incomplete record write",
+            RecordError::FileExist => "record file already exists",
+            RecordError::FileOpenError => "failed to open record file",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl fmt::Display for IndexingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            IndexingError::CreateFileError => "failed to create index file",
+            IndexingError::InvalidAttr => "invalid attribute type or length",
+            IndexingError::IncompleteWrite => "incomplete index write",
+            IndexingError::IncompleteRead => "incomplete index read",
+            IndexingError::FileExist => "index file already exists",
+            IndexingError::FileOpenError => "failed to open index file",
+            IndexingError::FileCreationError => "failed to create index file",
+            IndexingError::FileNotFound => "index file not found",
+            IndexingError::DestroyFileError => "failed to destroy index file",
+            IndexingError::AllocatePageError => "failed to allocate page",
+            IndexingError::GetPageError => "failed to get page",
+            IndexingError::UnpinPageError => "failed to unpin page",
+            IndexingError::DisposePageError => "failed to dispose page",
+            IndexingError::GetFirstPageError => "failed to get first page",
+            IndexingError::FindInsertIndexError => "failed to find insert index",
+            IndexingError::AbnormalEntryType => "abnormal entry type",
+            IndexingError::SplitNodeError => "failed to split node",
+            IndexingError::CreateNewNodeError => "failed to create node",
+            IndexingError::DeleteFromLeafError => "failed to delete from leaf",
+            IndexingError::DeleteFromNodeError => "failed to delete from node",
+            IndexingError::InvalidEntry => "entry not found in index",
+            IndexingError::UnoccupiedEntry => "unoccupied entry",
+            IndexingError::DisorderError => "keys out of order",
+            IndexingError::EntriesBroken => "broken entry list",
+            IndexingError::InvalidBucket => "invalid bucket",
+            IndexingError::EntryNotFoundInBucket => "entry not found in bucket",
+            IndexingError::ZeroKeyInBucket => "bucket has no keys",
+            IndexingError::NoneLastRid => "no last rid in bucket",
+            IndexingError::BufferTooSmall => "buffer shorter than attribute length",
+            IndexingError::InvalidLeafChain => "invalid leaf chain",
+            IndexingError::NonEmptyIndex => "index is not empty",
+            IndexingError::InsertEntryError => "failed to insert entry",
+            IndexingError::DeleteEntryError => "failed to delete entry",
+            IndexingError::KeyNotFound => "key not found in index",
+            IndexingError::DuplicateRid => "rid already in index for the key",
+            IndexingError::BucketChainCycle => "cycle in bucket chain",
+            IndexingError::InvalidKeyParts => "values do not match composite key parts",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            LockError::Conflict => "conflicting latch held on page",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for Error {}
+impl std::error::Error for PageFileError {}
+impl std::error::Error for RecordError {}
+impl std::error::Error for IndexingError {}
+impl std::error::Error for LockError {}
+
+//I/O errors from reading or writing page files.
+impl From<std::io::Error> for PageFileError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => PageFileError::IncompleteRead,
+            std::io::ErrorKind::PermissionDenied => PageFileError::Unix,
+            _ => PageFileError::Unix
+        }
+    }
+}
+
+impl From<PageFileError> for Error {
+    fn from(e: PageFileError) -> Self {
+        Error::PageFile(e)
+    }
+}
+
+impl From<RecordError> for Error {
+    fn from(e: RecordError) -> Self {
+        Error::Record(e)
+    }
+}
+
+impl From<IndexingError> for Error {
+    fn from(e: IndexingError) -> Self {
+        Error::Indexing(e)
+    }
+}
+
+impl From<LockError> for Error {
+    fn from(e: LockError) -> Self {
+        Error::Lock(e)
+    }
+}
+
+/*
+ * An error with a context string describing what was being done when
+ * the error happened, like:
+ *   pfh.get_page(page_num).context("fetching root page")?
+ */
+#[derive(Debug)]
+pub struct ContextError<E> {
+    inner: E,
+    context: &'static str,
+}
+
+impl<E> ContextError<E> {
+    pub fn get_inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn get_context(&self) -> &'static str {
+        self.context
+    }
+
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ContextError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.inner)
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for ContextError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+pub trait ErrorContext: Sized {
+    fn context(self, msg: &'static str) -> ContextError<Self>;
+}
+
+impl ErrorContext for Error {
+    fn context(self, msg: &'static str) -> ContextError<Self> {
+        ContextError { inner: self, context: msg }
+    }
+}
+
+impl ErrorContext for PageFileError {
+    fn context(self, msg: &'static str) -> ContextError<Self> {
+        ContextError { inner: self, context: msg }
+    }
+}
+
+impl ErrorContext for RecordError {
+    fn context(self, msg: &'static str) -> ContextError<Self> {
+        ContextError { inner: self, context: msg }
+    }
+}
+
+impl ErrorContext for IndexingError {
+    fn context(self, msg: &'static str) -> ContextError<Self> {
+        ContextError { inner: self, context: msg }
+    }
+}
+
+impl ErrorContext for LockError {
+    fn context(self, msg: &'static str) -> ContextError<Self> {
+        ContextError { inner: self, context: msg }
+    }
+}
+
+//attach context to the error of a Result.
+pub trait ResultContext<T, E> {
+    fn context(self, msg: &'static str) -> Result<T, ContextError<E>>;
+}
+
+impl<T, E: ErrorContext> ResultContext<T, E> for Result<T, E> {
+    fn context(self, msg: &'static str) -> Result<T, ContextError<E>> {
+        self.map_err(|e| e.context(msg))
+    }
+}
+
+/*
+ * Callers returning Error get the inner error back, so they can still
+ * match on it. The context is logged.
+ */
+impl From<ContextError<Error>> for Error {
+    fn from(e: ContextError<Error>) -> Self {
+        error!("{}", e);
+        e.inner
+    }
 }
@@ -0,0 +1,69 @@
+/*
+ * Composite keys of indexes over multiple columns.
+ *
+ * A composite key is its parts laid out one after another, the
+ * offset of a part is where it starts in the key. Keys are compared
+ * part by part, so the first part is the most significant one.
+ *
+ * An index stores at most MAX_KEY_PARTS parts in its file header.
+ */
+
+use std::cmp::Ordering;
+use super::AttrType;
+use super::index_handle::IndexHandle;
+
+pub const MAX_KEY_PARTS: usize = 4;
+
+#[derive(Debug, Copy, Clone)]
+pub struct KeyPart {
+    pub attr_type: AttrType,
+    pub attr_length: usize,
+    pub offset: usize
+}
+
+#[derive(Debug, Clone)]
+pub struct CompositeKey {
+    pub parts: Vec<KeyPart>,
+    pub total_length: usize
+}
+
+impl CompositeKey {
+    //parts are laid out in the order given.
+    pub fn new(attrs: &[(AttrType, usize)]) -> Self {
+        let mut parts = Vec::with_capacity(attrs.len());
+        let mut offset = 0;
+        for &(attr_type, attr_length) in attrs.iter() {
+            parts.push(KeyPart {
+                attr_type,
+                attr_length,
+                offset
+            });
+            offset += attr_length;
+        }
+        Self {
+            parts,
+            total_length: offset
+        }
+    }
+}
+
+pub fn compare_composite(k1: *const u8, k2: *const u8, schema: &CompositeKey) -> Ordering {
+    compare_parts(k1, k2, &schema.parts)
+}
+
+//compare two keys by parts, stops at the first part that differs.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub fn compare_parts(k1: *const u8, k2: *const u8, parts: &[KeyPart]) -> Ordering {
+    for part in parts.iter() {
+        let (v1, v2) = unsafe {
+            (k1.add(part.offset) as *mut u8, k2.add(part.offset) as *mut u8)
+        };
+        match IndexHandle::compare(v1, v2, part.attr_type, part.attr_length) {
+            Ordering::Equal => {},
+            other => {
+                return other;
+            }
+        }
+    }
+    Ordering::Equal
+}
@@ -12,12 +12,12 @@
  * It uses page_management/page_file module as a client.
  */
 
-use crate::page_management::page_file::{PageFileManager, PageFileHeader};
+use crate::page_management::page_file::{PageFileManager, PAGE_TYPE_INDEX_HEADER};
 use std::mem::size_of;
 use super::AttrType;
+use super::composite_key::{CompositeKey, MAX_KEY_PARTS};
 use super::index_handle::{IndexHandle, IndexFileHeader};
 use crate::errors::IndexingError;
-use std::io::ErrorKind;
 use crate::utils;
 
 #[derive(Debug)]
@@ -25,20 +25,23 @@ pub struct IndexFileManager {
 }
 
 impl IndexFileManager {
+    pub fn file_exists(file_name: &str) -> bool {
+        PageFileManager::file_exists(file_name)
+    }
+
     /*
      * open a file, if the file not found, create one.
      *
      * index_num is for helping create the name of the index file, in 
      * case of duplicate names.
      */
-    pub fn open_file(file_name: &String, index_num: u32, pfm: &mut PageFileManager,  attr_type: AttrType, attr_length: usize) -> Result<IndexHandle, IndexingError> {
+    pub fn open_file(file_name: &str, index_num: u32, pfm: &mut PageFileManager,  attr_type: AttrType, attr_length: usize) -> Result<IndexHandle, IndexingError> {
         if !Self::check_attr_validity(attr_type, attr_length) {
             dbg!(&(attr_type, attr_length));
             return Err(IndexingError::InvalidAttr);
         }
 
-        let mut new_name = file_name.clone();
-        new_name.push_str(&index_num.to_string());
+        let new_name = Self::index_file_name(file_name, index_num);
         
         let mut pfh = match pfm.open_file(&new_name) {
             Err(e) => {
@@ -56,27 +59,52 @@ impl IndexFileManager {
             Ok(v) => v
         };
 
-        let header = utils::get_header::<IndexFileHeader>(header_ph.get_data());
+        debug_assert_eq!(header_ph.get_page_type(), PAGE_TYPE_INDEX_HEADER);
+        let header = *utils::get_header::<IndexFileHeader>(header_ph.get_data());
 
-        let mut root_ph = match pfh.allocate_page() {
-            Err(e) => {
-                dbg!(e);
-                return Err(IndexingError::AllocatePageError);
-            },
-            Ok(v) => v
-        };
+        if let Err(e) = pfh.unpin_page(header_ph.get_page_num()) {
+            dbg!(&e);
+            return Err(IndexingError::UnpinPageError);
+        }
 
-        Ok(IndexHandle::new(&mut pfh, header, root_ph))
+        Ok(IndexHandle::new(&mut pfh, &header, header_ph.get_page_num()))
     }
     
-    pub fn create_file(file_name: &String, index_num: u32, pfm: &mut PageFileManager,  attr_type: AttrType, attr_length: usize) -> Result<IndexHandle, IndexingError> {
+    pub fn create_file(file_name: &str, index_num: u32, pfm: &mut PageFileManager,  attr_type: AttrType, attr_length: usize) -> Result<IndexHandle, IndexingError> {
         if !Self::check_attr_validity(attr_type, attr_length) {
             dbg!(&(attr_type, attr_length));
             return Err(IndexingError::InvalidAttr);
         }
+        Self::create_with_header(file_name, index_num, pfm, |root_page| IndexFileHeader::new(attr_length, attr_type, root_page))
+    }
 
-        let mut new_name = file_name.clone();
-        new_name.push_str(&index_num.to_string());
+    /*
+     * Create an index over a composite key.
+     * Every part must be a valid attribute, and the whole key can't 
+     * be longer than the longest string attribute.
+     * The index is opened by open_file as a STRING index of 
+     * key.total_length.
+     */
+    pub fn create_file_composite(file_name: &str, index_num: u32, pfm: &mut PageFileManager, key: &CompositeKey) -> Result<IndexHandle, IndexingError> {
+        let mut offset = 0;
+        for part in key.parts.iter() {
+            if !Self::check_attr_validity(part.attr_type, part.attr_length) || part.offset != offset {
+                dbg!(part);
+                return Err(IndexingError::InvalidAttr);
+            }
+            offset += part.attr_length;
+        }
+        if key.parts.is_empty() || key.parts.len() > MAX_KEY_PARTS || offset != key.total_length || offset > super::MAX_STRING_LEN {
+            dbg!((key.parts.len(), key.total_length));
+            return Err(IndexingError::InvalidAttr);
+        }
+        Self::create_with_header(file_name, index_num, pfm, |root_page| IndexFileHeader::with_composite_key(key, root_page))
+    }
+
+    //make_header builds the file header from the page num of the root.
+    fn create_with_header<F>(file_name: &str, index_num: u32, pfm: &mut PageFileManager, make_header: F) -> Result<IndexHandle, IndexingError>
+        where F: FnOnce(u32) -> IndexFileHeader {
+        let new_name = Self::index_file_name(file_name, index_num);
         
         let mut pfh = match pfm.create_file(&new_name) {
             Err(e) => {
@@ -93,6 +121,7 @@ impl IndexFileManager {
             },
             Ok(v) => v
         };
+        header_ph.set_page_type(PAGE_TYPE_INDEX_HEADER);
 
         let root_ph = match pfh.allocate_page() {
             Err(e) => {
@@ -102,31 +131,111 @@ impl IndexFileManager {
             Ok(v) => v
         };
 
-        let header = IndexFileHeader::new(attr_length, attr_type, root_ph.get_page_num());
+        let header = make_header(root_ph.get_page_num());
 
         unsafe {
             std::ptr::copy(&header as *const _ as *const u8, header_ph.get_data(), size_of::<IndexFileHeader>());
         }
 
-        Ok(IndexHandle::new(&mut pfh, &header, root_ph))
+        if let Err(e) = pfh.unpin_dirty_page(header_ph.get_page_num()) {
+            dbg!(&e);
+            return Err(IndexingError::UnpinPageError);
+        }
+
+        if let Err(e) = pfh.unpin_dirty_page(root_ph.get_page_num()) {
+            dbg!(&e);
+            return Err(IndexingError::UnpinPageError);
+        }
+
+        let mut ih = IndexHandle::new(&mut pfh, &header, header_ph.get_page_num());
+        ih.init_root()?;
+        Ok(ih)
+    }
+
+    pub fn index_exists(file_name: &str, index_num: u32) -> bool {
+        Self::file_exists(&Self::index_file_name(file_name, index_num))
+    }
+
+    /*
+     * Remove an index file.
+     * Pages of the file in the buffer are evicted, so no handle of the 
+     * index should be used after it is dropped.
+     */
+    pub fn drop_index(file_name: &str, index_num: u32, pfm: &mut PageFileManager) -> Result<(), IndexingError> {
+        let new_name = Self::index_file_name(file_name, index_num);
+        if !Self::file_exists(&new_name) {
+            dbg!(&new_name);
+            return Err(IndexingError::FileNotFound);
+        }
+        if let Err(e) = pfm.destroy_file(&new_name) {
+            dbg!(&e);
+            return Err(IndexingError::DestroyFileError);
+        }
+        Ok(())
+    }
+
+    /*
+     * List index nums of a base name, as index files are named 
+     * as base_name followed by index_num starting from 0.
+     * Stops at the first index num without a file.
+     */
+    pub fn list_indexes(base_name: &str) -> Vec<u32> {
+        let mut indexes = Vec::new();
+        let mut index_num = 0;
+        while index_num < u32::MAX && Self::index_exists(base_name, index_num) {
+            indexes.push(index_num);
+            index_num += 1;
+        }
+        indexes
+    }
+
+    pub fn index_file_name(file_name: &str, index_num: u32) -> String {
+        let mut new_name = String::from(file_name);
+        new_name.push_str(&index_num.to_string());
+        new_name
     }
 
     fn check_attr_validity(attr_type: AttrType, attr_length: usize) -> bool {
         match attr_type {
             AttrType::INT | AttrType::FLOAT => {
-                if attr_length == 4 {
-                    true
-                } else {
-                    false
-                }
+                attr_length == 4
+            },
+            AttrType::BIGINT | AttrType::DOUBLE => {
+                attr_length == 8
             },
             AttrType::STRING => {
-                if attr_length <= super::MAX_STRING_LEN {
-                    true
-                } else {
-                    false
-                }
+                attr_length <= super::MAX_STRING_LEN
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::IndexFileManager;
+    use crate::errors::IndexingError;
+    use crate::indexing::AttrType;
+    use crate::test_utils::make_test_pfm;
+
+    //listing stops at the first missing index num, a dropped index no longer exists.
+    #[test]
+    fn list_indexes_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let base_name = dir.path().join("table.idx").to_str().unwrap().to_string();
+        assert!(IndexFileManager::list_indexes(&base_name).is_empty());
+
+        for index_num in [0, 1, 3].iter() {
+            let mut ih = IndexFileManager::create_file(&base_name, *index_num, &mut pfm, AttrType::INT, 4).expect("create index failed");
+            ih.close().expect("close index failed");
+        }
+        assert!(IndexFileManager::index_exists(&base_name, 3));
+        assert!(!IndexFileManager::index_exists(&base_name, 2));
+        assert_eq!(IndexFileManager::list_indexes(&base_name), vec![0, 1]);
+
+        IndexFileManager::drop_index(&base_name, 1, &mut pfm).expect("drop index failed");
+        assert!(!IndexFileManager::index_exists(&base_name, 1));
+        assert!(IndexFileManager::index_exists(&base_name, 0));
+        assert_eq!(IndexFileManager::list_indexes(&base_name), vec![0]);
+        assert!(matches!(IndexFileManager::drop_index(&base_name, 1, &mut pfm), Err(IndexingError::FileNotFound)));
+    }
+}
@@ -131,16 +131,20 @@
  */
 
 use super::AttrType;
-use crate::page_management::page_file::{PageHandle, PageFileHandle, PAGE_SIZE};
-use crate::errors::{IndexingError, Error};
+use super::composite_key::{self, CompositeKey, KeyPart, MAX_KEY_PARTS};
+use crate::page_management::page_file::{PageHandle, PageFileHandle, PAGE_SIZE, PAGE_TYPE_BTREE_NODE, PAGE_TYPE_BUCKET};
+use crate::errors::{IndexingError, Error, ResultContext};
 use crate::utils;
 use std::cmp::Ordering;
-use std::mem::{size_of};
-use crate::record_management::record_file_handle::{RID};
+use std::collections::HashSet;
+use std::mem::{size_of, align_of};
+use crate::types::RID;
 use crate::{ok_or_return, error_return};
+use log::error;
 
-const NO_MORE_SLOTS: usize = 1<<32;//as 0 is a valid slot num, so we use 1<<32 to represent a invalid slot_num.
-const BEGINNING_OF_SLOT: usize = 1<<32 + 1;
+const NO_MORE_SLOTS: usize = usize::MAX;//as 0 is a valid slot num, so we use usize::MAX to represent a invalid slot_num.
+const BEGINNING_OF_SLOT: usize = usize::MAX - 1;
+const _: () = assert!(BEGINNING_OF_SLOT != NO_MORE_SLOTS);
 const NO_MORE_PAGES: u32 = 0;
 
 #[derive(Debug, Copy, Clone)]
@@ -159,6 +163,10 @@ pub struct IndexFileHeader {
     root_page: u32,
 
     //comparator: fn(val1: &T, val2: &T) -> std::cmp::Ordering
+
+    //parts of a composite key, an index over a single attribute has no parts.
+    key_parts: [KeyPart; MAX_KEY_PARTS],
+    num_key_parts: usize,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -177,6 +185,7 @@ pub struct NodeHeader {
 #[derive(Debug, Copy, Clone)]
 pub struct LeafHeader {
     is_leaf: bool,
+    #[allow(dead_code)]
     is_empty: bool,
 
     num_keys: usize,
@@ -197,6 +206,7 @@ pub struct InternalHeader {
     first_slot: usize,//the pointer to the first node of the linked list.
 
     first_child: u32,//page num of the first child node.
+    #[allow(dead_code)]
     num2: u32
 }
 
@@ -238,13 +248,89 @@ struct BucketEntry {
 pub struct IndexHandle {
     header: IndexFileHeader,
     header_changed: bool,
+    header_page_num: u32,
     pfh: PageFileHandle,
-    root_ph: PageHandle //PageHandle associated with the root page.
+    /*
+     * page num of the root page.
+     * The root is only pinned during an operation, a PageHandle kept
+     * across operations would dangle once the root page is evicted.
+     */
+    root_page_num: u32,
+    closed: bool //the header is written back once when closed.
+}
+
+/*
+ * IndexRangeScan iterates over RIDs of entries whose keys are in a range,
+ * in the order of keys.
+ *
+ * current_leaf_num and current_slot point to the next entry to check, 
+ * current_slot is BEGINNING_OF_SLOT when the scan moves to a new leaf.
+ * RIDs of a duplicate entry are read from its buckets all at once and 
+ * kept in pending.
+ * An empty upper means the scan has no upper bound.
+ *
+ * No page is kept pinned between two calls of next.
+ */
+pub struct IndexRangeScan<'a> {
+    ih: &'a mut IndexHandle,
+    upper: Vec<u8>,
+    upper_inclusive: bool,
+    current_leaf_num: u32,
+    current_slot: usize,
+    pending: Vec<RID>
+}
+
+/*
+ * Statistics of an index, gathered by walking the whole tree.
+ * avg_entries_per_leaf counts distinct keys, RIDs of duplicate keys
+ * are in buckets and counted by num_bucket_pages.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexStats {
+    pub num_entries: usize,
+    pub num_leaf_pages: usize,
+    pub num_internal_pages: usize,
+    pub tree_height: u32,
+    pub num_bucket_pages: usize,
+    pub avg_entries_per_leaf: f64
+}
+
+/*
+ * A String with an indent level, every line written is prefixed
+ * with two spaces per level.
+ */
+struct IndentedWriter {
+    buf: String,
+    indent: usize
+}
+
+impl IndentedWriter {
+    fn new() -> Self {
+        Self {
+            buf: String::new(),
+            indent: 0
+        }
+    }
+
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.indent {
+            self.buf.push_str("  ");
+        }
+        self.buf.push_str(s);
+        self.buf.push('\n');
+    }
+
+    fn into_string(self) -> String {
+        self.buf
+    }
 }
 
 impl IndexFileHeader {
     pub fn new(attr_length: usize, attr_type: AttrType, root_page: u32) -> Self {
-        let node_keys_num = (PAGE_SIZE - size_of::<NodeHeader>())/(size_of::<NodeEntry>() + attr_length);
+        //node entries follow the keys, leave space to align them as keys may be of any length.
+        let entry_align = align_of::<NodeEntry>();
+        let node_keys_num = (PAGE_SIZE - size_of::<NodeHeader>() - (entry_align - 1))/(size_of::<NodeEntry>() + attr_length);
+        let node_entries_offset = (size_of::<NodeHeader>() + node_keys_num * attr_length).div_ceil(entry_align) * entry_align;
         let bucket_keys_num = (PAGE_SIZE - size_of::<BucketHeader>())/(size_of::<BucketEntry>());//buckets don't have keys.
 
         Self {
@@ -253,13 +339,58 @@ impl IndexFileHeader {
             attr_type,
             
             keys_offset: size_of::<NodeHeader>(),
-            node_entries_offset: size_of::<NodeHeader>() + node_keys_num * attr_length,
+            node_entries_offset,
             bucket_entries_offset: size_of::<BucketHeader>(),
 
             max_node_keys: node_keys_num,
             max_bucket_keys: bucket_keys_num,
 
             root_page,
+
+            key_parts: [KeyPart {
+                attr_type: AttrType::INT,
+                attr_length: 0,
+                offset: 0
+            }; MAX_KEY_PARTS],
+            num_key_parts: 0,
+        }
+    }
+
+    /*
+     * Header of an index over a composite key, the whole key is 
+     * taken as a string of total_length bytes, except that it's 
+     * compared by parts.
+     * The key must have 1 to MAX_KEY_PARTS parts.
+     */
+    pub fn with_composite_key(key: &CompositeKey, root_page: u32) -> Self {
+        debug_assert!(!key.parts.is_empty() && key.parts.len() <= MAX_KEY_PARTS);
+        let mut header = Self::new(key.total_length, AttrType::STRING, root_page);
+        header.key_parts[..key.parts.len()].copy_from_slice(&key.parts);
+        header.num_key_parts = key.parts.len();
+        header
+    }
+
+    pub fn get_root_page(&self) -> u32 {
+        self.root_page
+    }
+
+    //None for an index over a single attribute.
+    pub fn get_composite_key(&self) -> Option<CompositeKey> {
+        if self.num_key_parts == 0 {
+            return None;
+        }
+        Some(CompositeKey {
+            parts: self.key_parts[..self.num_key_parts].to_vec(),
+            total_length: self.attr_length
+        })
+    }
+
+    //compare two keys of the index.
+    fn compare_keys(&self, val1: *mut u8, val2: *mut u8) -> Ordering {
+        if self.num_key_parts == 0 {
+            IndexHandle::compare(val1, val2, self.attr_type, self.attr_length)
+        } else {
+            composite_key::compare_parts(val1, val2, &self.key_parts[..self.num_key_parts])
         }
     }
 }
@@ -267,65 +398,163 @@ impl IndexFileHeader {
 
 
 impl IndexHandle {
-    pub fn new(pfh: &mut PageFileHandle, header: &IndexFileHeader, root_ph: PageHandle) -> Self {
+    pub fn new(pfh: &mut PageFileHandle, header: &IndexFileHeader, header_page_num: u32) -> Self {
         Self {
             header: *header,
             header_changed: false,
+            header_page_num,
             pfh: pfh.clone(),
-            root_ph,
+            root_page_num: header.get_root_page(),
+            closed: false,
+        }
+    }
+
+    /*
+     * Write the index file header back to the header page if changed.
+     */
+    pub fn close(&mut self) -> Result<(), Error> {
+        if self.closed {
+            return Ok(());
+        }
+        if self.header_changed {
+            let ph = match self.pfh.get_page(self.header_page_num) {
+                Err(e) => {
+                    dbg!(&e);
+                    return Err(e);
+                },
+                Ok(v) => v
+            };
+            unsafe {
+                std::ptr::copy(&self.header as *const _ as *const u8, ph.get_data(), size_of::<IndexFileHeader>());
+            }
+            if let Err(e) = self.pfh.unpin_dirty_page(self.header_page_num) {
+                dbg!(&e);
+                return Err(e);
+            }
+            self.header_changed = false;
+        }
+        self.closed = true;
+        self.pfh.close()
+    }
+
+    /*
+     * Insert an entry into an index over a composite key, values are
+     * the parts of the key in order.
+     */
+    pub fn insert_entry_composite(&mut self, values: &[&[u8]], rid: &RID) -> Result<(), Error> {
+        let mut key = self.build_composite_key(values)?;
+        self.insert_entry(key.as_mut_ptr(), rid)
+    }
+
+    pub fn delete_entry_composite(&mut self, values: &[&[u8]], rid: &RID) -> Result<(), Error> {
+        let mut key = self.build_composite_key(values)?;
+        self.delete_entry(key.as_mut_ptr(), rid)
+    }
+
+    //lay out the values of the parts as a key.
+    fn build_composite_key(&self, values: &[&[u8]]) -> Result<Vec<u8>, IndexingError> {
+        let parts = &self.header.key_parts[..self.header.num_key_parts];
+        if parts.is_empty() || values.len() != parts.len() {
+            dbg!((parts.len(), values.len()));
+            return Err(IndexingError::InvalidKeyParts);
+        }
+        let mut key = utils::allocate_buffer_vec(self.header.attr_length);
+        for (part, value) in parts.iter().zip(values.iter()) {
+            if value.len() != part.attr_length {
+                dbg!((part.attr_length, value.len()));
+                return Err(IndexingError::InvalidKeyParts);
+            }
+            key[part.offset..part.offset+part.attr_length].copy_from_slice(value);
         }
+        Ok(key)
     }
 
     /*
      * insert an entry with key value = key_val, and associated RID = rid.
+     * The root is pinned for the insertion, if the root splits, the 
+     * new root is the one pinned at the end.
      */
     pub fn insert_entry(&mut self, key_val: *mut u8, rid: &RID) -> Result<(), Error> {
-        let root_header = utils::get_header_mut::<NodeHeader>(self.root_ph.get_data());
+        let root_ph = self.pfh.get_page(self.root_page_num)?;
+        let res = self.insert_into_root(root_ph, key_val, rid);
+        if let Err(e) = self.pfh.unpin_dirty_page(self.root_page_num) {
+            dbg!(&e);
+            return res.and(Err(e));
+        }
+        res
+    }
+
+    fn insert_into_root(&mut self, mut root_ph: PageHandle, key_val: *mut u8, rid: &RID) -> Result<(), Error> {
+        let root_header = unsafe {
+            utils::get_header_mut::<NodeHeader>(root_ph.get_data())
+        };
         
         //if the root page is full.
         if root_header.num_keys == self.header.max_node_keys {
-            let new_root_ph = match self.create_new_node(&false) {
+            let new_root_ph = match self.create_new_node(&false).context("creating new root node") {
                 Err(e) => {
                     dbg!(&e);
                     return Err(Error::CreateNewNodeError);
                 },
                 Ok(v) => v
             };
-            let new_root_header = utils::get_header_mut::<InternalHeader>(new_root_ph.get_data());
+            let new_root_header = unsafe {
+                utils::get_header_mut::<InternalHeader>(new_root_ph.get_data())
+            };
             new_root_header.is_empty = false;
-            new_root_header.first_child = self.root_ph.get_page_num();
+            new_root_header.first_child = root_ph.get_page_num();
 
             //split the original root node.
-            if let Err(e) = self.split_node(new_root_ph, self.root_ph, root_header.is_leaf, BEGINNING_OF_SLOT) {
-                dbg!(&e);
-                return Err(Error::SplitNodeError);
-            }
+            let new_node_ph = match self.split_node(new_root_ph, root_ph, root_header.is_leaf, BEGINNING_OF_SLOT).context("splitting root node") {
+                Err(e) => {
+                    dbg!(&e);
+                    return Err(Error::SplitNodeError);
+                },
+                Ok((_, v)) => v
+            };
 
-            if let Err(e) = self.pfh.unpin_dirty_page(self.root_ph.get_page_num()) {
-                return Err(e);
-            }
+            self.pfh.unpin_dirty_page(new_node_ph.get_page_num())?;
 
-            self.root_ph = new_root_ph;
+            self.pfh.unpin_dirty_page(root_ph.get_page_num())?;
+
+            root_ph = new_root_ph;
+            self.root_page_num = new_root_ph.get_page_num();
             self.header.root_page = new_root_ph.get_page_num();
             self.header_changed = true;
 
         }
 
-        match self.insert_into_nonfull_node(self.root_ph, key_val, rid) {
+        match self.insert_into_nonfull_node(root_ph, key_val, rid).context("inserting into root node") {
             Err(e) => {
                 dbg!(&e);
-                Err(Error::InsertIntoNonFullNodeError)
+                match e.get_inner() {
+                    IndexingError::DuplicateRid => Err(Error::Indexing(IndexingError::DuplicateRid)),
+                    _ => Err(Error::InsertIntoNonFullNodeError)
+                }
             },
-            Ok(_) => Ok(())
+            Ok(_) => {
+                self.header.num_entries += 1;
+                self.header_changed = true;
+                Ok(())
+            }
         }
     }
 
+    //number of entries in the index.
+    pub fn entry_count(&self) -> usize {
+        self.header.num_entries
+    }
+
 
     fn insert_into_nonfull_node(&mut self, node_ph: PageHandle, key_val: *mut u8, rid: &RID) -> Result<(), IndexingError> {
-        let node_header = utils::get_header_mut::<NodeHeader>(node_ph.get_data());
+        debug_assert_eq!(node_ph.get_page_type(), PAGE_TYPE_BTREE_NODE);
+        debug_assert!(matches!(self.pfh.page_is_disposed(node_ph.get_page_num()), Ok(false)));
+        let node_header = unsafe {
+            utils::get_header_mut::<NodeHeader>(node_ph.get_data())
+        };
         let entries = self.get_node_entries(node_ph.get_data());
         let keys = unsafe {
-            node_ph.get_data().offset(self.header.keys_offset as isize)
+            node_ph.get_data().add(self.header.keys_offset)
         };
 
         if node_header.is_leaf {
@@ -342,7 +571,7 @@ impl IndexHandle {
                 //copy key_val to keys
                 let index = node_header.free_slot;
                 unsafe {
-                    std::ptr::copy(key_val, keys.offset((index * self.header.attr_length) as isize), self.header.attr_length);
+                    std::ptr::copy(key_val, keys.add(index * self.header.attr_length), self.header.attr_length);
                 }
                 node_header.is_empty = false;
                 node_header.num_keys += 1;
@@ -353,7 +582,7 @@ impl IndexHandle {
                 entries[index].slot_num = rid.get_slot_num();
 
                 if prev_index == BEGINNING_OF_SLOT {
-                    entries[index].next_slot = NO_MORE_SLOTS;
+                    entries[index].next_slot = node_header.first_slot;
                     node_header.first_slot = index;
                 } else {
                     entries[index].next_slot = entries[prev_index].next_slot;
@@ -368,14 +597,20 @@ impl IndexHandle {
                         return Err(IndexingError::AbnormalEntryType);
                     },
                     EntryType::New => {
+                        if prev_entry.page_num == rid.get_page_num() && prev_entry.slot_num == rid.get_slot_num() {
+                            return Err(IndexingError::DuplicateRid);
+                        }
                         //let bucket_ph = ok_or_return!(self, create_new_bucket());
                         let bucket_ph = self.create_new_bucket()?;
+                        let bucket_num = bucket_ph.get_page_num();
                         //insert_into_bucket is in charge of unpinning the page
-                        //no matter if it's dirty or not.
+                        //no matter if it's dirty or not, so the bucket is pinned
+                        //again for the second rid.
                         self.insert_into_bucket(bucket_ph, rid)?;
+                        let bucket_ph = ok_or_return!(self.pfh.get_page(bucket_num), IndexingError::GetPageError);
                         self.insert_into_bucket(bucket_ph, &RID::new(prev_entry.page_num, prev_entry.slot_num))?;
                         prev_entry.et_type = EntryType::Duplicate;
-                        prev_entry.page_num = bucket_ph.get_page_num();
+                        prev_entry.page_num = bucket_num;
                     },
                     EntryType::Duplicate => {
                         let bucket_ph = ok_or_return!(self.pfh.get_page(prev_entry.page_num), IndexingError::GetPageError);
@@ -384,16 +619,18 @@ impl IndexHandle {
                 }
             }
         } else {//if it's an internal node.\
-            let mut next_node: u32;//next level node to call this method.
             //let (prev_index, is_dup) = ok_or_return!(self, find_node_insert_index(key_val, node_ph.get_data()));
-            let (prev_index, is_dup) = self.find_node_insert_index(key_val, node_ph.get_data())?;
-            let node_header = utils::get_header_mut::<InternalHeader>(node_ph.get_data());
-            if prev_index == BEGINNING_OF_SLOT {
+            let (prev_index, _) = self.find_node_insert_index(key_val, node_ph.get_data())?;
+            let node_header = unsafe {
+                utils::get_header_mut::<InternalHeader>(node_ph.get_data())
+            };
+            //next level node to call this method.
+            let next_node = if prev_index == BEGINNING_OF_SLOT {
                 //connect to the first child node.
-                next_node = node_header.first_child;
+                node_header.first_child
             } else {
-                next_node = entries[prev_index].page_num;//page number of internal node entry stores the page number of the node it points to.
-            }
+                entries[prev_index].page_num//page number of internal node entry stores the page number of the node it points to.
+            };
 
             let mut next_node_ph = ok_or_return!(self.pfh.get_page(next_node), IndexingError::GetPageError);
             let next_node_header = utils::get_header::<NodeHeader>(next_node_ph.get_data());
@@ -402,13 +639,13 @@ impl IndexHandle {
                 //if the next node is full, we need to split the next node.
                 let (insert_index, new_node_ph) = self.split_node(node_ph, next_node_ph, next_node_header.is_leaf, prev_index)?;
                 let edge_val = unsafe {
-                    keys.offset((insert_index * self.header.attr_length) as isize)
+                    keys.add(insert_index * self.header.attr_length)
                 };
                 /*
                  * Compare the key_val with the edge_val.
                  * If less, goes to the next_node, else goes to the new_node.
                  */
-                match Self::compare(key_val, edge_val, self.header.attr_type, self.header.attr_length) {
+                match self.header.compare_keys(key_val, edge_val) {
                     Ordering::Greater | Ordering::Equal => {
                         error_return!(self.pfh.unpin_dirty_page(next_node_ph.get_page_num()), IndexingError::UnpinPageError);
                         next_node_ph = new_node_ph;
@@ -435,40 +672,55 @@ impl IndexHandle {
      * no relations.
      */
     fn insert_into_bucket(&mut self, mut ph: PageHandle, rid: &RID) -> Result<(), IndexingError> {
-        let mut flag = true;
-        while flag {
-            /*
-             * TODO
-             * In original code, here's the part that traverses all buckets just to 
-             * make sure no entry with a same rid is already inserted.
-             * I think it's a little unnessary, so I just leave it aside for now.
-             */
+        //a rid inserted twice would be returned twice for the key.
+        match self.contains_duplicate_rid(ph.get_page_num(), rid) {
+            Ok(false) => {},
+            res => {
+                error_return!(self.pfh.unpin_page(ph.get_page_num()), IndexingError::UnpinPageError);
+                return match res {
+                    Ok(_) => Err(IndexingError::DuplicateRid),
+                    Err(e) => Err(e)
+                };
+            }
+        }
+
+        loop {
+            debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_BUCKET);
             let mut bucket_entries = self.get_bucket_entries(ph.get_data());
-            let mut bucket_header = utils::get_header_mut::<BucketHeader>(ph.get_data());
-            if bucket_header.next_bucket == NO_MORE_PAGES && bucket_header.num_keys == self.header.max_bucket_keys {
-                flag = false;
+            let mut bucket_header = unsafe {
+                utils::get_header_mut::<BucketHeader>(ph.get_data())
+            };
+
+            //only the last bucket of the chain takes new rids.
+            if bucket_header.next_bucket != NO_MORE_PAGES {
+                let next_bucket = bucket_header.next_bucket;
+                error_return!(self.pfh.unpin_page(ph.get_page_num()), IndexingError::UnpinPageError);
+                ph = ok_or_return!(self.pfh.get_page(next_bucket), IndexingError::GetPageError);
+                continue;
+            }
+
+            if bucket_header.num_keys == self.header.max_bucket_keys {
                 let new_ph = self.create_new_bucket()?;
                 bucket_header.next_bucket = new_ph.get_page_num();
                 error_return!(self.pfh.unpin_dirty_page(ph.get_page_num()), IndexingError::UnpinPageError);
 
                 bucket_entries = self.get_bucket_entries(new_ph.get_data());
-                bucket_header = utils::get_header_mut::<BucketHeader>(new_ph.get_data());
+                bucket_header = unsafe {
+                    utils::get_header_mut::<BucketHeader>(new_ph.get_data())
+                };
                 ph = new_ph;
             }
 
-            if bucket_header.next_bucket == NO_MORE_PAGES {
-                let loc = bucket_header.free_slot;
-                bucket_entries[loc].page_num = rid.get_page_num();
-                bucket_entries[loc].slot_num = rid.get_slot_num();
-                bucket_header.free_slot = bucket_entries[loc].next_slot;
-                bucket_entries[loc].next_slot = bucket_header.first_slot;
-                bucket_header.first_slot = loc;
-                bucket_header.num_keys += 1;
+            let loc = bucket_header.free_slot;
+            bucket_entries[loc].page_num = rid.get_page_num();
+            bucket_entries[loc].slot_num = rid.get_slot_num();
+            bucket_header.free_slot = bucket_entries[loc].next_slot;
+            bucket_entries[loc].next_slot = bucket_header.first_slot;
+            bucket_header.first_slot = loc;
+            bucket_header.num_keys += 1;
 
-                error_return!(self.pfh.unpin_dirty_page(ph.get_page_num()), IndexingError::UnpinPageError);
-            }
-
-            ph = ok_or_return!(self.pfh.get_page(bucket_header.next_bucket), IndexingError::GetPageError);
+            error_return!(self.pfh.unpin_dirty_page(ph.get_page_num()), IndexingError::UnpinPageError);
+            break;
         }
         Ok(())
     }
@@ -485,24 +737,32 @@ impl IndexHandle {
      *   2. new node PageHandle.
      */
     fn split_node(&mut self, parent_ph: PageHandle, full_ph: PageHandle, is_leaf: bool, parent_prev_index: usize) -> Result<(usize, PageHandle), IndexingError> {
-        let parent_header = utils::get_header_mut::<InternalHeader>(parent_ph.get_data());
+        let parent_header = unsafe {
+            utils::get_header_mut::<InternalHeader>(parent_ph.get_data())
+        };
         let parent_entries = self.get_node_entries(parent_ph.get_data());
         
         let new_ph = self.create_new_node(&is_leaf)?;
 
-        let full_header = utils::get_header_mut::<NodeHeader>(full_ph.get_data());
-        let new_header = utils::get_header_mut::<NodeHeader>(new_ph.get_data());
+        let full_header = unsafe {
+
+            utils::get_header_mut::<NodeHeader>(full_ph.get_data())
+
+        };
+        let new_header = unsafe {
+            utils::get_header_mut::<NodeHeader>(new_ph.get_data())
+        };
         
         let new_entries = self.get_node_entries(new_ph.get_data());
         let full_entries = self.get_node_entries(full_ph.get_data());
         let new_keys = unsafe {
-            new_ph.get_data().offset(self.header.keys_offset as isize)
+            new_ph.get_data().add(self.header.keys_offset)
         };
         let full_keys = unsafe {
-            full_ph.get_data().offset(self.header.keys_offset as isize)
+            full_ph.get_data().add(self.header.keys_offset)
         };
         let parent_keys = unsafe {
-            parent_ph.get_data().offset(self.header.keys_offset as isize)
+            parent_ph.get_data().add(self.header.keys_offset)
         };
 
         /*
@@ -510,7 +770,7 @@ impl IndexHandle {
          */
         let mut prev_index: usize = BEGINNING_OF_SLOT;
         let mut curr_index: usize = full_header.first_slot;
-        for i in 0..(self.header.max_node_keys/2) {
+        for _ in 0..(self.header.max_node_keys/2) {
             prev_index = curr_index;
             curr_index = full_entries[curr_index].next_slot;
         }
@@ -518,7 +778,7 @@ impl IndexHandle {
 
         //find the key to insert into the parent node.
         let parent_key = unsafe {
-            full_keys.offset((curr_index * self.header.attr_length) as isize)
+            full_keys.add(curr_index * self.header.attr_length)
         };
 
         /*
@@ -529,7 +789,9 @@ impl IndexHandle {
          * Above actions is only taken when it's an internal node.
          */
         if !is_leaf {
-            let new_header = utils::get_header_mut::<InternalHeader>(new_ph.get_data());
+            let new_header = unsafe {
+                utils::get_header_mut::<InternalHeader>(new_ph.get_data())
+            };
             new_header.first_child = full_entries[curr_index].page_num;
             new_header.is_empty = false;
             //unlink curr_index from the old node
@@ -545,23 +807,24 @@ impl IndexHandle {
         let mut prev_index2 = BEGINNING_OF_SLOT;
         let mut curr_index2 = new_header.free_slot;
         while curr_index != NO_MORE_SLOTS {
+            //the free slot link is overwritten by the copy, so keep it first.
+            let next_free = new_entries[curr_index2].next_slot;
             new_entries[curr_index2] = full_entries[curr_index];//NodeEntry implemented Copy trait.
             unsafe {
-                std::ptr::copy(full_keys.offset((curr_index * self.header.attr_length) as isize), new_keys.offset((curr_index2 * self.header.attr_length) as isize), self.header.attr_length);
+                std::ptr::copy(full_keys.add(curr_index * self.header.attr_length), new_keys.add(curr_index2 * self.header.attr_length), self.header.attr_length);
             }
 
+            new_header.free_slot = next_free;
             if prev_index2 == BEGINNING_OF_SLOT {//as for the first slot.
-                new_header.free_slot = new_entries[curr_index2].next_slot;
                 new_entries[curr_index2].next_slot = new_header.first_slot;
                 new_header.first_slot = curr_index2;
             } else {
-                new_header.free_slot = new_entries[curr_index2].next_slot;
                 new_entries[curr_index2].next_slot = new_entries[prev_index2].next_slot;
                 new_entries[prev_index2].next_slot = curr_index2;
             }
 
             prev_index2 = curr_index2;
-            curr_index2 = new_entries[curr_index2].next_slot;
+            curr_index2 = new_header.free_slot;
 
             prev_index = curr_index;
             curr_index = full_entries[curr_index].next_slot;
@@ -576,7 +839,7 @@ impl IndexHandle {
         let loc = parent_header.free_slot;
         let slot = parent_header.free_slot;
         unsafe {
-            std::ptr::copy(parent_key, parent_keys.offset((loc * self.header.attr_length) as isize), self.header.attr_length);
+            std::ptr::copy(parent_key, parent_keys.add(loc * self.header.attr_length), self.header.attr_length);
         }
         if parent_prev_index == BEGINNING_OF_SLOT {
             parent_header.free_slot = parent_entries[loc].next_slot;
@@ -587,6 +850,9 @@ impl IndexHandle {
             parent_entries[loc].next_slot = parent_entries[parent_prev_index].next_slot;
             parent_entries[parent_prev_index].next_slot = slot;
         }
+        //the new key points to the new node.
+        parent_entries[loc].et_type = EntryType::New;
+        parent_entries[loc].page_num = new_ph.get_page_num();
         parent_header.num_keys += 1;
 
         /*
@@ -594,8 +860,12 @@ impl IndexHandle {
          * a leaf node.
          */
         if is_leaf {
-            let new_header = utils::get_header_mut::<LeafHeader>(new_ph.get_data());
-            let full_header = utils::get_header_mut::<LeafHeader>(full_ph.get_data());
+            let new_header = unsafe {
+                utils::get_header_mut::<LeafHeader>(new_ph.get_data())
+            };
+            let full_header = unsafe {
+                utils::get_header_mut::<LeafHeader>(full_ph.get_data())
+            };
             let next_page = full_header.next_page;
             
             new_header.prev_page = full_ph.get_page_num();
@@ -603,7 +873,9 @@ impl IndexHandle {
             full_header.next_page = new_ph.get_page_num();
             if next_page != NO_MORE_PAGES {
                 let full_next_ph = ok_or_return!(self.pfh.get_page(next_page), IndexingError::GetPageError);
-                let full_next_header = utils::get_header_mut::<LeafHeader>(full_next_ph.get_data());
+                let full_next_header = unsafe {
+                    utils::get_header_mut::<LeafHeader>(full_next_ph.get_data())
+                };
                 full_next_header.prev_page = new_ph.get_page_num();
 
                 error_return!(self.pfh.unpin_dirty_page(next_page), IndexingError::UnpinPageError);
@@ -614,164 +886,1321 @@ impl IndexHandle {
     }
     
     /*
-     * Delete an entry from a B+ tree is no doubt the most difficult operation to 
-     * implement.
-     * Thus, we decide not to merge nodes when the number of keys in a node is less
-     * than self.header.max_node_keys/2.
-     * Only when the page is empty, the corresponding BufferPage is disposed.
+     * Build the B+ tree bottom-up from entries sorted by key.
+     * Only allowed when the index is empty.
      *
-     * TODO: merge nodes.
+     * Leaves are filled from left to right and linked as they go, then 
+     * internal nodes are built level by level from the first keys of 
+     * nodes in the level below, until one node is left as the root.
+     * Entries of a level are spread evenly among its nodes, so nodes
+     * are as full as possible while none is less than half full.
+     *
+     * As buckets are not built here, entries with a key equal to the 
+     * previous one are inserted by insert_entry after the tree is built.
      */
-    pub fn delete_entry(&mut self, key_val: *mut u8, rid: &RID) -> Result<(), Error> {
-        let root_header = utils::get_header_mut::<NodeHeader>(self.root_ph.get_data());
-        
-        if root_header.is_leaf {
-            match self.delete_from_leaf(key_val, rid, self.root_ph) {
-                Err(e) => {
-                    dbg!(e);
-                    return Err(Error::DeleteFromLeafError);
+    pub fn bulk_load(&mut self, sorted_entries: &[(*const u8, RID)]) -> Result<(), IndexingError> {
+        let file_header = self.header;
+        debug_assert!(sorted_entries.windows(2).all(|w| {
+            file_header.compare_keys(w[0].0 as *mut u8, w[1].0 as *mut u8) != Ordering::Greater
+        }));
+
+        let root_ph = ok_or_return!(self.pfh.get_page(self.root_page_num), IndexingError::GetPageError);
+        let root_header = *utils::get_header::<NodeHeader>(root_ph.get_data());
+        if !root_header.is_leaf || root_header.num_keys != 0 {
+            dbg!(root_header.num_keys);
+            error_return!(self.pfh.unpin_page(self.root_page_num), IndexingError::UnpinPageError);
+            return Err(IndexingError::NonEmptyIndex);
+        }
+
+        let mut unique: Vec<&(*const u8, RID)> = Vec::with_capacity(sorted_entries.len());
+        let mut duplicates: Vec<&(*const u8, RID)> = Vec::new();
+        for entry in sorted_entries.iter() {
+            match unique.last() {
+                Some(last) if file_header.compare_keys(last.0 as *mut u8, entry.0 as *mut u8) == Ordering::Equal => {
+                    duplicates.push(entry);
                 },
-                Ok(_) => {}
+                _ => unique.push(entry)
+            }
+        }
+
+        //all entries fit in the root leaf.
+        if unique.len() <= self.header.max_node_keys {
+            let data = root_ph.get_data();
+            let mut prev_index = BEGINNING_OF_SLOT;
+            for (key, rid) in unique.iter() {
+                prev_index = self.link_node_entry(data, prev_index, Self::leaf_entry(rid), *key as *mut u8);
             }
+            error_return!(self.pfh.unpin_dirty_page(self.root_page_num), IndexingError::UnpinPageError);
         } else {
-            match self.delete_from_node(key_val, rid, self.root_ph) {
-                Err(e) => {
-                    dbg!(e);
-                    return Err(Error::DeleteFromNodeError);
-                },
-                Ok(v) => {
-                    let (to_delete, next_key) = v;
-                    if to_delete {
-                        //if the root node is empty, set it as a leaf node
-                        root_header.is_leaf = true;
+            //the empty root is replaced by the top node built below.
+            error_return!(self.pfh.unpin_page(self.root_page_num), IndexingError::UnpinPageError);
+            //page num and first key of each node in the current level.
+            let mut level: Vec<(u32, *mut u8)> = Vec::new();
+            let mut prev_ph: Option<PageHandle> = None;
+            let mut start = 0;
+            for size in Self::node_sizes(unique.len(), self.header.max_node_keys) {
+                let leaf_ph = ok_or_return!(self.pfh.allocate_page(), IndexingError::AllocatePageError);
+                self.init_node(leaf_ph, true);
+
+                let mut prev_index = BEGINNING_OF_SLOT;
+                for (key, rid) in unique[start..start+size].iter() {
+                    prev_index = self.link_node_entry(leaf_ph.get_data(), prev_index, Self::leaf_entry(rid), *key as *mut u8);
+                }
+
+                //link with the previous leaf.
+                if let Some(prev) = prev_ph {
+                    unsafe { utils::get_header_mut::<LeafHeader>(prev.get_data()) }.next_page = leaf_ph.get_page_num();
+                    unsafe { utils::get_header_mut::<LeafHeader>(leaf_ph.get_data()) }.prev_page = prev.get_page_num();
+                    error_return!(self.pfh.unpin_dirty_page(prev.get_page_num()), IndexingError::UnpinPageError);
+                }
+
+                level.push((leaf_ph.get_page_num(), unique[start].0 as *mut u8));
+                prev_ph = Some(leaf_ph);
+                start += size;
+            }
+            if let Some(prev) = prev_ph {
+                error_return!(self.pfh.unpin_dirty_page(prev.get_page_num()), IndexingError::UnpinPageError);
+            }
+
+            //an internal node has one more child than its keys.
+            while level.len() > 1 {
+                let mut upper_level = Vec::new();
+                let mut start = 0;
+                for size in Self::node_sizes(level.len(), self.header.max_node_keys + 1) {
+                    let node_ph = ok_or_return!(self.pfh.allocate_page(), IndexingError::AllocatePageError);
+                    self.init_node(node_ph, false);
+
+                    let node_header = unsafe {
+
+                        utils::get_header_mut::<InternalHeader>(node_ph.get_data())
+
+                    };
+                    node_header.first_child = level[start].0;
+                    node_header.is_empty = false;
+
+                    let mut prev_index = BEGINNING_OF_SLOT;
+                    for &(page_num, key) in level[start+1..start+size].iter() {
+                        let entry = NodeEntry {
+                            et_type: EntryType::New,
+                            next_slot: NO_MORE_SLOTS,
+                            page_num,
+                            slot_num: 0
+                        };
+                        prev_index = self.link_node_entry(node_ph.get_data(), prev_index, entry, key);
                     }
+                    error_return!(self.pfh.unpin_dirty_page(node_ph.get_page_num()), IndexingError::UnpinPageError);
+
+                    upper_level.push((node_ph.get_page_num(), level[start].1));
+                    start += size;
                 }
+                level = upper_level;
             }
+
+            //replace the empty root with the top node.
+            error_return!(self.pfh.dispose_page(self.root_page_num), IndexingError::DisposePageError);
+            self.root_page_num = level[0].0;
+            self.header.root_page = level[0].0;
         }
+        self.header.num_entries += unique.len();
+        self.header_changed = true;
 
+        for (key, rid) in duplicates.into_iter() {
+            if let Err(e) = self.insert_entry(*key as *mut u8, rid) {
+                dbg!(&e);
+                return Err(IndexingError::InsertEntryError);
+            }
+        }
         Ok(())
     }
 
-    fn delete_from_node(&mut self, key_val: *mut u8, rid: &RID, node: PageHandle) -> Result<(bool, *mut u8), IndexingError> {
-        let node_header = utils::get_header_mut::<InternalHeader>(node.get_data());
+    fn leaf_entry(rid: &RID) -> NodeEntry {
+        NodeEntry {
+            et_type: EntryType::New,
+            next_slot: NO_MORE_SLOTS,
+            page_num: rid.get_page_num(),
+            slot_num: rid.get_slot_num()
+        }
+    }
 
-        let (mut curr_index, is_dup) = self.find_node_insert_index(key_val, node.get_data())?;
+    /*
+     * Sizes of nodes when spreading total entries evenly among the 
+     * fewest nodes with at most max entries each.
+     */
+    fn node_sizes(total: usize, max: usize) -> Vec<usize> {
+        let num_nodes = total.div_ceil(max);
+        (0..num_nodes).map(|i| total / num_nodes + if i < total % num_nodes { 1 } else { 0 }).collect()
+    }
 
-        let node_entries = self.get_node_entries(node.get_data());
-        let next_page_num = {
-            if curr_index == BEGINNING_OF_SLOT {
-                node_entries[curr_index].page_num
+    /*
+     * Scan RIDs of entries with keys between lower and upper.
+     * Both bounds are expected to be at least attr_length long.
+     */
+    pub fn scan_range(&mut self, lower: &[u8], lower_inclusive: bool, upper: &[u8], upper_inclusive: bool) -> Result<IndexRangeScan<'_>, IndexingError> {
+        if lower.len() < self.header.attr_length || upper.len() < self.header.attr_length {
+            dbg!((lower.len(), upper.len()));
+            return Err(IndexingError::InvalidAttr);
+        }
+        let lower_val = lower.as_ptr() as *mut u8;
+        let leaf_num = self.find_leaf(lower_val)?;
+
+        //find the first entry no less than lower, or greater than lower if 
+        //lower is not inclusive.
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let leaf_header = utils::get_header::<LeafHeader>(leaf_ph.get_data());
+        let leaf_entries = self.get_node_entries(leaf_ph.get_data());
+        let (prev_index, is_dup) = self.find_node_insert_index(lower_val, leaf_ph.get_data())?;
+
+        let mut current_slot = {
+            if is_dup && lower_inclusive {
+                prev_index
+            } else if prev_index == BEGINNING_OF_SLOT {
+                leaf_header.first_slot
             } else {
-                node_header.first_child
+                leaf_entries[prev_index].next_slot
             }
         };
+        //move to the next leaf when there're no more entries in this leaf.
+        let mut current_leaf_num = leaf_num;
+        if current_slot == NO_MORE_SLOTS {
+            current_leaf_num = leaf_header.next_page;
+            current_slot = BEGINNING_OF_SLOT;
+        }
+        error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+
+        Ok(IndexRangeScan {
+            ih: self,
+            upper: upper.to_vec(),
+            upper_inclusive,
+            current_leaf_num,
+            current_slot,
+            pending: Vec::new()
+        })
+    }
 
-        let next_node_ph = ok_or_return!(self.pfh.get_page(next_page_num), IndexingError::GetPageError);
-
-        let next_node_header = utils::get_header::<NodeHeader>(next_node_ph.get_data());
-        
-        let (to_delete_next, next_next_key) = {
-            if next_node_header.is_leaf {
-                match self.delete_from_leaf(key_val, rid, next_node_ph) {
-                    Err(e) => {
-                        return Err(e);
-                    },
-                    Ok(v) => v
-                }
-            } else {
-                match self.delete_from_node(key_val, rid, next_node_ph) {
-                    Err(e) => {
-                        return Err(e);
-                    },
-                    Ok(v) => v
-                }
-            }
-        };
+    /*
+     * Scan all entries of the index, RIDs are yielded in the sorted order
+     * of their keys.
+     * The scan starts at the leftmost leaf and follows next_page, no key
+     * is compared.
+     */
+    pub fn sequential_scan(&mut self) -> Result<IndexRangeScan<'_>, IndexingError> {
+        let current_leaf_num = self.leftmost_leaf()?;
+        Ok(IndexRangeScan {
+            ih: self,
+            upper: Vec::new(),
+            upper_inclusive: true,
+            current_leaf_num,
+            current_slot: BEGINNING_OF_SLOT,
+            pending: Vec::new()
+        })
+    }
 
-        /*
-         * If the entry to delete is also a key in the parent node. We need to update 
-         * the key in the parent node. If there's no more entries in the next node, 
-         * the next_key returned is null and we just delete the next node.
-         *
-         * The next_key in this node to return:
-         *   1. if this node is about to be deleted, return null;
-         *   2. return the key after the first slot.
-         */
-        let mut this_next_key = std::ptr::null_mut();
-        let mut key_changed = false;
+    pub fn get_attr_length(&self) -> usize {
+        self.header.attr_length
+    }
 
-        if is_dup {
-            if next_next_key.is_null() && to_delete_next {
-                //means the next node is empty.
-                error_return!(self.pfh.unpin_dirty_page(next_node_ph.get_page_num()), IndexingError::UnpinPageError);
-                error_return!(self.pfh.dispose_page(next_node_ph.get_page_num()), IndexingError::DisposePageError);
+    pub fn get_composite_key(&self) -> Option<CompositeKey> {
+        self.header.get_composite_key()
+    }
 
-                if curr_index == BEGINNING_OF_SLOT {//if the next node is the first child, then the key in parent node need to update.
-                    let first_slot = node_header.first_slot;
-                    node_header.first_child = node_entries[first_slot].page_num;
-                    node_header.first_slot = node_entries[first_slot].next_slot;
-                    node_entries[first_slot].next_slot = node_header.free_slot;
-                    node_header.free_slot = first_slot;
+    /*
+     * Check if a key exists in the B+ tree without collecting its RIDs.
+     * Only one path from the root to a leaf is read, buckets of a 
+     * duplicate key are not, so it's cheap enough to check a unique
+     * constraint before every insert.
+     */
+    pub fn key_exists(&mut self, key_val: *const u8) -> Result<bool, IndexingError> {
+        let key_val = key_val as *mut u8;
+        let leaf_num = self.find_leaf(key_val)?;
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let res = self.find_node_insert_index(key_val, leaf_ph.get_data());
+        error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+        let (_, is_dup) = res?;
+        Ok(is_dup)
+    }
 
-                    key_changed = true;
+    /*
+     * All RIDs associated with a key, for equi-joins.
+     * A key with duplicates has its RIDs in a bucket chain, RIDs of the
+     * whole chain are returned.
+     */
+    pub fn get_all_rids_for_key(&mut self, key_val: *const u8) -> Result<Vec<RID>, IndexingError> {
+        let key_val = key_val as *mut u8;
+        let leaf_num = self.find_leaf(key_val)?;
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let res = self.find_node_insert_index(key_val, leaf_ph.get_data());
+        let entry = match res {
+            Ok((prev_index, true)) => Ok(self.get_node_entries(leaf_ph.get_data())[prev_index]),
+            Ok((_, false)) => Err(IndexingError::KeyNotFound),
+            Err(e) => Err(e)
+        };
+        error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
 
-                } else if curr_index == node_header.first_slot {
-                    let first_slot = node_header.first_slot;
+        let entry = entry?;
+        match entry.et_type {
+            EntryType::New => Ok(vec![RID::new(entry.page_num, entry.slot_num)]),
+            EntryType::Duplicate => self.get_bucket_rids(entry.page_num),
+            EntryType::Unoccupied => {
+                dbg!(&entry);
+                Err(IndexingError::UnoccupiedEntry)
+            }
+        }
+    }
 
-                    node_header.first_slot = node_entries[first_slot].next_slot;
-                    node_entries[first_slot].next_slot = node_header.free_slot;
-                    node_header.free_slot = first_slot;
+    /*
+     * Check if any entry of the index points to rid, bucket entries 
+     * included.
+     * All leaves are walked, so it's O(n) and meant for integrity 
+     * checks, e.g. a record just inserted should be found here.
+     */
+    pub fn find_entry_by_rid(&mut self, rid: &RID) -> Result<bool, IndexingError> {
+        for res in self.sequential_scan()? {
+            if res? == *rid {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
 
-                    key_changed = true;
+    /*
+     * Rebuild the bucket chain of a duplicate key, so that every bucket
+     * except the last one is full.
+     * All RIDs are collected first, then the old buckets are disposed
+     * and new ones are filled from the collected RIDs. The disposed 
+     * pages are reused by the new buckets.
+     * Returns the number of pages freed, a key without buckets frees 
+     * nothing.
+     */
+    pub fn defragment_buckets(&mut self, key_val: *const u8) -> Result<usize, IndexingError> {
+        let key_val = key_val as *mut u8;
+        let leaf_num = self.find_leaf(key_val)?;
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let index = match self.find_node_insert_index(key_val, leaf_ph.get_data()) {
+            Ok((index, true)) => index,
+            Ok((_, false)) => {
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Err(IndexingError::KeyNotFound);
+            },
+            Err(e) => {
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Err(e);
+            }
+        };
+        let leaf_entries = self.get_node_entries(leaf_ph.get_data());
+        if let EntryType::New = leaf_entries[index].et_type {
+            error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+            return Ok(0);
+        }
 
-                } else {
-                    let prev_index = Self::find_prev_index(node_entries, node_header.first_slot, curr_index)?;
+        let rids = match self.get_bucket_rids(leaf_entries[index].page_num) {
+            Err(e) => {
+                let _ = self.pfh.unpin_page(leaf_num);
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+        let old_pages = match self.dispose_bucket_chain(leaf_entries[index].page_num) {
+            Err(e) => {
+                let _ = self.pfh.unpin_page(leaf_num);
+                return Err(e);
+            },
+            Ok(v) => v
+        };
 
-                    node_entries[prev_index].next_slot = node_entries[curr_index].next_slot;
-                    node_entries[curr_index].next_slot = node_header.free_slot;
-                    node_header.free_slot = curr_index;
-                }
-                node_header.num_keys -= 1;
-
-            } else {//if there're elements exist in the next node.
-                //if the key of the next node is changed and this next node is not the first
-                //child.
-                if !next_next_key.is_null() && curr_index != BEGINNING_OF_SLOT {
-                    let loc = unsafe {
-                        node.get_data().offset((self.header.keys_offset + curr_index * self.header.attr_length) as isize)
-                    };
-                    unsafe {
-                        std::ptr::copy(next_next_key, loc, self.header.attr_length);
+        let mut prev_ph: Option<PageHandle> = None;
+        let mut new_pages = 0;
+        for chunk in rids.chunks(self.header.max_bucket_keys) {
+            let bucket_ph = match self.create_new_bucket() {
+                Err(e) => {
+                    if let Some(ph) = prev_ph {
+                        let _ = self.pfh.unpin_dirty_page(ph.get_page_num());
                     }
+                    let _ = self.pfh.unpin_dirty_page(leaf_num);
+                    return Err(e);
+                },
+                Ok(v) => v
+            };
+            let bucket_header = unsafe {
+                utils::get_header_mut::<BucketHeader>(bucket_ph.get_data())
+            };
+            let bucket_entries = self.get_bucket_entries(bucket_ph.get_data());
+            for (i, rid) in chunk.iter().enumerate() {
+                bucket_entries[i].page_num = rid.get_page_num();
+                bucket_entries[i].slot_num = rid.get_slot_num();
+            }
+            //entries are linked in order, the free list starts after them.
+            bucket_entries[chunk.len()-1].next_slot = NO_MORE_SLOTS;
+            bucket_header.first_slot = 0;
+            bucket_header.free_slot = if chunk.len() == self.header.max_bucket_keys {
+                NO_MORE_SLOTS
+            } else {
+                chunk.len()
+            };
+            bucket_header.num_keys = chunk.len();
 
-                    if curr_index == node_header.first_slot {
-                        key_changed = true;
+            match prev_ph {
+                None => {
+                    leaf_entries[index].page_num = bucket_ph.get_page_num();
+                },
+                Some(ph) => {
+                    unsafe {
+                        utils::get_header_mut::<BucketHeader>(ph.get_data()).next_bucket = bucket_ph.get_page_num();
                     }
+                    error_return!(self.pfh.unpin_dirty_page(ph.get_page_num()), IndexingError::UnpinPageError);
                 }
             }
+            prev_ph = Some(bucket_ph);
+            new_pages += 1;
         }
-        
-        if key_changed {
-            this_next_key = unsafe {
-                node.get_data().offset((self.header.keys_offset + node_header.first_slot * self.header.attr_length) as isize)
-            };
-        }
-
-        let mut to_delete = false;
-        if node_header.num_keys == 0 {
-            to_delete = true;
+        if let Some(ph) = prev_ph {
+            error_return!(self.pfh.unpin_dirty_page(ph.get_page_num()), IndexingError::UnpinPageError);
         }
-        Ok((to_delete, this_next_key))
+        error_return!(self.pfh.unpin_dirty_page(leaf_num), IndexingError::UnpinPageError);
+        Ok(old_pages - new_pages)
     }
 
-    fn delete_from_leaf(&mut self, key_val: *mut u8, rid: &RID, leaf_node: PageHandle) -> Result<(bool, *mut u8), IndexingError> {
-        let leaf_header = utils::get_header_mut::<LeafHeader>(leaf_node.get_data());
-        let leaf_entries = self.get_node_entries(leaf_node.get_data());
-        let leaf_keys = unsafe {
-            leaf_node.get_data().offset(self.header.keys_offset as isize)
+    /*
+     * Walk the whole tree depth first, every node and bucket page is 
+     * visited once.
+     */
+    pub fn gather_stats(&mut self) -> Result<IndexStats, IndexingError> {
+        let mut stats = IndexStats {
+            num_entries: self.header.num_entries,
+            num_leaf_pages: 0,
+            num_internal_pages: 0,
+            tree_height: 0,
+            num_bucket_pages: 0,
+            avg_entries_per_leaf: 0.0
         };
+        let mut leaf_keys = 0;
+        let mut bucket_heads: Vec<u32> = Vec::new();
+
+        //pages to visit with their depth, the root has depth 1.
+        let mut stack: Vec<(u32, u32)> = vec![(self.root_page_num, 1)];
+        while let Some((page_num, depth)) = stack.pop() {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_BTREE_NODE);
+            let header = utils::get_header::<InternalHeader>(ph.get_data());
+            let entries = self.get_node_entries(ph.get_data());
+
+            if header.is_leaf {
+                stats.num_leaf_pages += 1;
+                stats.tree_height = stats.tree_height.max(depth);
+                leaf_keys += header.num_keys;
+            } else {
+                stats.num_internal_pages += 1;
+                stack.push((header.first_child, depth + 1));
+            }
 
-        let (curr_index, is_dup) = self.find_node_insert_index(key_val, leaf_node.get_data())?;
+            let mut curr_index = header.first_slot;
+            while curr_index != NO_MORE_SLOTS {
+                let entry = &entries[curr_index];
+                if !header.is_leaf {
+                    stack.push((entry.page_num, depth + 1));
+                } else if let EntryType::Duplicate = entry.et_type {
+                    bucket_heads.push(entry.page_num);
+                }
+                curr_index = entry.next_slot;
+            }
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+        }
+
+        for head in bucket_heads {
+            let mut page_num = head;
+            while page_num != NO_MORE_PAGES {
+                let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+                let next_bucket = utils::get_header::<BucketHeader>(ph.get_data()).next_bucket;
+                error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+                stats.num_bucket_pages += 1;
+                page_num = next_bucket;
+            }
+        }
+
+        if stats.num_leaf_pages > 0 {
+            stats.avg_entries_per_leaf = leaf_keys as f64 / stats.num_leaf_pages as f64;
+        }
+        Ok(stats)
+    }
+
+    /*
+     * Render the tree for debugging, one node per line and children are
+     * indented under their parent. Internal nodes show their separator 
+     * keys, leaf nodes show their (key, RID) pairs, a duplicate key 
+     * shows one pair for each RID in its buckets.
+     */
+    pub fn print_tree(&mut self) -> Result<String, IndexingError> {
+        let mut writer = IndentedWriter::new();
+
+        //children are pushed in reverse order so that they are printed in key order.
+        let mut stack: Vec<(u32, usize)> = vec![(self.root_page_num, 0)];
+        while let Some((page_num, depth)) = stack.pop() {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_BTREE_NODE);
+            let header = utils::get_header::<InternalHeader>(ph.get_data());
+            let entries = self.get_node_entries(ph.get_data());
+
+            let mut items: Vec<String> = Vec::new();
+            let mut children: Vec<u32> = vec![header.first_child];
+            let mut bucket_rids: Result<Vec<RID>, IndexingError> = Ok(Vec::new());
+            let mut curr_index = header.first_slot;
+            while curr_index != NO_MORE_SLOTS {
+                let entry = &entries[curr_index];
+                let key = self.format_key(self.get_key(ph.get_data(), curr_index));
+                if !header.is_leaf {
+                    items.push(key);
+                    children.push(entry.page_num);
+                } else if let EntryType::Duplicate = entry.et_type {
+                    bucket_rids = self.get_bucket_rids(entry.page_num);
+                    match &bucket_rids {
+                        Ok(rids) => items.extend(rids.iter().map(|rid| format!("({}, {})", key, rid))),
+                        Err(_) => break
+                    }
+                } else {
+                    items.push(format!("({}, {})", key, RID::new(entry.page_num, entry.slot_num)));
+                }
+                curr_index = entry.next_slot;
+            }
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            bucket_rids?;
+
+            writer.indent = depth;
+            if header.is_leaf {
+                writer.line(&format!("leaf 0x{:08X}: {}", page_num, items.join(" ")));
+            } else {
+                writer.line(&format!("internal 0x{:08X}: [{}]", page_num, items.join(", ")));
+                stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+            }
+        }
+        Ok(writer.into_string())
+    }
+
+    fn format_key(&self, key: *mut u8) -> String {
+        match self.header.attr_type {
+            AttrType::INT => unsafe { *(key as *const i32) }.to_string(),
+            AttrType::FLOAT => unsafe { *(key as *const f32) }.to_string(),
+            AttrType::BIGINT => unsafe { *(key as *const i64) }.to_string(),
+            AttrType::DOUBLE => unsafe { *(key as *const f64) }.to_string(),
+            AttrType::STRING => {
+                //strings are null-padded to attr_length.
+                let bytes = unsafe {
+                    std::slice::from_raw_parts(key, self.header.attr_length)
+                };
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                String::from_utf8_lossy(&bytes[..len]).into_owned()
+            }
+        }
+    }
+
+    /*
+     * Height of the B+ tree, following first_child down to the leaf level.
+     * A tree with only a root leaf has height 1.
+     */
+    pub fn tree_height(&mut self) -> Result<u32, IndexingError> {
+        let mut height = 0;
+        let mut page_num = self.root_page_num;
+        loop {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let header = utils::get_header::<InternalHeader>(ph.get_data());
+            let (is_leaf, first_child) = (header.is_leaf, header.first_child);
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+
+            height += 1;
+            if is_leaf {
+                return Ok(height);
+            }
+            page_num = first_child;
+        }
+    }
+
+    /*
+     * Number of leaves, counted along the leaf chain from the leftmost 
+     * leaf. It's the least number of pages a full index scan reads.
+     */
+    pub fn num_leaf_pages(&mut self) -> Result<usize, IndexingError> {
+        let mut num_leaves = 0;
+        let mut page_num = self.leftmost_leaf()?;
+        while page_num != NO_MORE_PAGES {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let next_page = utils::get_header::<LeafHeader>(ph.get_data()).next_page;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            num_leaves += 1;
+            page_num = next_page;
+        }
+        Ok(num_leaves)
+    }
+
+    /*
+     * Walk through all leaves from the leftmost one and check:
+     *   1. prev_page of each leaf points back to the previous leaf.
+     *   2. keys are non-decreasing within a leaf and across leaves.
+     * Returns the number of leaves.
+     */
+    pub fn validate_leaf_chain(&mut self) -> Result<usize, IndexingError> {
+        let file_header = self.header;
+        let attr_length = self.header.attr_length;
+        let mut prev_page = NO_MORE_PAGES;
+        let mut last_key: Vec<u8> = Vec::new();
+        let mut num_leaves = 0;
+
+        let mut page_num = self.leftmost_leaf()?;
+        while page_num != NO_MORE_PAGES {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let header = utils::get_header::<LeafHeader>(ph.get_data());
+            let mut valid = header.is_leaf && header.prev_page == prev_page;
+
+            for slot in self.get_slots(ph.get_data()) {
+                if !valid {
+                    break;
+                }
+                let key = self.get_key(ph.get_data(), slot);
+                if !last_key.is_empty() && file_header.compare_keys(last_key.as_mut_ptr(), key) == Ordering::Greater {
+                    valid = false;
+                }
+                last_key.clear();
+                last_key.extend_from_slice(unsafe {
+                    std::slice::from_raw_parts(key, attr_length)
+                });
+            }
+
+            let next_page = header.next_page;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            if !valid {
+                dbg!(page_num);
+                return Err(IndexingError::InvalidLeafChain);
+            }
+
+            num_leaves += 1;
+            prev_page = page_num;
+            page_num = next_page;
+        }
+        Ok(num_leaves)
+    }
+
+    /*
+     * Walk the bucket chain of a key, a bucket visited twice means 
+     * the chain links back into itself.
+     * Returns the number of buckets, a key without duplicates has none.
+     */
+    pub fn validate_bucket_chain_for_key(&mut self, key_val: *const u8) -> Result<usize, IndexingError> {
+        let key_val = key_val as *mut u8;
+        let leaf_num = self.find_leaf(key_val)?;
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let entry = match self.find_node_insert_index(key_val, leaf_ph.get_data()) {
+            Ok((index, true)) => Ok(self.get_node_entries(leaf_ph.get_data())[index]),
+            Ok((_, false)) => Err(IndexingError::KeyNotFound),
+            Err(e) => Err(e)
+        };
+        error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+        let entry = entry?;
+        if let EntryType::New = entry.et_type {
+            return Ok(0);
+        }
+
+        let mut visited = HashSet::new();
+        let mut page_num = entry.page_num;
+        while page_num != NO_MORE_PAGES {
+            if !visited.insert(page_num) {
+                dbg!(page_num);
+                return Err(IndexingError::BucketChainCycle);
+            }
+            let bucket_ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let next_bucket = utils::get_header::<BucketHeader>(bucket_ph.get_data()).next_bucket;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            page_num = next_bucket;
+        }
+        Ok(visited.len())
+    }
+
+    /*
+     * Copy the minimum key into out, returns false if the tree is empty.
+     * As leaves are not disposed until they are merged, empty leaves are
+     * skipped by following next_page.
+     */
+    pub fn min_key(&mut self, out: &mut [u8]) -> Result<bool, IndexingError> {
+        if out.len() < self.header.attr_length {
+            dbg!(out.len());
+            return Err(IndexingError::BufferTooSmall);
+        }
+        let mut page_num = self.leftmost_leaf()?;
+        while page_num != NO_MORE_PAGES {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let header = utils::get_header::<LeafHeader>(ph.get_data());
+            let found = header.first_slot != NO_MORE_SLOTS;
+            if found {
+                self.copy_key(ph.get_data(), header.first_slot, out);
+            }
+            let next_page = header.next_page;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+
+            if found {
+                return Ok(true);
+            }
+            page_num = next_page;
+        }
+        Ok(false)
+    }
+
+    /*
+     * Copy the maximum key into out, returns false if the tree is empty.
+     * Empty leaves are skipped by following prev_page.
+     */
+    pub fn max_key(&mut self, out: &mut [u8]) -> Result<bool, IndexingError> {
+        if out.len() < self.header.attr_length {
+            dbg!(out.len());
+            return Err(IndexingError::BufferTooSmall);
+        }
+        let mut page_num = self.rightmost_leaf()?;
+        while page_num != NO_MORE_PAGES {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let header = utils::get_header::<LeafHeader>(ph.get_data());
+            let last_slot = self.get_slots(ph.get_data()).pop();
+            if let Some(slot) = last_slot {
+                self.copy_key(ph.get_data(), slot, out);
+            }
+            let prev_page = header.prev_page;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+
+            if last_slot.is_some() {
+                return Ok(true);
+            }
+            page_num = prev_page;
+        }
+        Ok(false)
+    }
+
+    fn copy_key(&self, data: *mut u8, slot: usize, out: &mut [u8]) {
+        let key = self.get_key(data, slot);
+        out[..self.header.attr_length].copy_from_slice(unsafe {
+            std::slice::from_raw_parts(key, self.header.attr_length)
+        });
+    }
+
+    /*
+     * page num of the rightmost leaf, the leaf is not pinned.
+     * The last child of an internal node is pointed by the last entry, 
+     * or first_child if the node has no entries.
+     */
+    fn rightmost_leaf(&mut self) -> Result<u32, IndexingError> {
+        let mut ph = ok_or_return!(self.pfh.get_page(self.root_page_num), IndexingError::GetPageError);
+        loop {
+            let header = utils::get_header::<InternalHeader>(ph.get_data());
+            let page_num = ph.get_page_num();
+            let next_page_num = {
+                if header.is_leaf {
+                    NO_MORE_PAGES
+                } else {
+                    match self.get_slots(ph.get_data()).pop() {
+                        None => header.first_child,
+                        Some(slot) => self.get_node_entries(ph.get_data())[slot].page_num
+                    }
+                }
+            };
+
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            if next_page_num == NO_MORE_PAGES {
+                return Ok(page_num);
+            }
+            ph = ok_or_return!(self.pfh.get_page(next_page_num), IndexingError::GetPageError);
+        }
+    }
+
+    //page num of the leftmost leaf, the leaf is not pinned.
+    fn leftmost_leaf(&mut self) -> Result<u32, IndexingError> {
+        let mut page_num = self.root_page_num;
+        loop {
+            let ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let header = utils::get_header::<InternalHeader>(ph.get_data());
+            let (is_leaf, first_child) = (header.is_leaf, header.first_child);
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+
+            if is_leaf {
+                return Ok(page_num);
+            }
+            page_num = first_child;
+        }
+    }
+
+    /*
+     * Descend from the root to the leaf that the key belongs to.
+     * Returns the page num of the leaf, the leaf is not pinned.
+     */
+    fn find_leaf(&mut self, key_val: *mut u8) -> Result<u32, IndexingError> {
+        let mut node_ph = ok_or_return!(self.pfh.get_page(self.root_page_num), IndexingError::GetPageError);
+        loop {
+            debug_assert_eq!(node_ph.get_page_type(), PAGE_TYPE_BTREE_NODE);
+            let node_header = utils::get_header::<InternalHeader>(node_ph.get_data());
+            if node_header.is_leaf {
+                let leaf_num = node_ph.get_page_num();
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Ok(leaf_num);
+            }
+
+            let (prev_index, _) = self.find_node_insert_index(key_val, node_ph.get_data())?;
+            let next_page_num = {
+                if prev_index == BEGINNING_OF_SLOT {
+                    node_header.first_child
+                } else {
+                    self.get_node_entries(node_ph.get_data())[prev_index].page_num
+                }
+            };
+
+            error_return!(self.pfh.unpin_page(node_ph.get_page_num()), IndexingError::UnpinPageError);
+            node_ph = ok_or_return!(self.pfh.get_page(next_page_num), IndexingError::GetPageError);
+        }
+    }
+
+    //all RIDs stored in a bucket chain.
+    fn get_bucket_rids(&mut self, bucket_page_num: u32) -> Result<Vec<RID>, IndexingError> {
+        let mut rids = Vec::new();
+        let mut page_num = bucket_page_num;
+        while page_num != NO_MORE_PAGES {
+            let bucket_ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            debug_assert_eq!(bucket_ph.get_page_type(), PAGE_TYPE_BUCKET);
+            let bucket_header = utils::get_header::<BucketHeader>(bucket_ph.get_data());
+            let bucket_entries = self.get_bucket_entries(bucket_ph.get_data());
+
+            let mut curr_index = bucket_header.first_slot;
+            while curr_index != NO_MORE_SLOTS {
+                rids.push(RID::new(bucket_entries[curr_index].page_num, bucket_entries[curr_index].slot_num));
+                curr_index = bucket_entries[curr_index].next_slot;
+            }
+
+            let next_bucket = bucket_header.next_bucket;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            page_num = next_bucket;
+        }
+        Ok(rids)
+    }
+
+    /*
+     * Dispose all pages of a bucket chain.
+     * The entry pointing to the chain is left to the caller.
+     * Returns the number of pages disposed.
+     */
+    fn dispose_bucket_chain(&mut self, first_bucket_num: u32) -> Result<usize, IndexingError> {
+        let mut disposed = 0;
+        let mut page_num = first_bucket_num;
+        while page_num != NO_MORE_PAGES {
+            let bucket_ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            debug_assert_eq!(bucket_ph.get_page_type(), PAGE_TYPE_BUCKET);
+            let next_bucket = utils::get_header::<BucketHeader>(bucket_ph.get_data()).next_bucket;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            error_return!(self.pfh.dispose_page(page_num), IndexingError::DisposePageError);
+            disposed += 1;
+            page_num = next_bucket;
+        }
+        Ok(disposed)
+    }
+
+    /*
+     * Delete a key with duplicates and all of its RIDs.
+     * The bucket chain is disposed at once, then the entry is turned
+     * into a New one with the first RID, so that the key itself is
+     * deleted by delete_entry like any other key.
+     * Returns the number of RIDs deleted.
+     */
+    fn delete_duplicate_key(&mut self, key_val: *mut u8) -> Result<usize, IndexingError> {
+        let leaf_num = self.find_leaf(key_val)?;
+        let leaf_ph = ok_or_return!(self.pfh.get_page(leaf_num), IndexingError::GetPageError);
+        let index = match self.find_node_insert_index(key_val, leaf_ph.get_data()) {
+            Ok((index, true)) => index,
+            Ok((_, false)) => {
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Err(IndexingError::KeyNotFound);
+            },
+            Err(e) => {
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Err(e);
+            }
+        };
+        let leaf_entries = self.get_node_entries(leaf_ph.get_data());
+        let bucket_num = leaf_entries[index].page_num;
+        let rids = match self.get_bucket_rids(bucket_num) {
+            Ok(v) => v,
+            Err(e) => {
+                error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.dispose_bucket_chain(bucket_num) {
+            error_return!(self.pfh.unpin_page(leaf_num), IndexingError::UnpinPageError);
+            return Err(e);
+        }
+
+        leaf_entries[index].et_type = EntryType::New;
+        leaf_entries[index].page_num = rids[0].get_page_num();
+        leaf_entries[index].slot_num = rids[0].get_slot_num();
+        error_return!(self.pfh.unpin_dirty_page(leaf_num), IndexingError::UnpinPageError);
+
+        if let Err(e) = self.delete_entry(key_val, &rids[0]) {
+            dbg!(&e);
+            return Err(IndexingError::DeleteEntryError);
+        }
+        //delete_entry counts only the first RID.
+        self.header.num_entries -= rids.len() - 1;
+        self.header_changed = true;
+        Ok(rids.len())
+    }
+
+    /*
+     * Delete all entries with keys between lower and upper, both inclusive.
+     * Returns the number of entries deleted.
+     *
+     * Entries in range are collected by walking through the leaves, 
+     * each leaf is visited once. Then they are deleted by delete_entry, 
+     * so that parents of emptied leaves are updated, underflowed nodes 
+     * are merged or redistributed. A key with duplicates has its whole
+     * bucket chain disposed at once, see delete_duplicate_key.
+     * Every deletion descends from the root again, a pass per leaf 
+     * would be faster but has to rebalance the tree on its own.
+     */
+    pub fn delete_range(&mut self, lower: *const u8, upper: *const u8) -> Result<usize, IndexingError> {
+        let file_header = self.header;
+        let attr_length = self.header.attr_length;
+        let (lower, upper) = (lower as *mut u8, upper as *mut u8);
+        if file_header.compare_keys(lower, upper) == Ordering::Greater {
+            return Ok(0);
+        }
+
+        let mut to_delete: Vec<(Vec<u8>, RID)> = Vec::new();
+        let mut dup_keys: Vec<Vec<u8>> = Vec::new();
+        let mut page_num = self.find_leaf(lower)?;
+        let mut finished = false;
+        while !finished && page_num != NO_MORE_PAGES {
+            let leaf_ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let leaf_header = utils::get_header::<LeafHeader>(leaf_ph.get_data());
+            let leaf_entries = self.get_node_entries(leaf_ph.get_data());
+
+            for slot in self.get_slots(leaf_ph.get_data()) {
+                let key = self.get_key(leaf_ph.get_data(), slot);
+                if file_header.compare_keys(key, lower) == Ordering::Less {
+                    continue;
+                }
+                if file_header.compare_keys(key, upper) == Ordering::Greater {
+                    finished = true;
+                    break;
+                }
+
+                let key = unsafe {
+                    std::slice::from_raw_parts(key, attr_length)
+                }.to_vec();
+                let entry = leaf_entries[slot];
+                match entry.et_type {
+                    EntryType::New => to_delete.push((key, RID::new(entry.page_num, entry.slot_num))),
+                    EntryType::Duplicate => dup_keys.push(key),
+                    EntryType::Unoccupied => {
+                        dbg!(&entry);
+                        error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+                        return Err(IndexingError::UnoccupiedEntry);
+                    }
+                }
+            }
+
+            let next_page = leaf_header.next_page;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            page_num = next_page;
+        }
+
+        for (key, rid) in to_delete.iter_mut() {
+            if let Err(e) = self.delete_entry(key.as_mut_ptr(), rid) {
+                dbg!(&e);
+                return Err(IndexingError::DeleteEntryError);
+            }
+        }
+        let mut deleted = to_delete.len();
+        for key in dup_keys.iter_mut() {
+            deleted += self.delete_duplicate_key(key.as_mut_ptr())?;
+        }
+        Ok(deleted)
+    }
+
+    /*
+     * Delete an entry from a B+ tree is no doubt the most difficult operation to 
+     * implement.
+     * When the number of keys in a node is less than self.header.max_node_keys/2,
+     * the node borrows a key from a sibling, or merges with a sibling if both 
+     * of them are no more than half full. See try_merge_or_redistribute.
+     */
+    pub fn delete_entry(&mut self, key_val: *mut u8, rid: &RID) -> Result<(), Error> {
+        //the root is pinned for the deletion, and it's unpinned before it's collapsed.
+        let root_ph = self.pfh.get_page(self.root_page_num)?;
+        let root_header = utils::get_header::<NodeHeader>(root_ph.get_data());
+        
+        if root_header.is_leaf {
+            let res = self.delete_from_leaf(key_val, rid, root_ph);
+            self.pfh.unpin_dirty_page(self.root_page_num)?;
+            if let Err(e) = res {
+                dbg!(e);
+                return Err(Error::DeleteFromLeafError);
+            }
+        } else {
+            let res = self.delete_from_node(key_val, rid, root_ph);
+            self.pfh.unpin_dirty_page(self.root_page_num)?;
+            match res {
+                Err(e) => {
+                    dbg!(e);
+                    return Err(Error::DeleteFromNodeError);
+                },
+                Ok(v) => {
+                    let (to_delete, _) = v;
+                    if to_delete {
+                        if let Err(e) = self.collapse_root() {
+                            dbg!(e);
+                            return Err(Error::DeleteFromNodeError);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.header.num_entries -= 1;
+        self.header_changed = true;
+        Ok(())
+    }
+
+    /*
+     * When the last two children of the root are merged, the root is 
+     * left with no key and a single child. The child becomes the new 
+     * root and the old root page is disposed, so the tree gets one 
+     * level lower.
+     */
+    fn collapse_root(&mut self) -> Result<(), IndexingError> {
+        let old_root_num = self.root_page_num;
+        let root_ph = ok_or_return!(self.pfh.get_page(old_root_num), IndexingError::GetPageError);
+        let first_child = utils::get_header::<InternalHeader>(root_ph.get_data()).first_child;
+        error_return!(self.pfh.unpin_page(old_root_num), IndexingError::UnpinPageError);
+
+        error_return!(self.pfh.dispose_page(old_root_num), IndexingError::DisposePageError);
+        self.root_page_num = first_child;
+        self.header.root_page = first_child;
+        self.header_changed = true;
+        Ok(())
+    }
+
+    fn delete_from_node(&mut self, key_val: *mut u8, rid: &RID, node: PageHandle) -> Result<(bool, *mut u8), IndexingError> {
+        debug_assert_eq!(node.get_page_type(), PAGE_TYPE_BTREE_NODE);
+        let node_header = unsafe {
+            utils::get_header_mut::<InternalHeader>(node.get_data())
+        };
+
+        let (prev_index, _) = self.find_node_insert_index(key_val, node.get_data())?;
+
+        //the entry at prev_index points to the child that the key belongs to,
+        //BEGINNING_OF_SLOT means the first child.
+        let node_entries = self.get_node_entries(node.get_data());
+        let next_page_num = {
+            if prev_index == BEGINNING_OF_SLOT {
+                node_header.first_child
+            } else {
+                node_entries[prev_index].page_num
+            }
+        };
+
+        let next_node_ph = ok_or_return!(self.pfh.get_page(next_page_num), IndexingError::GetPageError);
+
+        let next_node_header = utils::get_header::<NodeHeader>(next_node_ph.get_data());
+        
+        let res = {
+            if next_node_header.is_leaf {
+                self.delete_from_leaf(key_val, rid, next_node_ph)
+            } else {
+                self.delete_from_node(key_val, rid, next_node_ph)
+            }
+        };
+        //a failed deletion must not leave the child pinned.
+        let (_, next_next_key) = match res {
+            Err(e) => {
+                let _ = self.pfh.unpin_dirty_page(next_page_num);
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+
+        /*
+         * If the smallest key of the next node is changed, the key pointing to 
+         * the next node is updated. 
+         * As the first child has no key in this node, the smallest key of this
+         * node is changed, so it's returned to the parent.
+         */
+        let mut this_next_key = std::ptr::null_mut();
+        if !next_next_key.is_null() {
+            if prev_index == BEGINNING_OF_SLOT {
+                this_next_key = next_next_key;
+            } else {
+                unsafe {
+                    std::ptr::copy(next_next_key, self.get_key(node.get_data(), prev_index), self.header.attr_length);
+                }
+            }
+        }
+
+        self.try_merge_or_redistribute(node, next_node_ph, prev_index)?;
+
+        Ok((node_header.num_keys == 0, this_next_key))
+    }
+
+    /*
+     * Keep a node at least half full after a deletion, see rebalance_node.
+     * The node is unpinned here on every path, including errors, and
+     * disposed if it was merged into its left sibling.
+     */
+    fn try_merge_or_redistribute(&mut self, parent_ph: PageHandle, node_ph: PageHandle, parent_prev_index: usize) -> Result<(), IndexingError> {
+        let node_page_num = node_ph.get_page_num();
+        match self.rebalance_node(parent_ph, node_ph, parent_prev_index) {
+            Err(e) => {
+                let _ = self.pfh.unpin_dirty_page(node_page_num);
+                Err(e)
+            },
+            Ok(merged) => {
+                error_return!(self.pfh.unpin_dirty_page(node_page_num), IndexingError::UnpinPageError);
+                //a pinned page can't be disposed, so the node is disposed after it's unpinned.
+                if merged {
+                    error_return!(self.pfh.dispose_page(node_page_num), IndexingError::DisposePageError);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /*
+     * Keep a node at least half full after a deletion.
+     * parent_prev_index is the slot of the key pointing to the node in
+     * the parent, BEGINNING_OF_SLOT if the node is the first child.
+     *
+     * If the node has less than max_node_keys/2 keys:
+     *   1. borrow a key from the left sibling, or the right sibling, if the 
+     *      sibling has more than max_node_keys/2 keys. 
+     *   2. otherwise merge the node with a sibling, the right one of the two 
+     *      is merged into the left one, then the key pointing to the right one 
+     *      is removed from the parent and the right page is disposed.
+     * The root keeps at least one key, as it's not collapsed when it has 
+     * only one child left.
+     *
+     * Returns true if the node was merged into its left sibling, then 
+     * the caller disposes it. The node is left pinned for the caller,
+     * and siblings are unpinned on every path.
+     */
+    fn rebalance_node(&mut self, parent_ph: PageHandle, node_ph: PageHandle, parent_prev_index: usize) -> Result<bool, IndexingError> {
+        let min_keys = self.header.max_node_keys/2;
+        let node_header = utils::get_header::<NodeHeader>(node_ph.get_data());
+        if node_header.num_keys >= min_keys {
+            return Ok(false);
+        }
+        let is_leaf = node_header.is_leaf;
+
+        let parent_header = utils::get_header::<InternalHeader>(parent_ph.get_data());
+        let parent_entries = self.get_node_entries(parent_ph.get_data());
+        let parent_slots = self.get_slots(parent_ph.get_data());
+
+        //slots of the keys pointing to the left and right siblings.
+        let (left_index, sep_index) = {
+            if parent_prev_index == BEGINNING_OF_SLOT {
+                (None, BEGINNING_OF_SLOT)
+            } else {
+                let pos = match parent_slots.iter().position(|&slot| slot == parent_prev_index) {
+                    None => {
+                        dbg!(parent_prev_index);
+                        return Err(IndexingError::EntriesBroken);
+                    },
+                    Some(v) => v
+                };
+                if pos == 0 {
+                    (Some(BEGINNING_OF_SLOT), parent_prev_index)
+                } else {
+                    (Some(parent_slots[pos-1]), parent_prev_index)
+                }
+            }
+        };
+        let right_index = {
+            if parent_prev_index == BEGINNING_OF_SLOT {
+                parent_header.first_slot
+            } else {
+                parent_entries[parent_prev_index].next_slot
+            }
+        };
+
+        //borrow from the left sibling.
+        if let Some(index) = left_index {
+            let left_page_num = if index == BEGINNING_OF_SLOT { parent_header.first_child } else { parent_entries[index].page_num };
+            let left_ph = ok_or_return!(self.pfh.get_page(left_page_num), IndexingError::GetPageError);
+            let left_header = utils::get_header::<NodeHeader>(left_ph.get_data());
+
+            if left_header.num_keys > min_keys {
+                self.borrow_from_left(parent_ph, sep_index, left_ph, node_ph, is_leaf);
+                error_return!(self.pfh.unpin_dirty_page(left_page_num), IndexingError::UnpinPageError);
+                return Ok(false);
+            }
+            error_return!(self.pfh.unpin_page(left_page_num), IndexingError::UnpinPageError);
+        }
+
+        //borrow from the right sibling.
+        if right_index != NO_MORE_SLOTS {
+            let right_page_num = parent_entries[right_index].page_num;
+            let right_ph = ok_or_return!(self.pfh.get_page(right_page_num), IndexingError::GetPageError);
+            let right_header = utils::get_header::<NodeHeader>(right_ph.get_data());
+
+            if right_header.num_keys > min_keys {
+                self.borrow_from_right(parent_ph, right_index, node_ph, right_ph, is_leaf);
+                error_return!(self.pfh.unpin_dirty_page(right_page_num), IndexingError::UnpinPageError);
+                return Ok(false);
+            }
+            error_return!(self.pfh.unpin_page(right_page_num), IndexingError::UnpinPageError);
+        }
+
+        //merge the node into the left sibling.
+        if let Some(index) = left_index {
+            let left_page_num = if index == BEGINNING_OF_SLOT { parent_header.first_child } else { parent_entries[index].page_num };
+            let left_ph = ok_or_return!(self.pfh.get_page(left_page_num), IndexingError::GetPageError);
+
+            if let Err(e) = self.merge_nodes(parent_ph, index, sep_index, left_ph, node_ph, is_leaf) {
+                let _ = self.pfh.unpin_dirty_page(left_page_num);
+                return Err(e);
+            }
+
+            error_return!(self.pfh.unpin_dirty_page(left_page_num), IndexingError::UnpinPageError);
+            return Ok(true);
+        }
+
+        //merge the right sibling into the node.
+        if right_index != NO_MORE_SLOTS {
+            let right_page_num = parent_entries[right_index].page_num;
+            let right_ph = ok_or_return!(self.pfh.get_page(right_page_num), IndexingError::GetPageError);
+
+            if let Err(e) = self.merge_nodes(parent_ph, parent_prev_index, right_index, node_ph, right_ph, is_leaf) {
+                let _ = self.pfh.unpin_dirty_page(right_page_num);
+                return Err(e);
+            }
+
+            error_return!(self.pfh.unpin_dirty_page(right_page_num), IndexingError::UnpinPageError);
+            error_return!(self.pfh.dispose_page(right_page_num), IndexingError::DisposePageError);
+            return Ok(false);
+        }
+
+        Ok(false)
+    }
+
+    /*
+     * Move the last key of the left sibling to the node.
+     * sep_index is the slot of the key pointing to the node in the parent.
+     * For internal nodes, the key in the parent comes down to the node, and
+     * the last key of the left sibling goes up to the parent.
+     */
+    fn borrow_from_left(&mut self, parent_ph: PageHandle, sep_index: usize, left_ph: PageHandle, node_ph: PageHandle, is_leaf: bool) {
+        let left_slots = self.get_slots(left_ph.get_data());
+        let last = left_slots[left_slots.len()-1];
+        let before_last = if left_slots.len() > 1 { left_slots[left_slots.len()-2] } else { BEGINNING_OF_SLOT };
+        let entry = self.get_node_entries(left_ph.get_data())[last];
+        let sep_key = self.get_key(parent_ph.get_data(), sep_index);
+
+        if is_leaf {
+            self.link_node_entry(node_ph.get_data(), BEGINNING_OF_SLOT, entry, self.get_key(left_ph.get_data(), last));
+            let node_header = utils::get_header::<NodeHeader>(node_ph.get_data());
+            unsafe {
+                std::ptr::copy(self.get_key(node_ph.get_data(), node_header.first_slot), sep_key, self.header.attr_length);
+            }
+        } else {
+            let node_header = unsafe {
+                utils::get_header_mut::<InternalHeader>(node_ph.get_data())
+            };
+            let mut down_entry = entry;
+            down_entry.page_num = node_header.first_child;
+            self.link_node_entry(node_ph.get_data(), BEGINNING_OF_SLOT, down_entry, sep_key);
+            node_header.first_child = entry.page_num;
+            unsafe {
+                std::ptr::copy(self.get_key(left_ph.get_data(), last), sep_key, self.header.attr_length);
+            }
+        }
+        self.unlink_node_entry(left_ph.get_data(), before_last, last);
+    }
+
+    /*
+     * Move the first key of the right sibling to the node.
+     * sep_index is the slot of the key pointing to the right sibling in the parent.
+     */
+    fn borrow_from_right(&mut self, parent_ph: PageHandle, sep_index: usize, node_ph: PageHandle, right_ph: PageHandle, is_leaf: bool) {
+        let node_slots = self.get_slots(node_ph.get_data());
+        let tail = if node_slots.is_empty() { BEGINNING_OF_SLOT } else { node_slots[node_slots.len()-1] };
+        let right_header = unsafe {
+            utils::get_header_mut::<InternalHeader>(right_ph.get_data())
+        };
+        let first = right_header.first_slot;
+        let entry = self.get_node_entries(right_ph.get_data())[first];
+        let sep_key = self.get_key(parent_ph.get_data(), sep_index);
+
+        if is_leaf {
+            self.link_node_entry(node_ph.get_data(), tail, entry, self.get_key(right_ph.get_data(), first));
+            self.unlink_node_entry(right_ph.get_data(), BEGINNING_OF_SLOT, first);
+            unsafe {
+                std::ptr::copy(self.get_key(right_ph.get_data(), right_header.first_slot), sep_key, self.header.attr_length);
+            }
+        } else {
+            let mut down_entry = entry;
+            down_entry.page_num = right_header.first_child;
+            self.link_node_entry(node_ph.get_data(), tail, down_entry, sep_key);
+            right_header.first_child = entry.page_num;
+            unsafe {
+                std::ptr::copy(self.get_key(right_ph.get_data(), first), sep_key, self.header.attr_length);
+            }
+            self.unlink_node_entry(right_ph.get_data(), BEGINNING_OF_SLOT, first);
+        }
+    }
+
+    /*
+     * Merge the right node into the left node and remove the key pointing 
+     * to the right node from the parent.
+     * sep_prev_index is the slot before sep_index in the parent.
+     * The right page is disposed by the caller.
+     */
+    fn merge_nodes(&mut self, parent_ph: PageHandle, sep_prev_index: usize, sep_index: usize, left_ph: PageHandle, right_ph: PageHandle, is_leaf: bool) -> Result<(), IndexingError> {
+        let left_slots = self.get_slots(left_ph.get_data());
+        let mut tail = if left_slots.is_empty() { BEGINNING_OF_SLOT } else { left_slots[left_slots.len()-1] };
+        let right_entries = self.get_node_entries(right_ph.get_data());
+
+        //for internal nodes, the key in the parent comes down and points to 
+        //the first child of the right node.
+        if !is_leaf {
+            let right_header = utils::get_header::<InternalHeader>(right_ph.get_data());
+            let mut down_entry = self.get_node_entries(parent_ph.get_data())[sep_index];
+            down_entry.page_num = right_header.first_child;
+            tail = self.link_node_entry(left_ph.get_data(), tail, down_entry, self.get_key(parent_ph.get_data(), sep_index));
+        }
+
+        for slot in self.get_slots(right_ph.get_data()) {
+            tail = self.link_node_entry(left_ph.get_data(), tail, right_entries[slot], self.get_key(right_ph.get_data(), slot));
+        }
+
+        self.unlink_node_entry(parent_ph.get_data(), sep_prev_index, sep_index);
+
+        if is_leaf {
+            let left_header = unsafe {
+                utils::get_header_mut::<LeafHeader>(left_ph.get_data())
+            };
+            let right_header = utils::get_header::<LeafHeader>(right_ph.get_data());
+            let next_page = right_header.next_page;
+
+            left_header.next_page = next_page;
+            if next_page != NO_MORE_PAGES {
+                let next_ph = ok_or_return!(self.pfh.get_page(next_page), IndexingError::GetPageError);
+                let next_header = unsafe {
+                    utils::get_header_mut::<LeafHeader>(next_ph.get_data())
+                };
+                next_header.prev_page = left_ph.get_page_num();
+
+                error_return!(self.pfh.unpin_dirty_page(next_page), IndexingError::UnpinPageError);
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_from_leaf(&mut self, key_val: *mut u8, rid: &RID, leaf_node: PageHandle) -> Result<(bool, *mut u8), IndexingError> {
+        let leaf_header = unsafe {
+            utils::get_header_mut::<LeafHeader>(leaf_node.get_data())
+        };
+        let leaf_entries = self.get_node_entries(leaf_node.get_data());
+        let leaf_keys = unsafe {
+            leaf_node.get_data().add(self.header.keys_offset)
+        };
+
+        let (curr_index, is_dup) = self.find_node_insert_index(key_val, leaf_node.get_data())?;
         
         if !is_dup {
             return Err(IndexingError::InvalidEntry);
@@ -858,13 +2287,13 @@ impl IndexHandle {
         }
         if key_changed && !to_delete {
             this_next_key = unsafe {
-                leaf_keys.offset((leaf_header.first_slot * self.header.attr_length) as isize)
+                leaf_keys.add(leaf_header.first_slot * self.header.attr_length)
             };
         }
         Ok((to_delete, this_next_key))
     }
 
-    /**
+    /*
      * Delete from buckets.
      * 
      * Parameters:
@@ -893,7 +2322,11 @@ impl IndexHandle {
         let mut to_delete = false;
         let mut next_next_bucket = NO_MORE_PAGES;
 
-        let bucket_header = utils::get_header_mut::<BucketHeader>(bucket_ph.get_data());
+        let bucket_header = unsafe {
+
+            utils::get_header_mut::<BucketHeader>(bucket_ph.get_data())
+
+        };
         let bucket_entries = self.get_bucket_entries(bucket_ph.get_data());
 
         //if there's a next bucket, search in it first.
@@ -906,10 +2339,12 @@ impl IndexHandle {
                     found = false;
                 },
                 Err(e) => {
+                    let _ = self.pfh.unpin_page(next_bucket_ph.get_page_num());
                     return Err(e);
                 },
                 Ok(v) => {
                     if !v.0 {//if to_delete is false, means the entry is found before the next bucket. No matter if that bucket is deleted or not, all job should be done in the next bucket. We don't do anything about it.
+                        error_return!(self.pfh.unpin_dirty_page(next_bucket_ph.get_page_num()), IndexingError::UnpinPageError);
                         return Ok(v);
                     }
                     //(to_delete, next_next_bucket) = v;
@@ -921,7 +2356,9 @@ impl IndexHandle {
             error_return!(self.pfh.unpin_dirty_page(next_bucket_ph.get_page_num()), IndexingError::UnpinPageError);
 
             if found {
-                let next_bucket_header = utils::get_header_mut::<BucketHeader>(next_bucket_ph.get_data());
+                let next_bucket_header = unsafe {
+                    utils::get_header_mut::<BucketHeader>(next_bucket_ph.get_data())
+                };
                 
                 if to_delete && next_bucket_header.num_keys == 0 {
                     error_return!(self.pfh.dispose_page(next_bucket_ph.get_page_num()), IndexingError::DisposePageError);
@@ -973,47 +2410,92 @@ impl IndexHandle {
         Ok((to_delete, next_next_bucket))
     }
 
-    fn create_new_node(&mut self, is_leaf: &bool) -> Result<PageHandle, IndexingError> {
-        let new_ph = ok_or_return!(self.pfh.allocate_page(), IndexingError::AllocatePageError);
+    /*
+     * Initialize the root page as an empty leaf node.
+     * Only called when the index file is created.
+     */
+    pub fn init_root(&mut self) -> Result<(), IndexingError> {
+        let root_ph = ok_or_return!(self.pfh.get_page(self.root_page_num), IndexingError::GetPageError);
+        self.init_node(root_ph, true);
+        error_return!(self.pfh.unpin_dirty_page(self.root_page_num), IndexingError::UnpinPageError);
+        Ok(())
+    }
+
+    fn init_node(&self, ph: PageHandle, is_leaf: bool) {
+        ph.set_page_type(PAGE_TYPE_BTREE_NODE);
+        let data = ph.get_data();
         let new_nh = unsafe {
-            &mut *(new_ph.get_data() as *mut NodeHeader)
+            &mut *(data as *mut NodeHeader)
         };
         new_nh.is_empty = true;
-        new_nh.is_leaf = *is_leaf;
+        new_nh.is_leaf = is_leaf;
         new_nh.num_keys = 0;
         new_nh.free_slot = 0;
         new_nh.first_slot = NO_MORE_SLOTS;
         new_nh.num1 = 0;
         new_nh.num2 = 0;
         
-        let entries = self.get_node_entries(new_ph.get_data());
+        let entries = self.get_node_entries(data);
 
-        for i in 0..self.header.max_node_keys {
-            entries[i].et_type = EntryType::Unoccupied;
-            entries[i].page_num = 0;//0 is an invalid page num
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.et_type = EntryType::Unoccupied;
+            entry.page_num = 0;//0 is an invalid page num
             if i == self.header.max_node_keys - 1 {
-                entries[i].next_slot = NO_MORE_SLOTS;
+                entry.next_slot = NO_MORE_SLOTS;
             } else {
-                entries[i].next_slot = i+1;
+                entry.next_slot = i+1;
             }
         }
-        
-        match self.pfh.unpin_dirty_page(new_ph.get_page_num()) {
-            Err(e) => {
-                dbg!(&e);
-                Err(IndexingError::UnpinPageError)
-            },
-            Ok(_) => Ok(new_ph)
+    }
+
+    /*
+     * The new node is returned pinned, so its data stays valid while
+     * the caller fills it. Callers are responsible for unpinning it.
+     */
+    fn create_new_node(&mut self, is_leaf: &bool) -> Result<PageHandle, IndexingError> {
+        let new_ph = ok_or_return!(self.pfh.allocate_page(), IndexingError::AllocatePageError);
+        self.init_node(new_ph, *is_leaf);
+        Ok(new_ph)
+    }
+
+    //check if a rid is in any bucket of the chain starting at bucket_page_num.
+    fn contains_duplicate_rid(&mut self, bucket_page_num: u32, rid: &RID) -> Result<bool, IndexingError> {
+        let mut page_num = bucket_page_num;
+        while page_num != NO_MORE_PAGES {
+            let bucket_ph = ok_or_return!(self.pfh.get_page(page_num), IndexingError::GetPageError);
+            let bucket_header = utils::get_header::<BucketHeader>(bucket_ph.get_data());
+            let bucket_entries = self.get_bucket_entries(bucket_ph.get_data());
+
+            let mut found = false;
+            let mut curr_index = bucket_header.first_slot;
+            while curr_index != NO_MORE_SLOTS {
+                let entry = &bucket_entries[curr_index];
+                if entry.page_num == rid.get_page_num() && entry.slot_num == rid.get_slot_num() {
+                    found = true;
+                    break;
+                }
+                curr_index = entry.next_slot;
+            }
+
+            let next_bucket = bucket_header.next_bucket;
+            error_return!(self.pfh.unpin_page(page_num), IndexingError::UnpinPageError);
+            if found {
+                return Ok(true);
+            }
+            page_num = next_bucket;
         }
+        Ok(false)
     }
 
     /*
      * Every time a duplicate entry appears, a new page is allocated.
      * And all rids associated with these duplicate entries are stored in this page.
      * If one page is full, allocate another one.
+     * The new bucket is returned pinned, like create_new_node.
      */
     fn create_new_bucket(&mut self) -> Result<PageHandle, IndexingError> {
         let new_ph = ok_or_return!(self.pfh.allocate_page(), IndexingError::AllocatePageError);
+        new_ph.set_page_type(PAGE_TYPE_BUCKET);
         let new_bh = unsafe {
             &mut *(new_ph.get_data() as *mut BucketHeader)
         };
@@ -1025,22 +2507,15 @@ impl IndexHandle {
 
         let entries = self.get_bucket_entries(new_ph.get_data());
 
-        for i in 0..(self.header.max_bucket_keys) {
-            entries[i].page_num = 0;
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.page_num = 0;
             if i == self.header.max_bucket_keys - 1 {
-                entries[i].next_slot = NO_MORE_SLOTS;
+                entry.next_slot = NO_MORE_SLOTS;
             } else {
-                entries[i].next_slot = i+1;
+                entry.next_slot = i+1;
             }
         }
-
-        match self.pfh.unpin_dirty_page(new_ph.get_page_num()) {
-            Err(e) => {
-                dbg!(e);
-                Err(IndexingError::UnpinPageError)
-            },
-            Ok(_) => Ok(new_ph)
-        }
+        Ok(new_ph)
     }
 
     /*
@@ -1056,7 +2531,7 @@ impl IndexHandle {
     fn find_node_insert_index(&mut self, val: *mut u8, node_data: *mut u8) -> Result<(usize, bool), IndexingError> {
         let node_entries = self.get_node_entries(node_data);
         let keys = unsafe {
-            node_data.offset(self.header.keys_offset as isize)
+            node_data.add(self.header.keys_offset)
         };
         let node_header = unsafe {
             &mut *(node_data as *mut NodeHeader)
@@ -1070,9 +2545,9 @@ impl IndexHandle {
 
         while curr_index != NO_MORE_SLOTS {
             ptr = unsafe {
-                keys.offset((self.header.attr_length * curr_index) as isize)
+                keys.add(self.header.attr_length * curr_index)
             };
-            match Self::compare(val, ptr, self.header.attr_type, self.header.attr_length) {
+            match self.header.compare_keys(val, ptr) {
                 Ordering::Greater => {},
                 Ordering::Less => {
                     break;
@@ -1087,6 +2562,74 @@ impl IndexHandle {
         Ok((prev_index, is_dup))
     }
 
+    //slots of all entries of a node, in key order.
+    fn get_slots(&self, data: *mut u8) -> Vec<usize> {
+        let header = utils::get_header::<NodeHeader>(data);
+        let entries = self.get_node_entries(data);
+        let mut slots = Vec::with_capacity(header.num_keys);
+        let mut curr_index = header.first_slot;
+        while curr_index != NO_MORE_SLOTS {
+            slots.push(curr_index);
+            curr_index = entries[curr_index].next_slot;
+        }
+        slots
+    }
+
+    /*
+     * Insert an entry and its key into a node after prev_index, 
+     * BEGINNING_OF_SLOT to insert as the first entry.
+     * Returns the slot of the new entry.
+     */
+    fn link_node_entry(&self, data: *mut u8, prev_index: usize, entry: NodeEntry, key: *mut u8) -> usize {
+        let header = unsafe {
+            utils::get_header_mut::<NodeHeader>(data)
+        };
+        let entries = self.get_node_entries(data);
+
+        let slot = header.free_slot;
+        header.free_slot = entries[slot].next_slot;
+        entries[slot] = entry;
+        unsafe {
+            std::ptr::copy(key, self.get_key(data, slot), self.header.attr_length);
+        }
+
+        if prev_index == BEGINNING_OF_SLOT {
+            entries[slot].next_slot = header.first_slot;
+            header.first_slot = slot;
+        } else {
+            entries[slot].next_slot = entries[prev_index].next_slot;
+            entries[prev_index].next_slot = slot;
+        }
+        header.num_keys += 1;
+        header.is_empty = false;
+        slot
+    }
+
+    //remove the entry at slot from a node, prev_index is the slot before it.
+    fn unlink_node_entry(&self, data: *mut u8, prev_index: usize, slot: usize) {
+        let header = unsafe {
+            utils::get_header_mut::<NodeHeader>(data)
+        };
+        let entries = self.get_node_entries(data);
+
+        let next_slot = entries[slot].next_slot;
+        if prev_index == BEGINNING_OF_SLOT {
+            header.first_slot = next_slot;
+        } else {
+            entries[prev_index].next_slot = next_slot;
+        }
+        entries[slot].et_type = EntryType::Unoccupied;
+        entries[slot].next_slot = header.free_slot;
+        header.free_slot = slot;
+        header.num_keys -= 1;
+    }
+
+    fn get_key(&self, data: *mut u8, slot: usize) -> *mut u8 {
+        unsafe {
+            data.add(self.header.keys_offset + slot * self.header.attr_length)
+        }
+    }
+
     fn find_prev_index(entries: &[NodeEntry], start: usize, target: usize) -> Result<usize, IndexingError> {
         let mut prev_index = start;
         
@@ -1101,7 +2644,8 @@ impl IndexHandle {
         Err(IndexingError::EntriesBroken)
     }
 
-    fn compare(val1: *mut u8, val2: *mut u8, attr_type: AttrType, len: usize) -> Ordering {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn compare(val1: *mut u8, val2: *mut u8, attr_type: AttrType, len: usize) -> Ordering {
         match attr_type {
             AttrType::INT => {
                 let v1 = unsafe {
@@ -1127,14 +2671,33 @@ impl IndexHandle {
                     Ordering::Greater
                 }
             },
+            AttrType::BIGINT => {
+                let v1 = unsafe {
+                    & *(val1 as *mut i64)
+                };
+                let v2 = unsafe {
+                    & *(val2 as *mut i64)
+                };
+                v1.cmp(v2)
+            },
+            AttrType::DOUBLE => {
+                let v1 = unsafe {
+                    & *(val1 as *mut f64)
+                };
+                let v2 = unsafe {
+                    & *(val2 as *mut f64)
+                };
+                v1.total_cmp(v2)
+            },
             AttrType::STRING => {
+                //strings are null-padded to len, compare them as bytes.
                 let v1 = unsafe {
-                    std::mem::ManuallyDrop::new(String::from_raw_parts(val1, len, len))
+                    std::slice::from_raw_parts(val1, len)
                 };
                 let v2 = unsafe {
-                    std::mem::ManuallyDrop::new(String::from_raw_parts(val2, len, len))
+                    std::slice::from_raw_parts(val2, len)
                 };
-                v1.cmp(&v2)
+                v1.cmp(v2)
             }
         }
     }
@@ -1147,3 +2710,366 @@ impl IndexHandle {
         utils::get_arr_mut::<BucketEntry>(data, self.header.bucket_entries_offset, self.header.max_bucket_keys)
     }
 }
+
+impl Drop for IndexHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("close index file error");
+            dbg!(&e);
+        }
+    }
+}
+
+impl<'a> IndexRangeScan<'a> {
+    //stop the scan, following calls of next return None.
+    fn finish(&mut self) {
+        self.current_leaf_num = NO_MORE_PAGES;
+        self.pending.clear();
+    }
+}
+
+impl<'a> Iterator for IndexRangeScan<'a> {
+    type Item = Result<RID, IndexingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(rid) = self.pending.pop() {
+            return Some(Ok(rid));
+        }
+
+        while self.current_leaf_num != NO_MORE_PAGES {
+            let ih = &mut *self.ih;
+            let leaf_num = self.current_leaf_num;
+            let leaf_ph = match ih.pfh.get_page(leaf_num) {
+                Err(e) => {
+                    dbg!(&e);
+                    self.finish();
+                    return Some(Err(IndexingError::GetPageError));
+                },
+                Ok(v) => v
+            };
+            let leaf_header = utils::get_header::<LeafHeader>(leaf_ph.get_data());
+            let leaf_entries = ih.get_node_entries(leaf_ph.get_data());
+
+            let slot = if self.current_slot == BEGINNING_OF_SLOT { leaf_header.first_slot } else { self.current_slot };
+            if slot == NO_MORE_SLOTS {
+                self.current_leaf_num = leaf_header.next_page;
+                self.current_slot = BEGINNING_OF_SLOT;
+                if let Err(e) = ih.pfh.unpin_page(leaf_num) {
+                    dbg!(&e);
+                    self.finish();
+                    return Some(Err(IndexingError::UnpinPageError));
+                }
+                continue;
+            }
+
+            let in_range = self.upper.is_empty() || match ih.header.compare_keys(ih.get_key(leaf_ph.get_data(), slot), self.upper.as_ptr() as *mut u8) {
+                Ordering::Less => true,
+                Ordering::Equal => self.upper_inclusive,
+                Ordering::Greater => false
+            };
+            let entry = leaf_entries[slot];
+            self.current_slot = entry.next_slot;
+
+            if let Err(e) = ih.pfh.unpin_page(leaf_num) {
+                dbg!(&e);
+                self.finish();
+                return Some(Err(IndexingError::UnpinPageError));
+            }
+
+            if !in_range {
+                self.finish();
+                return None;
+            }
+
+            match entry.et_type {
+                EntryType::Unoccupied => {
+                    dbg!(&entry);
+                    self.finish();
+                    return Some(Err(IndexingError::UnoccupiedEntry));
+                },
+                EntryType::New => {
+                    return Some(Ok(RID::new(entry.page_num, entry.slot_num)));
+                },
+                EntryType::Duplicate => {
+                    match ih.get_bucket_rids(entry.page_num) {
+                        Err(e) => {
+                            self.finish();
+                            return Some(Err(e));
+                        },
+                        Ok(mut rids) => {
+                            //RIDs are popped from the end.
+                            rids.reverse();
+                            self.pending = rids;
+                            if let Some(rid) = self.pending.pop() {
+                                return Some(Ok(rid));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::file_manager::IndexFileManager;
+    use crate::test_utils::make_test_pfm;
+
+    //(page_num, prev_page, next_page) of each leaf, from the leftmost one.
+    fn leaf_chain(ih: &mut IndexHandle) -> Vec<(u32, u32, u32)> {
+        let mut chain = Vec::new();
+        let mut page_num = ih.leftmost_leaf().unwrap();
+        while page_num != NO_MORE_PAGES {
+            let ph = ih.pfh.get_page(page_num).unwrap();
+            let leaf_header = *utils::get_header::<LeafHeader>(ph.get_data());
+            ih.pfh.unpin_page(page_num).unwrap();
+            chain.push((page_num, leaf_header.prev_page, leaf_header.next_page));
+            page_num = leaf_header.next_page;
+        }
+        chain
+    }
+
+    //every first_child of an internal node is a live node page.
+    fn check_first_children(ih: &mut IndexHandle) {
+        let mut stack = vec![ih.root_page_num];
+        while let Some(page_num) = stack.pop() {
+            let ph = ih.pfh.get_page(page_num).unwrap();
+            let header = *utils::get_header::<InternalHeader>(ph.get_data());
+            if !header.is_leaf {
+                stack.push(header.first_child);
+                let entries = ih.get_node_entries(ph.get_data());
+                let mut curr_index = header.first_slot;
+                while curr_index != NO_MORE_SLOTS {
+                    stack.push(entries[curr_index].page_num);
+                    curr_index = entries[curr_index].next_slot;
+                }
+            }
+            ih.pfh.unpin_page(page_num).unwrap();
+            if header.is_leaf {
+                continue;
+            }
+
+            assert!(!ih.pfh.page_is_disposed(header.first_child).unwrap(), "first child {} of {} disposed", header.first_child, page_num);
+            let child_ph = ih.pfh.get_page(header.first_child).unwrap();
+            assert_eq!(child_ph.get_page_type(), PAGE_TYPE_BTREE_NODE, "first child {} of {}", header.first_child, page_num);
+            ih.pfh.unpin_page(header.first_child).unwrap();
+        }
+    }
+
+    /*
+     * split the 3rd of 4 leaves, the new leaf is linked between the 3rd
+     * and the 4th, and the 4th leaf is left with the pin count it had.
+     */
+    #[test]
+    fn split_node_leaf_links_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("split_leaf_links_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+        //keys are 1000 apart, so there is room to insert into any leaf.
+        let mut n: i32 = 0;
+        while ih.num_leaf_pages().unwrap() < 4 {
+            let mut key = n * 1000;
+            ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, n as usize)).expect("insert entry failed");
+            n += 1;
+        }
+        let chain = leaf_chain(&mut ih);
+        assert_eq!(chain.len(), 4);
+        let (third, fourth) = (chain[2].0, chain[3].0);
+        let fourth_pin_count = ih.pfh.pin_count_of(fourth).unwrap_or(0);
+
+        let ph = ih.pfh.get_page(third).unwrap();
+        let first_slot = utils::get_header::<LeafHeader>(ph.get_data()).first_slot;
+        let mut key = unsafe {
+            *(ih.get_key(ph.get_data(), first_slot) as *const i32)
+        };
+        ih.pfh.unpin_page(third).unwrap();
+
+        //keys right after the first key of the 3rd leaf all go into it.
+        let mut slot_num = 0;
+        while ih.num_leaf_pages().unwrap() < 5 {
+            key += 1;
+            ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(2, slot_num)).expect("insert entry failed");
+            slot_num += 1;
+        }
+
+        let chain = leaf_chain(&mut ih);
+        assert_eq!(chain.len(), 5);
+        assert_eq!(chain[2].0, third);
+        assert_eq!(chain[4].0, fourth);
+        for (i, &(_, prev_page, next_page)) in chain.iter().enumerate() {
+            let expected_prev = if i == 0 { NO_MORE_PAGES } else { chain[i-1].0 };
+            let expected_next = if i == chain.len()-1 { NO_MORE_PAGES } else { chain[i+1].0 };
+            assert_eq!(prev_page, expected_prev);
+            assert_eq!(next_page, expected_next);
+        }
+        assert_eq!(ih.pfh.pin_count_of(fourth).unwrap_or(0), fourth_pin_count);
+        ih.validate_leaf_chain().expect("invalid leaf chain");
+    }
+
+    /*
+     * deleting from the head empties the first child of nodes over and over,
+     * the first child of a node is never disposed, it takes in its right
+     * sibling instead, so first_child keeps pointing to a valid page.
+     */
+    #[test]
+    fn delete_first_child_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("delete_first_child_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+        let n: i32 = 12000;
+        for i in 0..n {
+            let mut key = i;
+            ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+        }
+        assert_eq!(ih.tree_height().unwrap(), 3);
+        check_first_children(&mut ih);
+
+        let deleted: i32 = 10000;
+        let mut min_key = [0u8; 4];
+        for i in 0..deleted {
+            let mut key = i;
+            ih.delete_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("delete entry failed");
+            if i % 500 == 0 {
+                ih.validate_leaf_chain().expect("invalid leaf chain");
+                check_first_children(&mut ih);
+                assert!(ih.min_key(&mut min_key).unwrap());
+                assert_eq!(i32::from_ne_bytes(min_key), i+1);
+            }
+        }
+
+        ih.validate_leaf_chain().expect("invalid leaf chain");
+        check_first_children(&mut ih);
+        assert_eq!(ih.entry_count(), (n-deleted) as usize);
+        assert_eq!(ih.sequential_scan().unwrap().count(), (n-deleted) as usize);
+        let rids: Vec<RID> = ih.scan_range(&deleted.to_ne_bytes(), true, &(deleted+9).to_ne_bytes(), true).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(rids.len(), 10);
+        for key in [0, deleted/2, deleted-1] {
+            assert!(!ih.key_exists(&key as *const i32 as *const u8).unwrap());
+        }
+        for key in [deleted, n-1] {
+            assert!(ih.key_exists(&key as *const i32 as *const u8).unwrap());
+        }
+    }
+
+    //a failed deletion leaves no page pinned, so later merges can still dispose pages.
+    #[test]
+    fn failed_delete_then_drain_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("failed_delete_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::STRING, 255).expect("create index failed");
+        let make_key = |i: usize| {
+            let mut key = vec![0u8; 255];
+            let digits = format!("{:04}", i);
+            key[..digits.len()].copy_from_slice(digits.as_bytes());
+            key
+        };
+
+        let n = 60;
+        for i in 0..n {
+            ih.insert_entry(make_key(i).as_mut_ptr(), &RID::new(1, i)).expect("insert entry failed");
+        }
+        assert!(ih.tree_height().unwrap() > 1);
+        //a live key with a wrong rid, and a missing key.
+        assert!(ih.delete_entry(make_key(n-1).as_mut_ptr(), &RID::new(9, 9)).is_err());
+        assert!(ih.delete_entry(make_key(n).as_mut_ptr(), &RID::new(1, n)).is_err());
+        assert_eq!(ih.entry_count(), n);
+
+        for i in 0..n {
+            ih.delete_entry(make_key(i).as_mut_ptr(), &RID::new(1, i)).expect("delete entry failed");
+            assert_eq!(ih.sequential_scan().unwrap().count(), ih.entry_count());
+        }
+        assert_eq!(ih.entry_count(), 0);
+        assert_eq!(ih.tree_height().unwrap(), 1);
+        assert_eq!(ih.pfh.pin_count_of(ih.root_page_num), Some(0));
+    }
+
+    //one rid more than a bucket holds spills into a second bucket.
+    #[test]
+    fn bucket_overflow_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("bucket_overflow_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+        let max_bucket_keys = ih.header.max_bucket_keys;
+        let mut key: i32 = 5;
+        for i in 0..=max_bucket_keys {
+            ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("insert entry failed");
+        }
+
+        //the root is the only leaf, and it has a single entry for the key.
+        let root_ph = ih.pfh.get_page(ih.root_page_num).unwrap();
+        let root_header = utils::get_header::<NodeHeader>(root_ph.get_data());
+        assert_eq!(root_header.num_keys, 1);
+        let entry = ih.get_node_entries(root_ph.get_data())[root_header.first_slot];
+        ih.pfh.unpin_page(ih.root_page_num).unwrap();
+        assert!(matches!(entry.et_type, EntryType::Duplicate));
+
+        let mut bucket_sizes = Vec::new();
+        let mut page_num = entry.page_num;
+        while page_num != NO_MORE_PAGES {
+            let ph = ih.pfh.get_page(page_num).unwrap();
+            let bucket_header = *utils::get_header::<BucketHeader>(ph.get_data());
+            bucket_sizes.push(bucket_header.num_keys);
+            ih.pfh.unpin_page(page_num).unwrap();
+            page_num = bucket_header.next_bucket;
+        }
+        assert_eq!(bucket_sizes, vec![max_bucket_keys, 1]);
+
+        let rids: Vec<RID> = ih.scan_range(&key.to_ne_bytes(), true, &key.to_ne_bytes(), true).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(rids.len(), max_bucket_keys + 1);
+    }
+
+    //inserting a (key, rid) pair twice fails, whether the key has a bucket or not.
+    #[test]
+    fn duplicate_rid_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("duplicate_rid_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+        let mut key: i32 = 5;
+        let key_ptr = &mut key as *mut i32 as *mut u8;
+        ih.insert_entry(key_ptr, &RID::new(1, 0)).expect("insert entry failed");
+        assert!(matches!(ih.insert_entry(key_ptr, &RID::new(1, 0)), Err(Error::Indexing(IndexingError::DuplicateRid))));
+
+        ih.insert_entry(key_ptr, &RID::new(1, 1)).expect("insert entry failed");
+        assert!(matches!(ih.insert_entry(key_ptr, &RID::new(1, 0)), Err(Error::Indexing(IndexingError::DuplicateRid))));
+        assert!(matches!(ih.insert_entry(key_ptr, &RID::new(1, 1)), Err(Error::Indexing(IndexingError::DuplicateRid))));
+
+        assert_eq!(ih.entry_count(), 2);
+        assert_eq!(ih.get_all_rids_for_key(key_ptr).unwrap().len(), 2);
+    }
+
+    //the root is not left pinned by insertions and deletions, including those that replace it.
+    #[test]
+    fn root_unpinned_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("root_unpinned_index").to_str().unwrap().to_string();
+        let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+        let first_root = ih.root_page_num;
+
+        let n = ih.header.max_node_keys + 1;
+        for i in 0..n {
+            let mut key = i as i32;
+            ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("insert entry failed");
+            assert_eq!(ih.pfh.pin_count_of(ih.root_page_num), Some(0));
+        }
+        assert_ne!(ih.root_page_num, first_root);
+        assert_eq!(ih.pfh.pin_count_of(first_root), Some(0));
+
+        for i in 0..n {
+            let mut key = i as i32;
+            ih.delete_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("delete entry failed");
+            assert_eq!(ih.pfh.pin_count_of(ih.root_page_num), Some(0));
+        }
+        assert_eq!(ih.entry_count(), 0);
+    }
+}
@@ -26,10 +26,18 @@ static MAX_STRING_LEN: usize = 255;
 pub enum AttrType {
     INT,
     FLOAT,
-    STRING
+    STRING,
+    BIGINT,//i64
+    DOUBLE,//f64
+}
+
+impl AttrType {
+    //datetime values are stored as i64 timestamps.
+    pub const DATETIME: AttrType = AttrType::BIGINT;
 }
 
 //pub mod file_manager;
+pub mod composite_key;
 pub mod index_handle;
 pub mod file_manager;
 
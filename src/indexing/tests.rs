@@ -9,58 +9,51 @@
 
 use crate::page_management::page_file;
 use crate::record_management::{record_file_handle, record_file_manager};
-use std::io;
-use std::io::prelude::*;
+use crate::test_utils::{make_test_pfm, make_test_pfm_with_buffer_size};
 
-const record_size: usize = 128;
+const RECORD_SIZE: usize = 128;
 
-fn init() -> (page_file::PageFileManager, record_file_handle::RecordFileHandle) {
-    let mut pfm = page_file::PageFileManager::new();
-    let mut rfh = record_file_manager::RecordFileManager::create_file(&String::from("Table2"), &mut pfm, record_size).expect("Create RecordFileManager failed");
-    (pfm, rfh)
+#[allow(dead_code)]
+fn init() -> (page_file::PageFileManager, record_file_handle::RecordFileHandle, tempfile::TempDir) {
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("Table2").to_str().unwrap().to_string();
+    let rfh = record_file_manager::RecordFileManager::create_file(&file_name, &mut pfm, RECORD_SIZE).expect("Create RecordFileManager failed");
+    (pfm, rfh, dir)
 }
 
 
-fn get_data() -> *mut u8 {
-    use std::fs::OpenOptions;
-    let mut fp = OpenOptions::new().read(true).write(false).open("/home/lunar/Documents/w").expect("Open file failed");
-    let buffer = crate::utils::allocate_buffer(record_size);
-    let sli = unsafe {
-        std::slice::from_raw_parts_mut(buffer, record_size)
-    };
-    let res = fp.read(sli);
-    if let Err(e) = res {
-        panic!("read error");
-    }
-    let read_bytes = res.unwrap();
-    if read_bytes < record_size {
-        panic!("Incomplete read");
+fn get_data() -> Vec<u8> {
+    let mut buffer = crate::utils::allocate_buffer_vec(RECORD_SIZE);
+    for (i, b) in buffer.iter_mut().enumerate() {
+        *b = i as u8;
     }
     buffer
 }
 
 
-fn records_insertion(pfm: &mut page_file::PageFileManager, rfh: &mut record_file_handle::RecordFileHandle) {
-    let data = get_data();
+#[allow(dead_code)]
+fn records_insertion(_pfm: &mut page_file::PageFileManager, rfh: &mut record_file_handle::RecordFileHandle) {
+    let mut data = get_data();
     use crate::record_management::record_file_handle::RID;
 
     let mut recs: Vec<RID> = Vec::new();
 
     for i in 0..40 {
-        match rfh.insert_record(data) {
+        match rfh.insert_record(data.as_mut_ptr()) {
             Ok(v) => {
                 dbg!(v);
                 recs.push(v);
             },
             Err(e) => {
                 dbg!(e);
-                panic!(format!("Insert {}th record error!", i));
+                panic!("Insert {}th record error!", i);
             }
         }
     }
 }
 
 //#[test]
+#[allow(dead_code)]
 fn indexing_test1() {
     let v = init();
     let mut pfh = v.0;
@@ -71,23 +64,466 @@ fn indexing_test1() {
 
 #[test]
 fn index_handle_test1() {
-    let mut pfm = page_file::PageFileManager::new();
-    let mut rfh = record_file_manager::RecordFileManager::create_file(&String::from("Table1"), &mut pfm, record_size).expect("create rfh failed");
-    let data = get_data();
+    let _ = env_logger::try_init();
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("index_handle_table").to_str().unwrap().to_string();
+    let mut rfh = record_file_manager::RecordFileManager::create_file(&file_name, &mut pfm, RECORD_SIZE).expect("create rfh failed");
+    let mut data = get_data();
     use crate::record_management::record_file_handle::RID;
 
     let mut recs: Vec<RID> = Vec::new();
 
     for i in 0..40 {
-        match rfh.insert_record(data) {
+        match rfh.insert_record(data.as_mut_ptr()) {
             Ok(v) => {
                 dbg!(v);
                 recs.push(v);
             },
             Err(e) => {
                 dbg!(e);
-                panic!(format!("Insert {}th record error!", i));
+                panic!("Insert {}th record error!", i);
             }
         }
     }
+
+    //every record is indexed, 4 keys so most of them go to buckets.
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+    for (i, rid) in recs.iter().enumerate() {
+        let mut key = (i % 4) as i32;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, rid).expect("insert entry failed");
+    }
+    for rid in recs.iter() {
+        assert!(ih.find_entry_by_rid(rid).expect("find entry failed"));
+    }
+    assert!(!ih.find_entry_by_rid(&RID::new(recs[0].get_page_num(), 1000)).expect("find entry failed"));
+    let key: i32 = 0;
+    assert_eq!(ih.validate_bucket_chain_for_key(&key as *const i32 as *const u8).expect("invalid bucket chain"), 1);
+    //keys with their RIDs in buckets exist as well.
+    for key in 0..4i32 {
+        assert!(ih.key_exists(&key as *const i32 as *const u8).expect("key_exists failed"));
+    }
+    let key: i32 = 4;
+    assert!(!ih.key_exists(&key as *const i32 as *const u8).expect("key_exists failed"));
+    drop(rfh);
+}
+
+#[test]
+fn bigint_keys_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("bigint_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::BIGINT, 8).expect("create index failed");
+
+    let n: i64 = 100;
+    for i in 0..n {
+        //keys beyond the range of i32, with negative ones.
+        let mut key = ((i * 7) % n - n / 2) << 33;
+        ih.insert_entry(&mut key as *mut i64 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+    }
+
+    let mut out = [0u8; 8];
+    assert!(ih.min_key(&mut out).unwrap());
+    assert_eq!(i64::from_ne_bytes(out), (-n / 2) << 33);
+    assert!(ih.max_key(&mut out).unwrap());
+    assert_eq!(i64::from_ne_bytes(out), (n / 2 - 1) << 33);
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+
+    let key: i64 = 3 << 33;
+    assert!(ih.key_exists(&key as *const i64 as *const u8).unwrap());
+    let key: i64 = (3 << 33) + 1;
+    assert!(!ih.key_exists(&key as *const i64 as *const u8).unwrap());
+}
+
+#[test]
+fn double_keys_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("double_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::DOUBLE, 8).expect("create index failed");
+
+    let n: i32 = 100;
+    for i in 0..n {
+        let mut key = ((i * 7) % n - n / 2) as f64 / 4.0;
+        ih.insert_entry(&mut key as *mut f64 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+    }
+
+    let mut out = [0u8; 8];
+    assert!(ih.min_key(&mut out).unwrap());
+    assert_eq!(f64::from_ne_bytes(out), (-n / 2) as f64 / 4.0);
+    assert!(ih.max_key(&mut out).unwrap());
+    assert_eq!(f64::from_ne_bytes(out), (n / 2 - 1) as f64 / 4.0);
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+
+    let lower: f64 = -1.0;
+    let upper: f64 = 1.0;
+    let rids: Vec<RID> = ih.scan_range(&lower.to_ne_bytes(), true, &upper.to_ne_bytes(), false).unwrap().map(|r| r.unwrap()).collect();
+    assert_eq!(rids.len(), 8);
+}
+
+#[test]
+fn string_keys_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("string_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::STRING, 5).expect("create index failed");
+
+    let mut abd = *b"abd\0\0";
+    let mut abc = *b"abc\0\0";
+    ih.insert_entry(abd.as_mut_ptr(), &RID::new(1, 0)).expect("insert entry failed");
+    ih.insert_entry(abc.as_mut_ptr(), &RID::new(1, 1)).expect("insert entry failed");
+
+    let mut out = [0u8; 5];
+    assert!(ih.min_key(&mut out).unwrap());
+    assert_eq!(&out, b"abc\0\0");
+    assert!(ih.max_key(&mut out).unwrap());
+    assert_eq!(&out, b"abd\0\0");
+
+    let rids: Vec<RID> = ih.scan_range(b"abc\0\0", true, b"abd\0\0", true).unwrap().map(|r| r.unwrap()).collect();
+    assert_eq!(rids, vec![RID::new(1, 1), RID::new(1, 0)]);
+}
+
+/*
+ * With a 3-page buffer pool, pages of nodes just split off get evicted
+ * as soon as they are unpinned, so a node used after being unpinned 
+ * would be caught here.
+ * 10 leaves worth of keys are inserted so the root and the leaves split.
+ */
+#[test]
+fn small_buffer_split_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm_with_buffer_size(3);
+    let file_name = dir.path().join("small_buffer_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n: i32 = 1440;
+    for i in 0..n {
+        let mut key = (i * 7) % n;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+    }
+
+    assert_eq!(ih.entry_count(), n as usize);
+    assert!(ih.tree_height().unwrap() > 1);
+    let num_leaves = ih.validate_leaf_chain().expect("invalid leaf chain");
+    assert_eq!(ih.num_leaf_pages().unwrap(), num_leaves);
+    assert_eq!(ih.gather_stats().unwrap().num_leaf_pages, num_leaves);
+    for key in [0, 1, n / 2, n - 1].iter() {
+        assert!(ih.key_exists(key as *const i32 as *const u8).unwrap());
+    }
+}
+
+#[test]
+fn root_collapse_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("root_collapse_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n: i32 = 1000;
+    for i in 0..n {
+        let mut key = i;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+    }
+    assert_eq!(ih.tree_height().unwrap(), 2);
+
+    //deleting from the tail leaves few enough keys to fit in a single leaf.
+    for i in (10..n).rev() {
+        let mut key = i;
+        ih.delete_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("delete entry failed");
+    }
+
+    assert_eq!(ih.tree_height().unwrap(), 1);
+    assert_eq!(ih.gather_stats().unwrap().num_internal_pages, 0);
+    assert_eq!(ih.validate_leaf_chain().unwrap(), 1);
+    assert_eq!(ih.num_leaf_pages().unwrap(), 1);
+    assert_eq!(ih.entry_count(), 10);
+    for key in 0..10 {
+        assert!(ih.key_exists(&key as *const i32 as *const u8).unwrap());
+    }
+}
+
+//the root is pinned only during an operation, so it may be evicted between operations through a tiny buffer pool and is fetched again.
+#[test]
+fn tiny_buffer_pool_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm_with_buffer_size(2);
+    let file_name = dir.path().join("tiny_buffer_pool_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n = 2000;
+    for i in 0..n {
+        let mut key = (i * 7919 % n) as i32;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("insert entry failed");
+    }
+    assert!(ih.gather_stats().unwrap().tree_height > 1);
+    for i in 0..n/2 {
+        let mut key = (i * 7919 % n) as i32;
+        ih.delete_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("delete entry failed");
+    }
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+    assert_eq!(ih.sequential_scan().unwrap().count(), n/2);
+}
+
+//keys are ordered by the INT part first, which bytes in little endian don't follow.
+#[test]
+fn composite_key_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::indexing::composite_key::{CompositeKey, compare_composite};
+    use crate::errors::{Error, IndexingError};
+    use crate::types::RID;
+    use std::cmp::Ordering;
+
+    let key = CompositeKey::new(&[(AttrType::INT, 4), (AttrType::STRING, 8)]);
+    assert_eq!(key.total_length, 12);
+    let make_key = |a: i32, name: &str| {
+        let mut buf = vec![0u8; 12];
+        buf[..4].copy_from_slice(&a.to_ne_bytes());
+        buf[4..4+name.len()].copy_from_slice(name.as_bytes());
+        buf
+    };
+    assert_eq!(compare_composite(make_key(256, "a").as_ptr(), make_key(1, "b").as_ptr(), &key), Ordering::Greater);
+    assert_eq!(compare_composite(make_key(1, "a").as_ptr(), make_key(1, "b").as_ptr(), &key), Ordering::Less);
+    assert_eq!(compare_composite(make_key(-1, "b").as_ptr(), make_key(-1, "b").as_ptr(), &key), Ordering::Equal);
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("composite_key_index").to_str().unwrap().to_string();
+    let bad_key = CompositeKey::new(&[(AttrType::INT, 3)]);
+    assert!(matches!(IndexFileManager::create_file_composite(&file_name, 1, &mut pfm, &bad_key), Err(IndexingError::InvalidAttr)));
+
+    let mut ih = IndexFileManager::create_file_composite(&file_name, 0, &mut pfm, &key).expect("create index failed");
+    let n = 400;
+    let values: Vec<(i32, String)> = (0..n).map(|i| ((i * 37 % 100) as i32 - 50, format!("{:08}", i))).collect();
+    for (i, (a, name)) in values.iter().enumerate() {
+        ih.insert_entry_composite(&[&a.to_ne_bytes(), name.as_bytes()], &RID::new(1, i)).expect("insert entry failed");
+    }
+    assert!(matches!(ih.insert_entry_composite(&[&1i32.to_ne_bytes()], &RID::new(1, n)), Err(Error::Indexing(IndexingError::InvalidKeyParts))));
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+
+    let mut expected: Vec<usize> = (0..n).collect();
+    expected.sort_by(|&i, &j| values[i].cmp(&values[j]));
+    let scanned: Vec<usize> = ih.sequential_scan().unwrap().map(|rid| rid.unwrap().get_slot_num()).collect();
+    assert_eq!(scanned, expected);
+
+    //the key parts are kept in the file header.
+    drop(ih);
+    let mut ih = IndexFileManager::open_file(&file_name, 0, &mut pfm, AttrType::STRING, 12).expect("open index failed");
+    let stored = ih.get_composite_key().expect("no composite key");
+    assert_eq!(stored.parts.len(), 2);
+    assert_eq!(stored.parts[1].offset, 4);
+    for (i, (a, name)) in values.iter().enumerate().filter(|(i, _)| i % 2 == 0) {
+        ih.delete_entry_composite(&[&a.to_ne_bytes(), name.as_bytes()], &RID::new(1, i)).expect("delete entry failed");
+    }
+    assert_eq!(ih.sequential_scan().unwrap().count(), n / 2);
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+}
+
+/*
+ * 2500 keys with two rids each fill 18 leaves under a single root,
+ * the second rid of each key goes to a bucket of the key.
+ * The loaded tree takes insertions like any other, and a tree with
+ * entries can't be loaded again.
+ */
+#[test]
+fn bulk_load_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::errors::IndexingError;
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("bulk_load_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n: i32 = 2500;
+    let keys: Vec<i32> = (0..n).collect();
+    let mut entries = Vec::new();
+    for key in keys.iter() {
+        for i in 0..2 {
+            entries.push((key as *const i32 as *const u8, RID::new(1, (key * 2 + i) as usize)));
+        }
+    }
+    ih.bulk_load(&entries).expect("bulk load failed");
+
+    assert_eq!(ih.tree_height().unwrap(), 2);
+    assert_eq!(ih.validate_leaf_chain().unwrap(), 18);
+    assert_eq!(ih.num_leaf_pages().unwrap(), 18);
+    assert_eq!(ih.entry_count(), 2 * n as usize);
+    for key in [0, 1, n / 2, n - 1].iter() {
+        assert_eq!(ih.validate_bucket_chain_for_key(key as *const i32 as *const u8).unwrap(), 1);
+    }
+    //rids of a key were made from the key, so a sorted scan has them in order.
+    let scanned: Vec<usize> = ih.sequential_scan().unwrap().map(|r| r.unwrap().get_slot_num() / 2).collect();
+    assert_eq!(scanned.len(), 2 * n as usize);
+    assert!(scanned.windows(2).all(|w| w[0] <= w[1]));
+
+    let mut key = n;
+    ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(2, 0)).expect("insert entry failed");
+    let mut key = n / 2;
+    ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(2, 1)).expect("insert entry failed");
+    assert_eq!(ih.entry_count(), 2 * n as usize + 2);
+    assert!(ih.key_exists(&n as *const i32 as *const u8).unwrap());
+    ih.validate_leaf_chain().expect("invalid leaf chain");
+    assert_eq!(ih.sequential_scan().unwrap().count(), 2 * n as usize + 2);
+
+    assert!(matches!(ih.bulk_load(&entries), Err(IndexingError::NonEmptyIndex)));
+    assert_eq!(ih.entry_count(), 2 * n as usize + 2);
+}
+
+/*
+ * The range crosses most leaves and keys with duplicates, so bucket
+ * chains are disposed and the tree shrinks back to a root leaf.
+ */
+#[test]
+fn delete_range_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::errors::IndexingError;
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("delete_range_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n: i32 = 2000;
+    for i in 0..n {
+        let mut key = i;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i as usize)).expect("insert entry failed");
+    }
+    //keys 500 to 509 get two more rids each.
+    for i in 0..20 {
+        let mut key = 500 + i / 2;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(2, i as usize)).expect("insert entry failed");
+    }
+    assert_eq!(ih.tree_height().unwrap(), 2);
+    assert_eq!(ih.gather_stats().unwrap().num_bucket_pages, 10);
+
+    //an empty range deletes nothing.
+    let (lower, upper) = (10i32, 5i32);
+    assert_eq!(ih.delete_range(&lower as *const i32 as *const u8, &upper as *const i32 as *const u8).unwrap(), 0);
+    assert_eq!(ih.entry_count(), n as usize + 20);
+
+    let (lower, upper) = (50i32, 1999i32);
+    assert_eq!(ih.delete_range(&lower as *const i32 as *const u8, &upper as *const i32 as *const u8).unwrap(), 1950 + 20);
+
+    assert_eq!(ih.tree_height().unwrap(), 1);
+    assert_eq!(ih.validate_leaf_chain().unwrap(), 1);
+    assert_eq!(ih.entry_count(), 50);
+    assert_eq!(ih.sequential_scan().unwrap().count(), 50);
+    let stats = ih.gather_stats().unwrap();
+    assert_eq!(stats.num_bucket_pages, 0);
+    assert_eq!(stats.num_internal_pages, 0);
+    for key in [0, 49].iter() {
+        assert!(ih.key_exists(key as *const i32 as *const u8).unwrap());
+    }
+    for key in [50, 505, 1999].iter() {
+        assert!(!ih.key_exists(key as *const i32 as *const u8).unwrap());
+    }
+    let key = 505i32;
+    assert!(matches!(ih.validate_bucket_chain_for_key(&key as *const i32 as *const u8), Err(IndexingError::KeyNotFound)));
+}
+
+//sparse buckets left by deletions are packed into as few pages as the rids need.
+#[test]
+fn defragment_buckets_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("defragment_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+
+    let n = 2000;
+    let mut key = 7i32;
+    for i in 0..n {
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("insert entry failed");
+    }
+    let mut other = 8i32;
+    ih.insert_entry(&mut other as *mut i32 as *mut u8, &RID::new(2, 0)).expect("insert entry failed");
+    //a key without buckets frees nothing.
+    assert_eq!(ih.defragment_buckets(&other as *const i32 as *const u8).unwrap(), 0);
+
+    //every tenth rid is kept.
+    for i in (0..n).filter(|i| i % 10 != 0) {
+        ih.delete_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("delete entry failed");
+    }
+    let before = ih.validate_bucket_chain_for_key(&key as *const i32 as *const u8).unwrap();
+
+    let freed = ih.defragment_buckets(&key as *const i32 as *const u8).unwrap();
+    let after = ih.validate_bucket_chain_for_key(&key as *const i32 as *const u8).unwrap();
+    assert!(freed > 0);
+    assert_eq!(freed, before - after);
+    assert_eq!(ih.gather_stats().unwrap().num_bucket_pages, after);
+    assert_eq!(ih.defragment_buckets(&key as *const i32 as *const u8).unwrap(), 0);
+
+    assert_eq!(ih.entry_count(), n / 10 + 1);
+    let mut slots: Vec<usize> = ih.sequential_scan().unwrap()
+        .map(|r| r.unwrap())
+        .filter(|rid| rid.get_page_num() == 1)
+        .map(|rid| rid.get_slot_num())
+        .collect();
+    slots.sort_unstable();
+    assert_eq!(slots, (0..n).step_by(10).collect::<Vec<usize>>());
+}
+
+/*
+ * Page nums are fixed in a new manager: the first index is file 1,
+ * its root leaf is page 1 and page 2 is the bucket of key 2.
+ * 15 long string keys overflow the root leaf of the second index in
+ * file 2, which splits into pages 1 and 3 under a new root at page 2.
+ */
+#[test]
+fn print_tree_test() {
+    let _ = env_logger::try_init();
+    use crate::indexing::{AttrType, file_manager::IndexFileManager};
+    use crate::types::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("print_tree_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::INT, 4).expect("create index failed");
+    for (i, k) in [3i32, 1, 2, 2].iter().enumerate() {
+        let mut key = *k;
+        ih.insert_entry(&mut key as *mut i32 as *mut u8, &RID::new(1, i)).expect("insert entry failed");
+    }
+    assert_eq!(ih.print_tree().unwrap(), concat!(
+        "leaf 0x00010001: (1, (page=0x00000001, slot=1)) (2, (page=0x00000001, slot=2)) ",
+        "(2, (page=0x00000001, slot=3)) (3, (page=0x00000001, slot=0))\n"
+    ));
+
+    let file_name = dir.path().join("print_tree_string_index").to_str().unwrap().to_string();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::STRING, 255).expect("create index failed");
+    for i in 0..15 {
+        let mut key = [0u8; 255];
+        key[..2].copy_from_slice(format!("{:02}", i).as_bytes());
+        ih.insert_entry(key.as_mut_ptr(), &RID::new(1, i)).expect("insert entry failed");
+    }
+    assert_eq!(ih.tree_height().unwrap(), 2);
+    assert_eq!(ih.print_tree().unwrap(), concat!(
+        "internal 0x00020002: [07]\n",
+        "  leaf 0x00020001: (00, (page=0x00000001, slot=0)) (01, (page=0x00000001, slot=1)) ",
+        "(02, (page=0x00000001, slot=2)) (03, (page=0x00000001, slot=3)) (04, (page=0x00000001, slot=4)) ",
+        "(05, (page=0x00000001, slot=5)) (06, (page=0x00000001, slot=6))\n",
+        "  leaf 0x00020003: (07, (page=0x00000001, slot=7)) (08, (page=0x00000001, slot=8)) ",
+        "(09, (page=0x00000001, slot=9)) (10, (page=0x00000001, slot=10)) (11, (page=0x00000001, slot=11)) ",
+        "(12, (page=0x00000001, slot=12)) (13, (page=0x00000001, slot=13)) (14, (page=0x00000001, slot=14))\n"
+    ));
 }
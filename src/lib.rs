@@ -10,8 +10,14 @@
 pub mod errors;
 pub mod page_management;
 pub mod utils;
+pub mod types;
 pub mod record_management;
 pub mod indexing;
 pub mod macros;
+pub mod wal;
+pub mod lock_manager;
 
-//pub mod database_manager;
+pub mod database_manager;
+
+#[cfg(test)]
+pub mod test_utils;
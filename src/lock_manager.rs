@@ -0,0 +1,172 @@
+/*
+ * Page level latches.
+ * A page can be latched by any number of readers in shared mode,
+ * or by one writer in exclusive mode.
+ *
+ * Latches are not waited for, a conflicting request returns
+ * LockError::Conflict immediately, and the caller decides whether
+ * to retry.
+ *
+ * A latch is released when it's dropped, so a latch never outlives
+ * the operation that takes it.
+ *
+ * All handles of a file share its lock table as a SharedLockManager,
+ * latches of the table are taken by LockManager::acquire_shared and
+ * LockManager::acquire_exclusive.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::errors::LockError;
+use crate::types::PageNum;
+
+pub type SharedLockManager = Rc<RefCell<LockManager>>;
+
+#[derive(Debug, Default)]
+pub struct LockEntry {
+    shared_count: u32,
+    has_exclusive: bool
+}
+
+#[derive(Debug, Default)]
+pub struct LockManager {
+    table: HashMap<PageNum, LockEntry>
+}
+
+/*
+ * A latch of a shared lock table. The table is kept by Rc and only 
+ * borrowed to take and release the latch, so other handles of the 
+ * file can take latches while it's held.
+ */
+#[derive(Debug)]
+pub struct Latch {
+    lm: SharedLockManager,
+    page_num: PageNum,
+    exclusive: bool
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self {
+            table: HashMap::new()
+        }
+    }
+
+    /*
+     * Latches keep the table by Rc rather than by reference, so any
+     * number of them can be held at once.
+     */
+    pub fn acquire_shared(lm: &SharedLockManager, page_num: PageNum) -> Result<Latch, LockError> {
+        lm.borrow_mut().lock(page_num, false)?;
+        Ok(Latch {
+            lm: Rc::clone(lm),
+            page_num,
+            exclusive: false
+        })
+    }
+
+    pub fn acquire_exclusive(lm: &SharedLockManager, page_num: PageNum) -> Result<Latch, LockError> {
+        lm.borrow_mut().lock(page_num, true)?;
+        Ok(Latch {
+            lm: Rc::clone(lm),
+            page_num,
+            exclusive: true
+        })
+    }
+
+    fn lock(&mut self, page_num: PageNum, exclusive: bool) -> Result<(), LockError> {
+        let entry = self.table.entry(page_num).or_default();
+        if entry.has_exclusive || (exclusive && entry.shared_count > 0) {
+            return Err(LockError::Conflict);
+        }
+        if exclusive {
+            entry.has_exclusive = true;
+        } else {
+            entry.shared_count += 1;
+        }
+        Ok(())
+    }
+
+    pub fn is_locked(&self, page_num: PageNum) -> bool {
+        self.table.contains_key(&page_num)
+    }
+
+    //entries are removed once no latch is held on the page.
+    fn release(&mut self, page_num: PageNum, exclusive: bool) {
+        let entry = match self.table.get_mut(&page_num) {
+            None => return,
+            Some(v) => v
+        };
+        if exclusive {
+            entry.has_exclusive = false;
+        } else {
+            entry.shared_count -= 1;
+        }
+        if entry.shared_count == 0 && !entry.has_exclusive {
+            self.table.remove(&page_num);
+        }
+    }
+}
+
+impl Latch {
+    pub fn get_page_num(&self) -> PageNum {
+        self.page_num
+    }
+}
+
+impl Drop for Latch {
+    fn drop(&mut self) {
+        self.lm.borrow_mut().release(self.page_num, self.exclusive);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LockManager, SharedLockManager};
+    use crate::errors::LockError;
+    use std::rc::Rc;
+
+    #[test]
+    fn lock_manager_test() {
+        let lm = SharedLockManager::default();
+        {
+            let _latch = LockManager::acquire_exclusive(&lm, 1).unwrap();
+            assert!(lm.borrow().is_locked(1));
+        }
+        assert!(!lm.borrow().is_locked(1));
+
+        //shared latches of a page are held together, and keep out an exclusive one until all are released.
+        let r1 = LockManager::acquire_shared(&lm, 1).unwrap();
+        let r2 = LockManager::acquire_shared(&lm, 1).unwrap();
+        assert!(matches!(LockManager::acquire_exclusive(&lm, 1), Err(LockError::Conflict)));
+        drop(r1);
+        assert!(matches!(LockManager::acquire_exclusive(&lm, 1), Err(LockError::Conflict)));
+        drop(r2);
+        assert!(!lm.borrow().is_locked(1));
+
+        let w = LockManager::acquire_exclusive(&lm, 2).unwrap();
+        assert!(matches!(LockManager::acquire_shared(&lm, 2), Err(LockError::Conflict)));
+        assert!(matches!(LockManager::acquire_exclusive(&lm, 2), Err(LockError::Conflict)));
+        drop(w);
+        assert!(LockManager::acquire_shared(&lm, 2).is_ok());
+    }
+
+    //latches taken through different clones of a shared table conflict.
+    #[test]
+    fn shared_lock_manager_test() {
+        let lm1 = SharedLockManager::default();
+        let lm2 = Rc::clone(&lm1);
+        {
+            let latch = LockManager::acquire_exclusive(&lm1, 1).unwrap();
+            assert_eq!(latch.get_page_num(), 1);
+            assert!(matches!(LockManager::acquire_shared(&lm2, 1), Err(LockError::Conflict)));
+            assert!(LockManager::acquire_exclusive(&lm2, 2).is_ok());
+        }
+        assert!(!lm2.borrow().is_locked(1));
+
+        let _r1 = LockManager::acquire_shared(&lm1, 1).unwrap();
+        let _r2 = LockManager::acquire_shared(&lm2, 1).unwrap();
+        assert!(matches!(LockManager::acquire_exclusive(&lm2, 1), Err(LockError::Conflict)));
+    }
+}
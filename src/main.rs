@@ -1,5 +1,2 @@
-use std::fs::OpenOptions;
-use rust::page_management::buffer_manager;
-
 fn main() {
 }
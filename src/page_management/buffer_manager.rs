@@ -9,16 +9,16 @@
 
 use std::fs::File;
 use std::collections::HashMap;
-use std::os::unix::fs::FileExt;
 use std::ptr::{self, NonNull};
-use std::mem::size_of;
+use std::mem::{align_of, size_of};
 use std::alloc::{self, Layout};
 
 use crate::errors::PageFileError;
+use crate::utils;
 use super::page_file::{self, PageHeader};
+use super::storage::StorageBackend;
 
-//use std::{println as debug, println as info, println as error};
-use crate::{info, debug, error};
+use log::{debug, info, warn};
 /*
  * Memory and References.
  * Let me explain how I resolve memory passing between functions
@@ -43,6 +43,17 @@ use crate::{info, debug, error};
  * is different from it in a file.
  */
 
+/*
+ * A buffer page is either in the free list, pinned by someone, 
+ * or in the unused list waiting to be reused.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferPageState {
+    Free,
+    Pinned,
+    Unpinned
+}
+
 #[derive(Debug)]
 pub struct BufferPage {
     pub data: *mut u8,//data including page header, bitmap, and the records data.
@@ -52,9 +63,16 @@ pub struct BufferPage {
     dirty: bool,
     pin_count: u32,
     page_num: u32,
+    state: BufferPageState,
     fp: Option<File>
 }
 
+impl Default for BufferPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BufferPage {
     pub fn new() -> Self {
         BufferPage {
@@ -64,6 +82,7 @@ impl BufferPage {
             dirty: false,
             pin_count: 0,
             page_num: 0, //o is an invalid page number, so we use it for page initialization.
+            state: BufferPageState::Free,
             fp: None
         }
     }
@@ -76,14 +95,8 @@ impl BufferPage {
             dirty: self.dirty,
             pin_count: self.pin_count,
             page_num: self.page_num,
-            fp: {
-                match &self.fp {
-                    None => None,
-                    Some(v) => {
-                        Some(v.try_clone().unwrap())
-                    }
-                }
-            }
+            state: self.state,
+            fp: self.fp.as_ref().map(|v| v.try_clone().unwrap())
         }
     }
 }
@@ -109,6 +122,27 @@ impl BufferPage {
  *
  * It is important not to leave pages in memory unnecessarily.
  */
+/*
+ * Tunable parameters of a buffer pool.
+ * dirty_flush_threshold is the ratio (0.0-1.0) of dirty pages in the 
+ * buffer above which unpinned dirty pages are written back ahead of 
+ * eviction.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct BufferConfig {
+    pub num_pages: usize,
+    pub dirty_flush_threshold: f32
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            num_pages: 128,
+            dirty_flush_threshold: 0.75
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BufferManager {
     num_pages: u32, //number of pages in the buffer pool, free pages not included.
@@ -120,7 +154,9 @@ pub struct BufferManager {
      * in their data structure.*/
     free: i32,
     buffer_table: Vec<NonNull<BufferPage>>, 
-    page_table: HashMap<u32, usize> //we need this table to get a page quickly.
+    page_table: HashMap<u32, usize>, //we need this table to get a page quickly.
+    num_dirty: usize, //number of dirty pages, kept up to date whenever a dirty flag changes.
+    dirty_flush_threshold: f32
 }
 
 impl std::fmt::Debug for BufferManager {
@@ -133,13 +169,23 @@ impl std::fmt::Debug for BufferManager {
             .field("last", &self.last)
             .field("free", &self.free)
             .field("page_table", &self.page_table)
+            .field("num_dirty", &self.num_dirty)
+            .field("dirty_flush_threshold", &self.dirty_flush_threshold)
             .finish()
     }
 }
 
 impl BufferManager {
     pub fn new(num_pages: usize) -> Self {
-        println!("Initializing Buffer Manager.");
+        Self::with_config(BufferConfig {
+            num_pages,
+            ..BufferConfig::default()
+        })
+    }
+
+    pub fn with_config(config: BufferConfig) -> Self {
+        let num_pages = config.num_pages;
+        info!("Initializing Buffer Manager.");
         BufferManager {
             buffer_table: {
                 //let mut v = vec![NonNull::new(Box::into_raw(Box::new(BufferPage::new()))).unwrap(); 128];
@@ -159,7 +205,9 @@ impl BufferManager {
             first: -1,
             last: -1,
             free: 0,
-            page_table: HashMap::new()
+            page_table: HashMap::new(),
+            num_dirty: 0,
+            dirty_flush_threshold: config.dirty_flush_threshold
         }
     }
 
@@ -167,19 +215,18 @@ impl BufferManager {
         self.page_size
     }
 
+    /*
+     * Double the buffer pool when all pages are pinned.
+     * Each new slot gets its own BufferPage, and the new slots are
+     * linked into the free list starting at the old length.
+     */
     fn resize_buffer(&mut self) {
-        let cap = self.buffer_table.capacity() as u32;
+        let cap = self.buffer_table.len() as u32;
         if self.num_pages < cap {
             debug!("No need to resize buffer");
             return ;
         }
-        dbg!((cap<<1) as usize);
-        let mut new_table: Vec<NonNull<BufferPage>> = Vec::with_capacity((cap<<1) as usize);
-        debug!("Not crashed yet.");
-        for i in 0..cap {
-            new_table.push(self.buffer_table[i as usize]);
-        }
-        self.buffer_table = new_table;
+        self.buffer_table.reserve_exact(cap as usize);
         info!("Buffer pool new capacity: {}", self.buffer_table.capacity());
         //link all free pages.
         let start = cap as i32;
@@ -194,16 +241,16 @@ impl BufferManager {
     }
 
     fn get_page_offset(&self, index: usize) -> u64 {
-        (size_of::<page_file::PageFileHeader>() + index * self.page_size) as u64
+        (page_file::PAGE_FILE_HEADER_SIZE + index * self.page_size) as u64
     }
 
     /*
      * read page header and data.
      * page_num indicates the location of the page in a file.
      * index indicates the index of the BufferPage at the buffer_table.
-     * fp: file pointer of the file to read from.
+     * backend: the storage to read from, usually the file of the page.
      */
-    fn read_page(&mut self, page_num: u32, index: usize, fp: &File) -> Result<(), PageFileError> {
+    fn read_page(&mut self, page_num: u32, index: usize, backend: &mut dyn StorageBackend) -> Result<(), PageFileError> {
         let file_page_index = (page_num & 0x0000ffff) as usize;
         let buffer_page = unsafe {
             &mut *self.buffer_table[index].as_ptr()
@@ -215,15 +262,9 @@ impl BufferManager {
         debug!("page data allocated ");
 
         let sli = unsafe {
-            std::slice::from_raw_parts_mut(buffer_page.data, self.page_size)
+            utils::page_data_slice(buffer_page.data, 0, self.page_size)
         };
-        let res = fp.read_at(sli, self.get_page_offset(file_page_index));
-
-        if let Err(_) = res {
-            return Err(PageFileError::ReadAtError);
-        }
-        
-        let read_bytes = res.unwrap();
+        let read_bytes = backend.read_page(self.get_page_offset(file_page_index), sli)?;
         if read_bytes < self.page_size {
             return Err(PageFileError::IncompleteRead);
         }
@@ -245,16 +286,11 @@ impl BufferManager {
      * possibility that there are more than 1<<16 pages in one file.
      * In this way, we can make sure each page number is identical.
      */
-    fn write_page(&self, page_num: u32, index: usize) -> Result<(), PageFileError> {
+    fn write_page(&self, page_num: u32, index: usize, backend: &mut dyn StorageBackend) -> Result<(), PageFileError> {
         let file_page_index = (page_num & 0x0000ffff) as usize;
         let buffer_page = unsafe {
             &mut *self.buffer_table[index].as_ptr()
         };
-        
-        if let None = buffer_page.fp {
-            return Err(PageFileError::NoFilePointer);
-        }
-        let fp = buffer_page.fp.as_ref().unwrap();
 
         if buffer_page.data.is_null() {
             return Err(PageFileError::DataUnintialized);
@@ -263,27 +299,40 @@ impl BufferManager {
         let sli = unsafe {
             std::slice::from_raw_parts(buffer_page.data, self.page_size)
         };
-        let res = fp.write_at(sli, self.get_page_offset(file_page_index));
-
-        if let Err(v) = res {
-            dbg!(v);
-            return Err(PageFileError::WriteAtError);
-        }
-
-        let write_bytes = res.unwrap();
+        let write_bytes = backend.write_page(self.get_page_offset(file_page_index), sli)?;
         if write_bytes < self.page_size {
             return Err(PageFileError::IncompleteWrite);
         }
-
         Ok(())
     }
 
+    //write a page back to the file it's read from.
+    fn write_back(&mut self, index: usize) -> Result<(), PageFileError> {
+        let buffer_page = unsafe {
+            &mut *self.buffer_table[index].as_ptr()
+        };
+        let mut fp = match buffer_page.fp.take() {
+            None => {
+                return Err(PageFileError::NoFilePointer);
+            },
+            Some(v) => v
+        };
+        let res = self.write_page(buffer_page.page_num, index, &mut fp);
+        buffer_page.fp = Some(fp);
+        //the page on disk is up to date now.
+        if res.is_ok() && buffer_page.dirty {
+            buffer_page.dirty = false;
+            self.num_dirty -= 1;
+        }
+        res
+    }
+
     /*
      * Free a page in buffer, the page must be unpinned.
      * Method needed when there is no free page.
      */
     fn free_page(&mut self, index: usize) -> Result<(), PageFileError> {
-        if index > self.buffer_table.len() {
+        if index >= self.buffer_table.len() {
             return Err(PageFileError::OutOfIndex);
         }
         let page = unsafe {
@@ -292,17 +341,11 @@ impl BufferManager {
         if page.pin_count != 0 {
             return Err(PageFileError::PagePinned);
         }
-        if (self.first as usize) != index && page.prev == 0 {
-            //means the page is in the free list.
+        if page.state == BufferPageState::Free {
             return Err(PageFileError::PageFreed);
         }
         if page.dirty {
-            let res = self.write_page(page.page_num, index);
-            if let Ok(()) = res {
-                
-            } else {
-                return res;
-            }
+            self.write_back(index)?;
         }
         self.unlink(index);
         let page = unsafe {
@@ -315,6 +358,7 @@ impl BufferManager {
         //link the page to the free list.
         page.next = self.free;
         page.prev = -1;
+        page.state = BufferPageState::Free;
         page.fp = None;
         self.free = index as i32;
         self.num_pages -= 1;
@@ -349,18 +393,34 @@ impl BufferManager {
             &mut *self.buffer_table[index].as_ptr()
         };
         page.next = self.first;
+        page.prev = -1;
         if self.first != -1 {
             unsafe {
                 self.buffer_table[self.first as usize].as_mut().prev = index as i32;
             }
         }
         self.first = index as i32;
-        dbg!(&self.first);
         if self.last == -1 {
             self.last = index as i32;
-            dbg!(&self.last);
         }
-        //dbg!(page.clone_metadata());
+    }
+
+    //link a page at the least recently used end, so it's the first to be evicted.
+    fn link_tail(&mut self, index: usize) {
+        let page = unsafe {
+            &mut *self.buffer_table[index].as_ptr()
+        };
+        page.prev = self.last;
+        page.next = -1;
+        if self.last != -1 {
+            unsafe {
+                self.buffer_table[self.last as usize].as_mut().next = index as i32;
+            }
+        }
+        self.last = index as i32;
+        if self.first == -1 {
+            self.first = index as i32;
+        }
     }
 
     /*
@@ -369,56 +429,40 @@ impl BufferManager {
      * If the page is already pinned, then increase pin count by
      * 1 and return.
      * If it is in the unused list, then unlink it from the 
-     * unused list.
+     * unused list. It goes back to the head of the list when
+     * it's unpinned, so a page hit moves to the most recently
+     * used end and the tail stays the least recently used page.
      */
     fn update_page(&mut self, index: usize) {
-        let pin_count: u32;
-        let prev: i32;
-        let next: i32;
-        {
-            let page = unsafe {
-                self.buffer_table[index].as_ref()
-            };
-            pin_count = page.pin_count;
-            prev = page.prev;
-            next = page.next;
-        }
-        if pin_count > 1 {
+        let state = unsafe {
+            self.buffer_table[index].as_ref().state
+        };
+        if state == BufferPageState::Pinned {
             unsafe {
                 (*self.buffer_table[index].as_ptr()).pin_count += 1;
             }
             return ;
         }
         //remove the page from the unused list.
-        if prev == -1 {
-            self.first = next;
-        } else {
-            unsafe {
-                (*self.buffer_table[prev as usize].as_ptr()).next = next;
-            }
-        }
-        if next == -1 {
-            self.last = prev;
-        } else {
-            unsafe {
-                (*self.buffer_table[next as usize].as_ptr()).prev = prev;
-            }
-        }
+        self.unlink(index);
         let page = unsafe {
             self.buffer_table[index].as_mut()
         };
         page.pin_count += 1;
         page.prev = -1;
         page.next = -1;
+        page.state = BufferPageState::Pinned;
     }
 
     fn internal_alloc(&mut self) -> Result<usize, PageFileError> {
-        dbg!(&self.free);
-        info!("Start to internal alloc");
         if self.free == -1 {
-            debug!("No free pages");
-            dbg!(&self.last);
-            match self.free_page(self.last as usize) {
+            debug!("No free pages, last unused page index = {}", self.last);
+            //all pages are pinned if the unused list is empty.
+            let res = match self.last {
+                -1 => Err(PageFileError::NoPage),
+                last => self.free_page(last as usize)
+            };
+            match res {
                 Ok(()) => {},
                 Err(PageFileError::NoPage) => {
                     debug!("resizing buffer");
@@ -432,7 +476,6 @@ impl BufferManager {
         let temp = self.free as usize;
         unsafe {
             self.free = self.buffer_table[temp].as_mut().next;
-            dbg!(&self.free);
         }
         self.num_pages += 1;
         Ok(temp)
@@ -464,8 +507,9 @@ impl BufferManager {
                 return Err(e);
             }
             let newpage_index = res.unwrap();
-            dbg!(&newpage_index);
-            match self.read_page(page_num, newpage_index, fp) {
+            //the page keeps its own file pointer for writing back.
+            let mut page_fp = fp.try_clone().unwrap();
+            match self.read_page(page_num, newpage_index, &mut page_fp) {
                 Ok(()) => {},
                 Err(e) => {
                     dbg!(&e);
@@ -477,10 +521,11 @@ impl BufferManager {
             let new_page = unsafe {&mut *self.buffer_table[newpage_index].as_ptr()};
             new_page.next = -1;
             new_page.pin_count = 1;
+            new_page.state = BufferPageState::Pinned;
             new_page.page_num = page_num;
-            new_page.fp = Some(fp.try_clone().unwrap());
+            new_page.fp = Some(page_fp);
             unsafe {
-                Ok(self.buffer_table[index].as_mut().data)
+                Ok(self.buffer_table[newpage_index].as_mut().data)
             }
         }
     }
@@ -497,11 +542,8 @@ impl BufferManager {
      * initialization work will be done when the page is used.
      */
     pub fn allocate_page(&mut self, page_num: u32, fp: &File) -> Result<*mut u8, PageFileError> {
-        info!("buffer allocate_page start!");
-        dbg!(&self.page_table);
-        if let Some(_) = self.page_table.get(&page_num) {
-            debug!("The page is in the buffer");
-            dbg!(page_num);
+        if self.page_table.contains_key(&page_num) {
+            debug!("The page with page_num={:#010x} is in the buffer", page_num);
         }
         let res = self.internal_alloc();
         if let Err(e) = res {
@@ -517,6 +559,7 @@ impl BufferManager {
         page.fp = Some(fp.try_clone().unwrap());
         page.pin_count = 1;
         page.next = -1;
+        page.state = BufferPageState::Pinned;
         
         if page.data.is_null() {
             page.data = Self::allocate_buffer(self.page_size);
@@ -535,15 +578,12 @@ impl BufferManager {
      * passed out either.
      */
     pub fn unpin(&mut self, page_num: u32) -> Result<(), PageFileError> {
-        let index: usize;
-        match self.page_table.get(&page_num) {
+        let index: usize = match self.page_table.get(&page_num) {
             None => {
                 return Err(PageFileError::PageNotInBuf);
             },
-            Some(v) => {
-                index = *v;
-            }
-        }
+            Some(v) => *v
+        };
         let page = unsafe {
             &mut *self.buffer_table[index].as_ptr()
         };
@@ -552,6 +592,7 @@ impl BufferManager {
         }
         page.pin_count -= 1;
         if page.pin_count == 0 {
+            page.state = BufferPageState::Unpinned;
             self.link(index);
         }
         Ok(())
@@ -560,7 +601,7 @@ impl BufferManager {
     pub fn mark_dirty(&mut self, page_num: u32) -> Result<(), PageFileError> {
         match self.page_table.get(&page_num) {
             None => {
-                Err(PageFileError::HashNotFound)
+                return Err(PageFileError::HashNotFound);
             },
             Some(v) => {
                 let bp = unsafe {
@@ -569,10 +610,168 @@ impl BufferManager {
                 if bp.pin_count == 0 {
                     return Err(PageFileError::PageFreed);
                 }
-                bp.dirty = true;
-                Ok(())
+                if !bp.dirty {
+                    bp.dirty = true;
+                    self.num_dirty += 1;
+                }
+            }
+        }
+        /*
+         * write back dirty pages before too many of them pile up.
+         * The page is marked dirty anyway, pages failed to be written
+         * back stay dirty, so the error is only logged.
+         */
+        if self.num_dirty as f32 / self.buffer_table.len() as f32 > self.dirty_flush_threshold {
+            if let Err(e) = self.checkpoint() {
+                warn!("checkpoint triggered by dirty pages failed: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
+    //pin count of a page, None if the page is not in the buffer.
+    pub fn pin_count_of(&self, page_num: u32) -> Option<u32> {
+        self.page_table.get(&page_num).map(|&index| unsafe {
+            self.buffer_table[index].as_ref().pin_count
+        })
+    }
+
+    /*
+     * page num of the buffer page a data pointer belongs to, None if
+     * the pointer is not the data of any page in use.
+     * Walks through the whole buffer table, only for debug builds.
+     */
+    #[cfg(debug_assertions)]
+    pub fn page_num_of(&self, data: *mut u8) -> Option<u32> {
+        self.buffer_table.iter()
+            .map(|page| unsafe { page.as_ref() })
+            .find(|page| page.state != BufferPageState::Free && page.data == data)
+            .map(|page| page.page_num)
+    }
+
+    pub fn dirty_page_count(&self) -> usize {
+        self.num_dirty
+    }
+
+    /*
+     * Write back dirty pages in the unused list, starting from the 
+     * least recently used one.
+     * Pinned pages are left alone, as they may be in the middle of 
+     * being modified.
+     * Returns the number of pages written back.
+     */
+    pub fn checkpoint(&mut self) -> Result<usize, PageFileError> {
+        let mut flushed = 0;
+        let mut index = self.last;
+        while index != -1 {
+            let page = unsafe {
+                &mut *self.buffer_table[index as usize].as_ptr()
+            };
+            if page.dirty {
+                self.write_back(index as usize)?;
+                flushed += 1;
             }
+            index = page.prev;
+        }
+        Ok(flushed)
+    }
+
+    /*
+     * Evict all pages that belong to a file from the buffer.
+     * The pages are not written back, as the file is about to be 
+     * removed. So all pages of the file must be unpinned, otherwise
+     * PagePinned is returned and no page is evicted.
+     *
+     * A page belongs to a file if the left 16 bits of its page 
+     * number equal to the file number.
+     */
+    pub fn evict_file_pages(&mut self, file_num: u16) -> Result<(), PageFileError> {
+        let indexes: Vec<usize> = self.page_table.iter()
+            .filter(|(page_num, _)| (*page_num >> 16) as u16 == file_num)
+            .map(|(_, index)| *index)
+            .collect();
+
+        for &index in indexes.iter() {
+            let page = unsafe {
+                self.buffer_table[index].as_ref()
+            };
+            if page.pin_count != 0 {
+                dbg!(page.page_num);
+                return Err(PageFileError::PagePinned);
+            }
+        }
+
+        for index in indexes {
+            self.evict(index);
+        }
+        Ok(())
+    }
+
+    /*
+     * Emergency cleanup for a file that is closed while some of its
+     * pages are still pinned, which can only be caused by a bug.
+     * These pages could never be evicted, so their pin counts are 
+     * dropped to 0 and they are linked back at the tail of the unused
+     * list, as they are the pages to evict next.
+     * Returns the number of pages forcefully unpinned.
+     */
+    pub fn unpin_all_for_file(&mut self, file_num: u16) -> usize {
+        let mut count = 0;
+        for index in 0..self.buffer_table.len() {
+            let page = unsafe {
+                &mut *self.buffer_table[index].as_ptr()
+            };
+            if page.pin_count == 0 || (page.page_num >> 16) as u16 != file_num {
+                continue;
+            }
+            warn!("page {} of file {} is still pinned {} times, force unpinning it", page.page_num, file_num, page.pin_count);
+            page.pin_count = 0;
+            page.state = BufferPageState::Unpinned;
+            self.link_tail(index);
+            count += 1;
+        }
+        count
+    }
+
+    /*
+     * Evict a single page from the buffer without writing it back,
+     * for a page that is about to be cut off from its file.
+     * It's fine if the page is not in the buffer.
+     */
+    pub fn evict_page(&mut self, page_num: u32) -> Result<(), PageFileError> {
+        let index = match self.page_table.get(&page_num) {
+            None => {
+                return Ok(());
+            },
+            Some(v) => *v
+        };
+        if unsafe { self.buffer_table[index].as_ref().pin_count } != 0 {
+            dbg!(page_num);
+            return Err(PageFileError::PagePinned);
         }
+        self.evict(index);
+        Ok(())
+    }
+
+    //move an unpinned page to the free list, its data is dropped.
+    fn evict(&mut self, index: usize) {
+        self.unlink(index);
+        let page = unsafe {
+            self.buffer_table[index].as_mut()
+        };
+        self.page_table.remove(&page.page_num);
+        if page.dirty {
+            page.dirty = false;
+            self.num_dirty -= 1;
+        }
+        page.page_num = 0;
+        //link the page to the free list.
+        page.next = self.free;
+        page.prev = -1;
+        page.state = BufferPageState::Free;
+        page.fp = None;
+        self.free = index as i32;
+        self.num_pages -= 1;
     }
 
     /*
@@ -585,16 +784,207 @@ impl BufferManager {
      * a file can be an appropriate method.
      */
     #[warn(unused_variables)]
-    pub fn flush_pages(&self, fp: &File) {
+    pub fn flush_pages(&self, _fp: &File) {
         
     }
 
+    //a frame starts with a PageHeader, so it's aligned as one.
     pub fn allocate_buffer(size: usize) -> *mut u8 {
-        let layout = Layout::from_size_align(size, size_of::<u8>()).expect("create layout error");
+        let layout = Layout::from_size_align(size, align_of::<PageHeader>()).expect("create layout error");
         unsafe {
             alloc::alloc(layout)
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{BufferManager, BufferConfig};
+    use crate::errors::PageFileError;
+    use crate::page_management::storage::MemoryBackend;
+    use crate::test_utils::create_test_file;
+
+    //pin every page in the pool, then one more page forces a resize.
+    #[test]
+    fn resize_buffer_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let initial_capacity = 4;
+        let mut bm = BufferManager::new(initial_capacity);
+
+        let file_num: u32 = 1<<16;
+        let mut pages = Vec::new();
+        for i in 0..=(initial_capacity as u32) {
+            let data = bm.allocate_page(file_num | i, fp).expect("allocate page failed");
+            pages.push(data);
+        }
+        assert_eq!(bm.buffer_table.len(), initial_capacity << 1);
+
+        //every pinned page keeps its own buffer.
+        for i in 0..pages.len() {
+            for j in (i+1)..pages.len() {
+                assert_ne!(pages[i], pages[j]);
+            }
+        }
+        for i in 0..=(initial_capacity as u32) {
+            bm.unpin(file_num | i).unwrap();
+        }
+    }
+    //dirtying more than half of the pool writes back the unpinned ones.
+    #[test]
+    fn dirty_flush_threshold_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let mut bm = BufferManager::with_config(BufferConfig {
+            num_pages: 4,
+            dirty_flush_threshold: 0.5
+        });
+
+        let file_num: u32 = 1<<16;
+        for i in 0..2 {
+            bm.allocate_page(file_num | i, fp).unwrap();
+            bm.mark_dirty(file_num | i).unwrap();
+            bm.unpin(file_num | i).unwrap();
+        }
+        assert_eq!(bm.dirty_page_count(), 2);
+
+        bm.allocate_page(file_num | 2, fp).unwrap();
+        bm.mark_dirty(file_num | 2).unwrap();
+        assert_eq!(bm.dirty_page_count(), 1);
+        assert_eq!(fp.metadata().unwrap().len() as usize, bm.get_page_offset(2) as usize);
+        //a page is counted once however many times it's marked.
+        bm.mark_dirty(file_num | 2).unwrap();
+        assert_eq!(bm.dirty_page_count(), 1);
+
+        bm.unpin(file_num | 2).unwrap();
+        assert_eq!(bm.checkpoint().unwrap(), 1);
+        assert_eq!(bm.dirty_page_count(), 0);
+
+        //evicted pages are dropped from the count.
+        bm.get_page(file_num | 1, fp).unwrap();
+        bm.mark_dirty(file_num | 1).unwrap();
+        bm.unpin(file_num | 1).unwrap();
+        assert_eq!(bm.dirty_page_count(), 1);
+        bm.evict_page(file_num | 1).unwrap();
+        assert_eq!(bm.dirty_page_count(), 0);
+    }
+
+    //a page written back is clean, so a second checkpoint writes nothing.
+    #[test]
+    fn checkpoint_clears_dirty_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let mut bm = BufferManager::new(4);
+
+        let page_num: u32 = (1<<16) | 1;
+        bm.allocate_page(page_num, fp).unwrap();
+        bm.mark_dirty(page_num).unwrap();
+        bm.unpin(page_num).unwrap();
+        assert_eq!(bm.dirty_page_count(), 1);
+
+        assert_eq!(bm.checkpoint().unwrap(), 1);
+        assert_eq!(bm.dirty_page_count(), 0);
+        assert_eq!(bm.checkpoint().unwrap(), 0);
+
+        //write_back alone clears the flag as well.
+        bm.get_page(page_num, fp).unwrap();
+        bm.mark_dirty(page_num).unwrap();
+        bm.write_back(bm.page_table[&page_num]).unwrap();
+        assert_eq!(bm.dirty_page_count(), 0);
+        bm.unpin(page_num).unwrap();
+    }
+
+    //access page A, then B, then A again, B becomes the least recently used.
+    #[test]
+    fn lru_order_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let mut bm = BufferManager::new(4);
+
+        let file_num: u32 = 1<<16;
+        let (page_a, page_b) = (file_num | 1, file_num | 2);
+        bm.allocate_page(page_a, fp).unwrap();
+        bm.unpin(page_a).unwrap();
+        bm.allocate_page(page_b, fp).unwrap();
+        bm.unpin(page_b).unwrap();
+        bm.get_page(page_a, fp).unwrap();
+        bm.unpin(page_a).unwrap();
+
+        assert_eq!(bm.first, bm.page_table[&page_a] as i32);
+        assert_eq!(bm.last, bm.page_table[&page_b] as i32);
+    }
+
+    //pages left pinned block evicting the file until they are force unpinned.
+    #[test]
+    fn unpin_all_for_file_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let mut bm = BufferManager::new(4);
+
+        let (file1, file2): (u32, u32) = (1<<16, 2<<16);
+        bm.allocate_page(file1 | 1, fp).unwrap();
+        bm.allocate_page(file1 | 2, fp).unwrap();
+        bm.get_page(file1 | 2, fp).unwrap();
+        bm.allocate_page(file2 | 1, fp).unwrap();
+        assert!(matches!(bm.evict_file_pages(1), Err(PageFileError::PagePinned)));
+
+        assert_eq!(bm.unpin_all_for_file(1), 2);
+        assert_eq!(bm.unpin_all_for_file(1), 0);
+        assert_eq!(bm.last, bm.page_table[&(file1 | 2)] as i32);
+        assert_eq!(bm.pin_count_of(file2 | 1), Some(1));
+        bm.evict_file_pages(1).unwrap();
+        assert_eq!(bm.pin_count_of(file1 | 1), None);
+        bm.unpin(file2 | 1).unwrap();
+    }
+
+    //pages go through a MemoryBackend without any file.
+    #[test]
+    fn memory_backend_test() {
+        let _ = env_logger::try_init();
+        let mut bm = BufferManager::new(2);
+        let mut mem = MemoryBackend::new(bm.get_pagesize());
+        let page_num: u32 = (1<<16) | 3;
+        let index = bm.internal_alloc().unwrap();
+
+        //nothing is written yet.
+        assert!(matches!(bm.read_page(page_num, index, &mut mem), Err(PageFileError::IncompleteRead)));
+
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(bm.buffer_table[index].as_ref().data, bm.get_pagesize())
+        };
+        data.iter_mut().for_each(|b| *b = 0xab);
+        bm.write_page(page_num, index, &mut mem).unwrap();
+        assert_eq!(mem.page_count(), 1);
+
+        data.iter_mut().for_each(|b| *b = 0);
+        bm.read_page(page_num, index, &mut mem).unwrap();
+        assert!(data.iter().all(|&b| b == 0xab));
+    }
 
+    #[cfg(debug_assertions)]
+    #[test]
+    fn page_num_of_test() {
+        let _ = env_logger::try_init();
+        let (tf, _) = create_test_file(0);
+        let fp = tf.as_file();
+        let mut bm = BufferManager::new(4);
+        let file_num: u32 = 1<<16;
+        let p1 = bm.allocate_page(file_num | 1, fp).unwrap();
+        let p2 = bm.allocate_page(file_num | 2, fp).unwrap();
+        assert_eq!(bm.page_num_of(p1), Some(file_num | 1));
+        assert_eq!(bm.page_num_of(p2), Some(file_num | 2));
+        let mut other = 0u8;
+        assert_eq!(bm.page_num_of(&mut other as *mut u8), None);
+
+        //an evicted page's buffer no longer belongs to it.
+        bm.unpin(file_num | 1).unwrap();
+        bm.evict_page(file_num | 1).unwrap();
+        assert_eq!(bm.page_num_of(p1), None);
+        bm.unpin(file_num | 2).unwrap();
+    }
+}
@@ -9,7 +9,8 @@
 
 pub mod buffer_manager;
 pub mod page_file;
+pub mod storage;
 
-//#[cfg(test)]
-//mod tests; 
+#[cfg(test)]
+mod tests; 
 
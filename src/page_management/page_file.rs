@@ -32,14 +32,14 @@
 
 use std::fs::{File, OpenOptions};
 use super::buffer_manager::BufferManager;
+use std::collections::HashMap;
 use std::os::unix::fs::FileExt;
 use std::mem::size_of;
-use std::ptr::NonNull;
-//use std::{println as info, println as debug, println as warn, println as error};
-use crate::{info, debug, error};
+use std::rc::Rc;
+use log::debug;
 
 use crate::errors::{Error, PageFileError};
-use super::buffer_manager::BufferPage;
+use crate::lock_manager::SharedLockManager;
 
 pub const PAGE_SIZE: usize = 4096;
 const BUFFER_SIZE: usize = 128;
@@ -76,10 +76,30 @@ const BUFFER_SIZE: usize = 128;
  * page to store PAGE_SIZE long data. And returns a pointer whenever a page num is 
  * provided.
  */
+/*
+ * page_type tells what a page is used for, so a reader can check 
+ * it's looking at the kind of page it expects.
+ * An allocated page is PAGE_TYPE_UNTYPED until the client sets its
+ * type, a disposed page goes back to PAGE_TYPE_FREE. So only pages 
+ * in the free list are PAGE_TYPE_FREE.
+ */
+pub const PAGE_TYPE_FREE: u8 = 0;
+pub const PAGE_TYPE_BTREE_NODE: u8 = 1;
+pub const PAGE_TYPE_BUCKET: u8 = 2;
+pub const PAGE_TYPE_RECORD: u8 = 3;
+pub const PAGE_TYPE_INDEX_HEADER: u8 = 4;
+pub const PAGE_TYPE_UNTYPED: u8 = 5;
+
+/*
+ * The header is aligned to 8 bytes so that the client data right 
+ * after it keeps the alignment of the page buffer.
+ */
 #[derive(Debug, Clone, Copy)]
+#[repr(C, align(8))]
 pub struct PageHeader {
     pub page_num: u32, //page number
     pub next_free: u32, //next_free is the page_num of the next free page. All free pages are linked together by this.
+    pub page_type: u8,
 }
 
 impl PageHeader {
@@ -87,6 +107,7 @@ impl PageHeader {
         Self {
             page_num,
             next_free: 0,
+            page_type: PAGE_TYPE_FREE,
         }
     }
 }
@@ -113,15 +134,39 @@ impl PageHandle {
         self.page_num
     }
 
+    /*
+     * The data returned starts right after the page header,
+     * so clients never overwrite the page header.
+     */
     pub fn get_data(&self) -> *mut u8 {
-        self.data
+        unsafe {
+            self.data.add(size_of::<PageHeader>())
+        }
+    }
+
+    pub fn get_page_type(&self) -> u8 {
+        unsafe {
+            (*(self.data as *const PageHeader)).page_type
+        }
+    }
+
+    pub fn set_page_type(&self, page_type: u8) {
+        unsafe {
+            (*(self.data as *mut PageHeader)).page_type = page_type;
+        }
     }
 }
 
 /*
  * PageFile layout:
  *  |PageFileHeader|pages|
+ *
+ * The header is stored in PAGE_FILE_HEADER_SIZE bytes, little-endian:
+ *  |file_num: u16|num_pages: u64|free: u32|
+ * so page files don't depend on the size of usize or struct padding.
  */
+pub const PAGE_FILE_HEADER_SIZE: usize = 14;
+
 #[derive(Debug, Clone, Copy)]
 pub struct PageFileHeader {
     file_num: u16,
@@ -137,6 +182,28 @@ impl PageFileHeader {
             free: 0,
         }
     }
+
+    pub fn to_bytes(h: &PageFileHeader) -> [u8; PAGE_FILE_HEADER_SIZE] {
+        let mut b = [0u8; PAGE_FILE_HEADER_SIZE];
+        b[0..2].copy_from_slice(&h.file_num.to_le_bytes());
+        b[2..10].copy_from_slice(&(h.num_pages as u64).to_le_bytes());
+        b[10..14].copy_from_slice(&h.free.to_le_bytes());
+        b
+    }
+
+    pub fn from_bytes(b: &[u8; PAGE_FILE_HEADER_SIZE]) -> PageFileHeader {
+        let mut file_num = [0u8; 2];
+        let mut num_pages = [0u8; 8];
+        let mut free = [0u8; 4];
+        file_num.copy_from_slice(&b[0..2]);
+        num_pages.copy_from_slice(&b[2..10]);
+        free.copy_from_slice(&b[10..14]);
+        PageFileHeader {
+            file_num: u16::from_le_bytes(file_num),
+            num_pages: u64::from_le_bytes(num_pages) as usize,
+            free: u32::from_le_bytes(free)
+        }
+    }
 }
 
 /*
@@ -160,16 +227,57 @@ impl PageFileHeader {
 #[derive(Debug)]
 pub struct PageFileManager {
     num_files: u16,//num_files is permenant, which means even after the database is closed. Next time it opens, num_files will still be the same. So num_files actually represent the number of all tables ever created. Even after tables are dropped later. Every time the database is opend, this data is read from a specific file.
-    buffer_manager: BufferManager//place where the only BufferManager get instaniated, every time a page file is opened, a reference to this instance is created and saved in the corresponding PageFileHandle.
+    buffer_manager: BufferManager,//place where the only BufferManager get instaniated, every time a page file is opened, a reference to this instance is created and saved in the corresponding PageFileHandle.
+    locks: HashMap<u16, SharedLockManager>//lock table of each file by file_num, handed to every handle of the file.
+}
+
+impl Default for PageFileManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PageFileManager {
     pub fn new() -> Self {
+        Self::with_num_files(1)
+    }
+
+    /*
+     * As num_files is permenant, the DatabaseManager reads it from
+     * the database metadata file and passes it in, so file numbers 
+     * assigned to new files won't collide with existing files.
+     */
+    pub fn with_num_files(start: u16) -> Self {
+        Self {
+            num_files: start,
+            buffer_manager: BufferManager::new(BUFFER_SIZE),
+            locks: HashMap::new()
+        }
+    }
+
+    //a manager with a buffer pool of num_pages pages, mostly for tests.
+    pub fn with_buffer_size(num_pages: usize) -> Self {
         Self {
             num_files: 1,
-            buffer_manager: BufferManager::new(BUFFER_SIZE)
+            buffer_manager: BufferManager::new(num_pages),
+            locks: HashMap::new()
         }
     }
+
+    //handles of the same file latch pages in the same lock table.
+    fn attach_locks(&mut self, mut pfh: PageFileHandle) -> PageFileHandle {
+        pfh.locks = Rc::clone(self.locks.entry(pfh.get_file_num()).or_default());
+        pfh
+    }
+
+    pub fn get_num_files(&self) -> u16 {
+        self.num_files
+    }
+
+    //write back dirty pages of all files that are not pinned.
+    pub fn checkpoint(&mut self) -> Result<usize, Error> {
+        Ok(self.buffer_manager.checkpoint()?)
+    }
     /*
      * create a page file.
      */
@@ -180,40 +288,117 @@ impl PageFileManager {
             free: 0
         };
         self.num_files += 1;
-        match OpenOptions::new().read(true).write(true).create(true).open(file_name) {
+        match OpenOptions::new().read(true).write(true).create(true).truncate(false).open(file_name) {
             Err(e) => {
                 dbg!(&e);
                 Err(Error::CreatePageFileError)
             },
             Ok(fp) => {
-                let sli = unsafe {
-                    std::slice::from_raw_parts(&file_header as *const _ as *const u8, size_of::<PageFileHeader>())
-                };
-                match fp.write_at(sli, 0) {
+                let sli = PageFileHeader::to_bytes(&file_header);
+                match fp.write_at(&sli, 0) {
                     Err(e) => {
                         dbg!(&e);
                         panic!("write at error");
                     },
                     Ok(write_bytes) => {
-                        if write_bytes < size_of::<PageFileHeader>() {
+                        if write_bytes < PAGE_FILE_HEADER_SIZE {
                             dbg!(write_bytes);
                             return Err(Error::IncompleteWrite);
                         }
                     }
                 }
-                Ok(PageFileHandle::new(&fp, &mut self.buffer_manager as *mut _))
+                let pfh = PageFileHandle::new(&fp, file_name, &mut self.buffer_manager as *mut _);
+                Ok(self.attach_locks(pfh))
             }
         }
     }
 
+    /*
+     * check if a page file exists, so callers can decide between
+     * create_file and open_file without going through the error path.
+     */
+    pub fn file_exists(file_name: &str) -> bool {
+        std::path::Path::new(file_name).exists()
+    }
+
+    /*
+     * destroy a page file.
+     * The file number is read from the file header, all pages of 
+     * the file in the buffer are evicted, then the file is removed.
+     * If any page of the file is still pinned while a handle of the
+     * file is alive, the file is in use and FileInUse is returned.
+     * Pages left pinned after all handles are dropped can only be 
+     * caused by a bug, they are force unpinned and evicted.
+     */
+    pub fn destroy_file(&mut self, file_name: &str) -> Result<(), Error> {
+        let fp = match File::open(file_name) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(Error::FileOpenError);
+            },
+            Ok(v) => v
+        };
+        let file_header = match PageFileHandle::read_header(&fp) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(Error::FileOpenError);
+            },
+            Ok(v) => v
+        };
+
+        if let Err(e) = self.buffer_manager.evict_file_pages(file_header.file_num) {
+            dbg!(&e);
+            //every handle of the file holds a reference to its lock table.
+            if self.locks.get(&file_header.file_num).is_some_and(|lm| Rc::strong_count(lm) > 1) {
+                return Err(Error::FileInUse);
+            }
+            self.buffer_manager.unpin_all_for_file(file_header.file_num);
+            if let Err(e) = self.buffer_manager.evict_file_pages(file_header.file_num) {
+                dbg!(&e);
+                return Err(Error::FileInUse);
+            }
+        }
+
+        self.locks.remove(&file_header.file_num);
+        match std::fs::remove_file(file_name) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::DestroyPageFileError)
+            },
+            Ok(_) => Ok(())
+        }
+    }
+
     pub fn open_file(&mut self, file_name: &String) -> Result<PageFileHandle, Error> {
-        match File::open(file_name) {
+        //pages and the header are written back through the same file pointer.
+        match OpenOptions::new().read(true).write(true).open(file_name) {
             Err(e) => {
                 dbg!(&e);
                 Err(Error::FileOpenError)
             },
             Ok(f) => {
-                Ok(PageFileHandle::new(&f, &mut self.buffer_manager as *mut _))
+                let pfh = PageFileHandle::new(&f, file_name, &mut self.buffer_manager as *mut _);
+                Ok(self.attach_locks(pfh))
+            }
+        }
+    }
+
+    /*
+     * Open a page file that will never be modified through the
+     * returned handle, e.g. for backups and audit tools.
+     * Pages are still cached by the BufferManager, but as they
+     * can never be marked dirty, they are never written back.
+     */
+    pub fn open_file_readonly(&mut self, file_name: &str) -> Result<PageFileHandle, Error> {
+        match OpenOptions::new().read(true).open(file_name) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::FileOpenError)
+            },
+            Ok(f) => {
+                let mut pfh = PageFileHandle::new(&f, file_name, &mut self.buffer_manager as *mut _);
+                pfh.read_only = true;
+                Ok(self.attach_locks(pfh))
             }
         }
     }
@@ -237,6 +422,9 @@ pub struct PageFileHandle {
     fp: File,
     header: PageFileHeader,
     header_changed: bool,//set true when the header is changed, then we need to write the header back to file when the file is about to be closed.
+    read_only: bool,//set true when opened by open_file_readonly, all modifications return Error::ReadOnly.
+    file_name: String,
+    locks: SharedLockManager,//page latches, shared by all handles of the file opened by the same PageFileManager.
     buffer_manager: &'static mut BufferManager
 }
 
@@ -246,13 +434,17 @@ impl PageFileHandle {
             fp: self.fp.try_clone().expect("clone file pointer error"),
             header: self.header,
             header_changed: self.header_changed,
+            read_only: self.read_only,
+            file_name: self.file_name.clone(),
+            locks: Rc::clone(&self.locks),
             buffer_manager: unsafe {
                 &mut *(self.buffer_manager as *mut _)//my way of copying a reference.
             }
         }
     }
 
-    pub fn new(f: &File, bm: *mut BufferManager) -> Self {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn new(f: &File, file_name: &str, bm: *mut BufferManager) -> Self {
         Self {
             fp: f.try_clone().expect("File pointer cloning error"),
             header: {
@@ -266,6 +458,9 @@ impl PageFileHandle {
                 }
             },
             header_changed: false,
+            read_only: false,
+            file_name: file_name.to_string(),
+            locks: SharedLockManager::default(),
             buffer_manager: unsafe {
                 &mut *bm
             }
@@ -273,21 +468,13 @@ impl PageFileHandle {
     }
 
     fn read_header(fp: &File) -> Result<PageFileHeader, PageFileError> {
-        let mut pf_header = PageFileHeader::new(0);
-        unsafe {
-            let slice_header = std::slice::from_raw_parts_mut(&mut pf_header as *mut _ as *mut u8, size_of::<PageFileHeader>());
-            let res = fp.read_at(slice_header, 0);
-            if let Err(_) = res {
-                dbg!(res);
-                return Err(PageFileError::ReadAtError);
-            }
-            let read_bytes = res.unwrap();
-            if read_bytes < size_of::<PageFileHeader>() {
-                dbg!(read_bytes);
-                return Err(PageFileError::IncompleteRead);
-            }
+        let mut buf = [0u8; PAGE_FILE_HEADER_SIZE];
+        let read_bytes = fp.read_at(&mut buf, 0)?;
+        if read_bytes < PAGE_FILE_HEADER_SIZE {
+            dbg!(read_bytes);
+            return Err(PageFileError::IncompleteRead);
         }
-        Ok(pf_header)
+        Ok(PageFileHeader::from_bytes(&buf))
     }
 
     /*
@@ -303,16 +490,19 @@ impl PageFileHandle {
      * to is full. 
      */
     pub fn allocate_page(&mut self) -> Result<PageHandle, Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
         let page_num: u32;
         let first_free = self.header.free;
-        let mut page_header: &mut PageHeader;
+        let page_header: &mut PageHeader;
         let data: *mut u8;
 
         if first_free > 0 {
             /*
-             * For a previously allocated page, we don't need
-             * any initialization. Cause the work was already 
-             * done when the page was disposed.
+             * A disposed page keeps its old data, and it may be
+             * read back from the file if it was evicted. Its data
+             * is zeroed below, the same as a new page.
              */
             debug!("Allocate a previously allocated page");
             page_num = first_free;
@@ -347,11 +537,12 @@ impl PageFileHandle {
 
         page_header.next_free = 0;
         page_header.page_num = page_num;
+        page_header.page_type = PAGE_TYPE_UNTYPED;
         dbg!(&page_header);
         self.header_changed = true;
         //zero out the page data.
         unsafe {
-            let p = data.offset(size_of::<PageHeader>() as isize);
+            let p = data.add(size_of::<PageHeader>());
             std::ptr::write_bytes(p, 0, PAGE_SIZE);
         }
         match self.mark_dirty(page_num) {
@@ -360,12 +551,58 @@ impl PageFileHandle {
         }
    }
 
+    /*
+     * Get the page at index, or create it if it's beyond the end of 
+     * the file. Meant for recovery, where a logged page may have 
+     * never reached the disk.
+     * The file is extended to index+1 pages, pages skipped over are 
+     * not initialized and are expected to be recovered as well.
+     */
+    pub fn get_or_create_page(&mut self, index: usize) -> Result<PageHandle, Error> {
+        let page_num = self.get_page_num(index);
+        if index < self.header.num_pages {
+            return self.get_page(page_num);
+        }
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        self.header.num_pages = index + 1;
+        self.header_changed = true;
+        let data = match self.buffer_manager.allocate_page(page_num, &self.fp) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(Error::AllocatePageError);
+            },
+            Ok(v) => v
+        };
+        let page_header = unsafe {
+            &mut *(data as *mut PageHeader)
+        };
+        page_header.next_free = 0;
+        page_header.page_num = page_num;
+        page_header.page_type = PAGE_TYPE_UNTYPED;
+        unsafe {
+            let p = data.add(size_of::<PageHeader>());
+            std::ptr::write_bytes(p, 0, PAGE_SIZE);
+        }
+        match self.mark_dirty(page_num) {
+            Ok(_) => Ok(PageHandle::new(page_num, data)),
+            Err(e) => Err(e)
+        }
+    }
+
     /*
      * Dispose a page.
      * The disposed page will be linked and all its data will
      * not be cleared.
+     * Disposing a page that is already in the free list returns 
+     * PageDisposed, with the page left unpinned.
      */
     pub fn dispose_page(&mut self, page_num: u32) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
         match self.buffer_manager.get_page(page_num, &self.fp) {
             Err(e) => {
                 dbg!(page_num);
@@ -373,34 +610,153 @@ impl PageFileHandle {
                 Err(Error::GetPageError)
             },
             Ok(v) => {
+                //the only pin allowed is the one just taken by get_page.
+                if let Some(pin_count) = self.buffer_manager.pin_count_of(page_num) {
+                    if pin_count > 1 {
+                        dbg!(page_num, pin_count);
+                        self.unpin_page(page_num)?;
+                        return Err(Error::PageFile(PageFileError::PagePinned));
+                    }
+                }
                 let page_header = unsafe {
                     &mut *(v as *mut PageHeader)
                 };
-                if page_header.next_free != 0 {
+                //the tail of the free list has no next_free, but it's still PAGE_TYPE_FREE.
+                if page_header.next_free != 0 || page_header.page_type == PAGE_TYPE_FREE || page_num == self.header.free {
                     dbg!(&page_header);
+                    self.unpin_page(page_num)?;
                     return Err(Error::PageDisposed);
                 }
                 page_header.next_free = self.header.free;
+                page_header.page_type = PAGE_TYPE_FREE;
                 dbg!(&page_header);
                 self.header.free = page_num;
                 dbg!(&self.header.free);
                 self.header_changed = true;
-                self.mark_dirty(page_num);//page header changed.
-                self.buffer_manager.unpin(page_num);
-                Ok(())
+                self.mark_dirty(page_num)?;//page header changed.
+                self.unpin_page(page_num)
             }
         }
     }
 
+    //pin count of a page, None if the page is not in the buffer.
+    pub fn pin_count_of(&self, page_num: u32) -> Option<u32> {
+        self.buffer_manager.pin_count_of(page_num)
+    }
+
+    /*
+     * Check if a page is in the free list, to catch a disposed page
+     * being used as a data page.
+     * Only pages in the free list are PAGE_TYPE_FREE, so the tail of
+     * the list is detected as well.
+     */
+    pub fn page_is_disposed(&mut self, page_num: u32) -> Result<bool, Error> {
+        let ph = self.get_page(page_num)?;
+        let page_type = ph.get_page_type();
+        self.unpin_page(page_num)?;
+        Ok(page_type == PAGE_TYPE_FREE)
+    }
+
+    /*
+     * Check the list of disposed pages for a cycle with Floyd's 
+     * algorithm: a slow cursor moves one page a step and a fast one
+     * moves two, they meet only if the list links back into itself.
+     * Returns the length of the list.
+     */
+    pub fn validate_free_list(&mut self) -> Result<usize, Error> {
+        let mut slow = self.header.free;
+        let mut fast = self.header.free;
+        while fast != 0 {
+            fast = self.next_free_of(fast)?;
+            if fast == 0 {
+                break;
+            }
+            fast = self.next_free_of(fast)?;
+            slow = self.next_free_of(slow)?;
+            if fast != 0 && fast == slow {
+                dbg!(slow);
+                return Err(Error::FreeListCycle);
+            }
+        }
+
+        let mut len = 0;
+        let mut page_num = self.header.free;
+        while page_num != 0 {
+            len += 1;
+            page_num = self.next_free_of(page_num)?;
+        }
+        Ok(len)
+    }
+
+    //next_free of a page, the page is not left pinned.
+    fn next_free_of(&mut self, page_num: u32) -> Result<u32, Error> {
+        let ph = self.get_page(page_num)?;
+        let next_free = unsafe {
+            (*(ph.data as *const PageHeader)).next_free
+        };
+        self.unpin_page(page_num)?;
+        Ok(next_free)
+    }
+
+    /*
+     * Cut the file down to its first num_pages pages.
+     * Pages cut off must be unpinned, they are removed from the free 
+     * list if they were disposed, and dropped from the buffer without
+     * being written back.
+     */
+    pub fn truncate(&mut self, num_pages: usize) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if num_pages >= self.header.num_pages {
+            return Ok(());
+        }
+
+        //unlink the pages cut off from the free list.
+        let mut prev = 0;
+        let mut curr = self.header.free;
+        while curr != 0 {
+            let next = self.next_free_of(curr)?;
+            if (curr & 0xffff) as usize >= num_pages {
+                if prev == 0 {
+                    self.header.free = next;
+                } else {
+                    let prev_ph = self.get_page(prev)?;
+                    unsafe {
+                        (*(prev_ph.data as *mut PageHeader)).next_free = next;
+                    }
+                    self.unpin_dirty_page(prev)?;
+                }
+            } else {
+                prev = curr;
+            }
+            curr = next;
+        }
+
+        for i in num_pages..self.header.num_pages {
+            if let Err(e) = self.buffer_manager.evict_page(self.get_page_num(i)) {
+                dbg!(&e);
+                return Err(Error::FileInUse);
+            }
+        }
+
+        let file_size = PAGE_FILE_HEADER_SIZE + num_pages * self.buffer_manager.get_pagesize();
+        if let Err(e) = self.fp.set_len(file_size as u64) {
+            dbg!(&e);
+            return Err(Error::PageFile(PageFileError::Unix));
+        }
+        self.header.num_pages = num_pages;
+        self.header_changed = true;
+        Ok(())
+    }
+
     pub fn get_page(&mut self, page_num: u32) -> Result<PageHandle, Error> {
         match self.buffer_manager.get_page(page_num, &self.fp) {
             Err(e) => {
                 dbg!(&e);
                 Err(Error::GetPageError)
             },
-            Ok(v) => {
-                Ok(PageHandle::new(page_num, v))
-            }
+            Ok(v) => Ok(PageHandle::new(page_num, v))
         }
     }
 
@@ -419,6 +775,9 @@ impl PageFileHandle {
     }
 
     pub fn mark_dirty(&mut self, page_num: u32) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
         if let Err(e) = self.buffer_manager.mark_dirty(page_num) {
             dbg!(&e);
             Err(Error::MarkDirtyError)
@@ -427,17 +786,324 @@ impl PageFileHandle {
         }
     }
 
+    //the page is unpinned even if it fails to be marked dirty.
     pub fn unpin_dirty_page(&mut self, page_num: u32) -> Result<(), Error> {
-        match self.mark_dirty(page_num) {
-            Ok(_) => {},
-            err => {
-                return err;
+        let res = self.mark_dirty(page_num);
+        self.unpin_page(page_num)?;
+        res
+    }
+
+    /*
+     * Write the page file header back to the file if it's changed.
+     * Pages are still managed by the buffer, so closing a file handle
+     * doesn't flush them.
+     */
+    pub fn close(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.write_header()
+    }
+
+    /*
+     * Write the header and dirty pages back, then wait until they 
+     * reach the disk. Pinned pages are not written back, the same as
+     * checkpoint.
+     */
+    pub fn force_sync(&mut self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        self.write_header()?;
+        self.buffer_manager.checkpoint()?;
+        if let Err(e) = self.fp.sync_all() {
+            dbg!(&e);
+            return Err(Error::FileSyncError);
+        }
+        Ok(())
+    }
+
+    /*
+     * Rename the file, the data is synced first.
+     * The file stays open through the rename, so the handle can 
+     * still be used.
+     */
+    pub fn rename(&mut self, new_name: &str) -> Result<(), Error> {
+        self.force_sync()?;
+        if let Err(e) = std::fs::rename(&self.file_name, new_name) {
+            dbg!(&e);
+            return Err(Error::FileOpenError);
+        }
+        self.file_name = new_name.to_string();
+        Ok(())
+    }
+
+    pub fn get_file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn get_locks(&self) -> SharedLockManager {
+        Rc::clone(&self.locks)
+    }
+
+    //write the header back if it's changed.
+    fn write_header(&mut self) -> Result<(), Error> {
+        if !self.header_changed {
+            return Ok(());
+        }
+        let sli = PageFileHeader::to_bytes(&self.header);
+        match self.fp.write_at(&sli, 0) {
+            Err(e) => {
+                dbg!(&e);
+                Err(Error::WriteHeaderError)
+            },
+            Ok(write_bytes) => {
+                if write_bytes < PAGE_FILE_HEADER_SIZE {
+                    dbg!(write_bytes);
+                    return Err(Error::IncompleteWrite);
+                }
+                self.header_changed = false;
+                Ok(())
             }
         }
-        self.unpin_page(page_num)
     }
 
-    fn get_page_num(&self, page_index: usize) -> u32 {
+    pub fn get_file_num(&self) -> u16 {
+        self.header.file_num
+    }
+
+    pub fn get_page_num(&self, page_index: usize) -> u32 {
         ((self.header.file_num as u32) << 16) | (page_index as u32)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PageFileManager, PageFileHeader, PageHeader, PAGE_SIZE, PAGE_FILE_HEADER_SIZE};
+    use crate::errors::Error;
+    use crate::test_utils::make_test_pfm;
+
+    //a page reused from the free list comes back zeroed.
+    #[test]
+    fn reuse_disposed_page_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("reuse_disposed_page_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let ph = pfh.allocate_page().expect("allocate page failed");
+        let page_num = ph.get_page_num();
+        unsafe {
+            std::ptr::write_bytes(ph.get_data(), 0xab, PAGE_SIZE);
+        }
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.dispose_page(page_num).expect("dispose page failed");
+
+        let ph = pfh.allocate_page().expect("allocate page failed");
+        assert_eq!(ph.get_page_num(), page_num);
+        let data = unsafe {
+            std::slice::from_raw_parts(ph.get_data(), PAGE_SIZE)
+        };
+        assert!(data.iter().all(|&b| b == 0));
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.close().expect("close failed");
+    }
+
+    #[test]
+    fn page_is_disposed_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("page_is_disposed_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let mut pages = Vec::new();
+        for _ in 0..2 {
+            let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+            pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+            pages.push(page_num);
+        }
+        assert!(!pfh.page_is_disposed(pages[1]).unwrap());
+        pfh.dispose_page(pages[0]).expect("dispose page failed");
+        pfh.dispose_page(pages[1]).expect("dispose page failed");
+        //pages[0] is the tail of the free list.
+        assert!(pfh.page_is_disposed(pages[1]).unwrap());
+        assert!(pfh.page_is_disposed(pages[0]).unwrap());
+        assert_eq!(pfh.validate_free_list().unwrap(), 2);
+
+        //link the tail back to the head.
+        let ph = pfh.get_page(pages[0]).expect("get page failed");
+        unsafe {
+            (*(ph.data as *mut PageHeader)).next_free = pages[1];
+        }
+        pfh.unpin_dirty_page(pages[0]).expect("unpin page failed");
+        assert!(matches!(pfh.validate_free_list(), Err(Error::FreeListCycle)));
+        pfh.close().expect("close failed");
+    }
+
+    //pages in the free list can't be disposed again, including the tail of the list.
+    #[test]
+    fn dispose_twice_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("dispose_twice_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let mut pages = Vec::new();
+        for _ in 0..2 {
+            let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+            pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+            pages.push(page_num);
+        }
+        pfh.dispose_page(pages[0]).expect("dispose page failed");
+        pfh.dispose_page(pages[1]).expect("dispose page failed");
+        for &page_num in pages.iter() {
+            assert!(matches!(pfh.dispose_page(page_num), Err(Error::PageDisposed)));
+            assert_eq!(pfh.pin_count_of(page_num), Some(0));
+        }
+        assert_eq!(pfh.validate_free_list().unwrap(), 2);
+    }
+
+    //a file with pinned pages is in use while its handle is alive, pages left pinned after that are force unpinned.
+    #[test]
+    fn destroy_file_in_use_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("destroy_file_in_use_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+        let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+
+        assert!(matches!(pfm.destroy_file(&file_name), Err(Error::FileInUse)));
+        assert!(std::path::Path::new(&file_name).exists());
+
+        drop(pfh);
+        pfm.destroy_file(&file_name).expect("destroy file failed");
+        assert!(!std::path::Path::new(&file_name).exists());
+        assert_eq!(pfm.buffer_manager.pin_count_of(page_num), None);
+    }
+
+    //a page beyond the end of the file is created, and the pages after it are allocated as usual.
+    #[test]
+    fn get_or_create_page_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("get_or_create_page_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let ph = pfh.allocate_page().expect("allocate page failed");
+        let first_page = ph.get_page_num();
+        unsafe {
+            std::ptr::write_bytes(ph.get_data(), 0xab, PAGE_SIZE);
+        }
+        pfh.unpin_dirty_page(first_page).expect("unpin page failed");
+
+        let ph = pfh.get_or_create_page(0).expect("get page failed");
+        assert_eq!(ph.get_page_num(), first_page);
+        #[cfg(debug_assertions)]
+        assert_eq!(pfh.buffer_manager.page_num_of(ph.data), Some(first_page));
+        assert_eq!(unsafe { *ph.get_data() }, 0xab);
+        pfh.unpin_page(first_page).expect("unpin page failed");
+
+        let ph = pfh.get_or_create_page(3).expect("create page failed");
+        assert_eq!(ph.get_page_num(), pfh.get_page_num(3));
+        #[cfg(debug_assertions)]
+        assert_eq!(pfh.buffer_manager.page_num_of(ph.data), Some(pfh.get_page_num(3)));
+        let data = unsafe {
+            std::slice::from_raw_parts(ph.get_data(), PAGE_SIZE)
+        };
+        assert!(data.iter().all(|&b| b == 0));
+        pfh.unpin_dirty_page(ph.get_page_num()).expect("unpin page failed");
+
+        let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+        assert_eq!(page_num, pfh.get_page_num(4));
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.close().expect("close failed");
+    }
+
+    //data written before a rename is found under the new name.
+    #[test]
+    fn rename_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("rename_test").to_str().unwrap().to_string();
+        let new_name = dir.path().join("rename_test_new").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let ph = pfh.allocate_page().expect("allocate page failed");
+        let page_num = ph.get_page_num();
+        unsafe {
+            std::ptr::write_bytes(ph.get_data(), 0xab, PAGE_SIZE);
+        }
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.rename(&new_name).expect("rename failed");
+        assert_eq!(pfh.get_file_name(), new_name);
+        assert!(!PageFileManager::file_exists(&file_name));
+
+        //a new buffer pool reads the page from the disk.
+        let mut other_pfm = PageFileManager::new();
+        let mut other_pfh = other_pfm.open_file(&new_name).expect("open file failed");
+        let ph = other_pfh.get_first_page().expect("get page failed");
+        assert_eq!(unsafe { *ph.get_data() }, 0xab);
+        other_pfh.unpin_page(ph.get_page_num()).expect("unpin page failed");
+        pfh.close().expect("close failed");
+    }
+
+    //the free list head is written with the header on close, so it survives reopening.
+    #[test]
+    fn disposed_page_reuse_after_reopen_test() {
+        let _ = env_logger::try_init();
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("disposed_page_reuse_after_reopen_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+
+        let mut pages = Vec::new();
+        for _ in 0..3 {
+            let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+            pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+            pages.push(page_num);
+        }
+        pfh.dispose_page(pages[1]).expect("dispose page failed");
+        pfh.close().expect("close failed");
+        drop(pfh);
+
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+        let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+        assert_eq!(page_num, pages[1]);
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.close().expect("close failed");
+    }
+
+    //the header is written little-endian in PAGE_FILE_HEADER_SIZE bytes.
+    #[test]
+    fn page_file_header_bytes_test() {
+        let header = PageFileHeader {
+            file_num: 0x0102,
+            num_pages: 0x0304,
+            free: 0x0506_0708
+        };
+        let b = PageFileHeader::to_bytes(&header);
+        assert_eq!(b, [0x02, 0x01, 0x04, 0x03, 0, 0, 0, 0, 0, 0, 0x08, 0x07, 0x06, 0x05]);
+        let decoded = PageFileHeader::from_bytes(&b);
+        assert_eq!(decoded.file_num, header.file_num);
+        assert_eq!(decoded.num_pages, header.num_pages);
+        assert_eq!(decoded.free, header.free);
+
+        let (mut pfm, dir) = make_test_pfm();
+        let file_name = dir.path().join("page_file_header_bytes_test").to_str().unwrap().to_string();
+        pfm.create_file(&file_name).expect("create file failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+        let page_num = pfh.allocate_page().expect("allocate page failed").get_page_num();
+        pfh.unpin_dirty_page(page_num).expect("unpin page failed");
+        pfh.close().expect("close failed");
+        pfm.checkpoint().expect("checkpoint failed");
+        //the first page starts right after the header.
+        let page_size = std::mem::size_of::<PageHeader>() + PAGE_SIZE;
+        assert_eq!(std::fs::metadata(&file_name).unwrap().len(), (PAGE_FILE_HEADER_SIZE + page_size) as u64);
+    }
+}
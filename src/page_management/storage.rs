@@ -0,0 +1,87 @@
+/*
+ * Where the buffer pool reads pages from and writes pages to.
+ * Pages are addressed by their byte offsets in the storage, like
+ * read_at and write_at of a file.
+ *
+ * File is the storage used by page files. MemoryBackend keeps
+ * pages in memory, so the buffer pool can be tested without
+ * touching the filesystem.
+ */
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+pub trait StorageBackend {
+    //returns the number of bytes read, which is less than buf.len() at the end of the storage.
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize>;
+    fn sync(&self) -> io::Result<()>;
+}
+
+impl StorageBackend for File {
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_at(buf, offset)
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, offset)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_data()
+    }
+}
+
+/*
+ * Pages written are kept by their offsets, a page never written
+ * reads as the end of the storage.
+ * At most page_size bytes of a write are kept.
+ */
+#[derive(Debug)]
+pub struct MemoryBackend {
+    pages: BTreeMap<u64, Vec<u8>>,
+    page_size: usize
+}
+
+impl MemoryBackend {
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            page_size
+        }
+    }
+
+    pub fn get_page_size(&self) -> usize {
+        self.page_size
+    }
+
+    //number of pages written.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn read_page(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        match self.pages.get(&offset) {
+            None => Ok(0),
+            Some(page) => {
+                let len = buf.len().min(page.len());
+                buf[..len].copy_from_slice(&page[..len]);
+                Ok(len)
+            }
+        }
+    }
+
+    fn write_page(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.page_size);
+        self.pages.insert(offset, buf[..len].to_vec());
+        Ok(len)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -8,59 +8,43 @@
  **********************************************/
 
 use super::buffer_manager::*;
-use super::page_file::*;
-use std::fs::File;
-use std::fs::OpenOptions;
-
-fn open() -> File {
-    OpenOptions::new().read(true).write(true).open("/home/lunar/Documents/fzf").unwrap()
-}
+use crate::test_utils::{create_test_file, make_test_pfm};
 
 /*
  * Test1:
  * Read 128 pages, make them dirty, unpin half of them, then read another 128 pages.
  */
-//#[test]
-//fn buffer_manager_test1() {
-    //let mut buffer = BufferManager::new(128);
-    //let mut f = OpenOptions::new().read(true).write(true).open("/home/lunar/Documents/fzf").unwrap();
-    //let file_num: u32 = 1<<16;
-    //for i in 0..128 {
-        //match buffer.get_page(file_num | (i as u32), &f) {
-            //Err(_) => {
-                //panic!("read page_num={:#010x} failed", file_num|(i as u32));
-            //},
-            //Ok(mut v) => {
-                //let page = unsafe {
-                    //&mut *v.as_ptr()
-                //};
-                //page.mark_dirty();
-                ////dbg!(&page);
-            //}
-        //}
-    //}
-    //for i in 0..64 {
-        //println!("unpin page_num={:#010x}", file_num | (i as u32));
-        //buffer.unpin(file_num | (i as u32));
-    //}
-    //println!("Unpin finished");
-    //for i in 0..32 {
-        //match buffer.get_page(file_num | (i as u32), &f) {
-            //Err(_) => {
-                //panic!("read page_num={:#010x} failed");
-            //},
-            //Ok(_) => {}
-        //}
-    //}
-    //for i in 128..256 {
-        //match buffer.get_page(file_num | (i as u32), &f) {
-            //Err(_) => {
-                //panic!("read page_num={:#010x} failed", file_num|(i as u32));
-            //},
-            //Ok(_) => {}
-        //}
-    //}
-//}
+#[test]
+fn buffer_manager_test1() {
+    let mut buffer = BufferManager::new(128);
+    //page offsets are shifted by the file header, leave room for one more page.
+    let (tf, _) = create_test_file((257 * buffer.get_pagesize()) as u64);
+    let f = tf.as_file();
+    let file_num: u32 = 1<<16;
+    for i in 0..128 {
+        match buffer.get_page(file_num | (i as u32), f) {
+            Err(_) => {
+                panic!("read page_num={:#010x} failed", file_num|(i as u32));
+            },
+            Ok(_) => {
+                buffer.mark_dirty(file_num | (i as u32)).unwrap();
+            }
+        }
+    }
+    for i in 0..64 {
+        buffer.unpin(file_num | (i as u32)).unwrap();
+    }
+    for i in 0..32 {
+        if buffer.get_page(file_num | (i as u32), f).is_err() {
+            panic!("read page_num={:#010x} failed", file_num|(i as u32));
+        }
+    }
+    for i in 128..256 {
+        if buffer.get_page(file_num | (i as u32), f).is_err() {
+            panic!("read page_num={:#010x} failed", file_num|(i as u32));
+        }
+    }
+}
 
 /*
  * Page File Unit Test1.
@@ -68,9 +52,9 @@ fn open() -> File {
  */
 #[test]
 fn page_file_test1() {
-    let mut pf = crate::page_management::page_file::PageFileManager::new();
-    let table_name = String::from("Table1");
-    pf.create_file(&table_name);
+    let (mut pf, dir) = make_test_pfm();
+    let table_name = dir.path().join("Table1").to_str().unwrap().to_string();
+    pf.create_file(&table_name).expect("create file failed");
     let mut fh = pf.open_file(&table_name).expect("");
     let mut v = Vec::new();
     for i in 0..128 {
@@ -80,11 +64,12 @@ fn page_file_test1() {
             panic!("get {}th page error", i);
         }
         let ph = p.unwrap();
-        dbg!(&ph);
         v.push(ph);
     }
     for ph in v {
-        fh.dispose_page(ph.get_page_num());
+        fh.unpin_dirty_page(ph.get_page_num()).expect("unpin page failed");
+        fh.dispose_page(ph.get_page_num()).expect("dispose page failed");
     }
+    assert_eq!(fh.validate_free_list().unwrap(), 128);
+    fh.close().expect("close failed");
 }
-
@@ -7,21 +7,18 @@
   > Copyright@ https://github.com/xiaoqixian
  **********************************************/
 
+use std::mem::size_of;
+use std::marker::PhantomData;
+use std::collections::BTreeMap;
 use crate::utils;
-use crate::page_management::page_file::{PageFileHandle, PageHandle};
-use crate::errors::{Error, RecordError};
-
-#[derive(Debug, Copy, Clone)]
-pub struct RID {
-    page_num: u32,
-    slot_num: usize
-}
-
-pub struct Record {
-    rid: RID,
-    record_size: usize,
-    data: *mut u8
-}
+use log::error;
+use crate::page_management::page_file::{PageFileManager, PageFileHandle, PageHandle, PAGE_SIZE, PAGE_TYPE_RECORD};
+use super::record_file_manager::RecordFileManager;
+use crate::errors::{Error, RecordError, ResultContext};
+use crate::lock_manager::{LockManager, SharedLockManager};
+use crate::indexing::index_handle::IndexHandle;
+use crate::types::Pod;
+pub use crate::types::{RID, Record};
 
 /*
  * Every record file represents a table, every table has a same record 
@@ -30,7 +27,11 @@ pub struct Record {
  * In a page, all records are managed by a bitmap. The size of the bitmap
  * is calculated by the record size.
  *
- * Page data layout: bitmap | records.
+ * Page data layout: page header | null bitmaps | bitmap | padding | records.
+ * Null bitmaps of all slots come in slot order, each has a bit per
+ * column telling if the column of the record is null. They are only
+ * there if the file is created with nullable columns.
+ * The padding aligns records to usize.
  */
 #[derive(Debug, Copy, Clone)]
 pub struct RecordFileHeader {
@@ -39,7 +40,11 @@ pub struct RecordFileHeader {
     pub bitmap_size: usize,
     pub records_offset: usize,
     pub num_records_per_page: usize,
-    pub num_pages: usize
+    pub num_pages: usize,
+    pub total_records: usize,//number of live records in the file.
+    pub first_free_page: u32,//head of the free page list, kept so the list survives reopening.
+    pub null_bitmap_offset: usize,
+    pub null_bitmap_size: usize//bytes of the null bitmap of a record, 0 if records have no null bitmaps.
 }
 
 
@@ -54,34 +59,49 @@ pub struct RecordFileHandle {
     header_num: u32,//page num of the header, that's right, header is stored in one of the pages. When the page file is about to be closed, we use the header_num to get the page and copy the header of this handle into it.
     free: u32,//when all of the records of a page are deleted, the page is linked for later usage. 
     header: RecordFileHeader,
-    pfh: PageFileHandle
+    pfh: PageFileHandle,
+    /*
+     * Reads latch the page in shared mode and writes in exclusive mode.
+     * The lock table is the one of the page file, so handles of the 
+     * same table latch pages in the same table.
+     */
+    locks: SharedLockManager
 }
 
-impl RID {
-    pub fn new(page_num: u32, slot_num: usize) -> Self {
-        Self {
-            page_num,
-            slot_num,
-        }
-    }
-
-    pub fn get_page_num(&self) -> u32 {
-        self.page_num
-    }
+/*
+ * RecordScan iterates over all live records of a record file.
+ * 
+ * The header page is the first page of the file, so record pages 
+ * are from page index 1 to header.num_pages.
+ * page_cursor is the index of the page to scan, and slot_cursor is
+ * the slot to start searching for the next occupied slot.
+ * 
+ * Every call of next pins the current page, and unpins it before
+ * returning. So no page is left pinned if the scan is dropped 
+ * halfway.
+ */
+pub struct RecordScan<'a> {
+    rfh: &'a mut RecordFileHandle,
+    page_cursor: usize,
+    slot_cursor: usize
+}
 
-    pub fn get_slot_num(&self) -> usize {
-        self.slot_num
-    }
+/*
+ * RecordTypedScan reads every record of a RecordScan as a T.
+ * T is a plain data type with the size of a record, the record 
+ * bytes are read unaligned as they are copied into a Vec<u8>.
+ */
+pub struct RecordTypedScan<'a, T: Pod> {
+    inner: RecordScan<'a>,
+    _marker: PhantomData<T>
 }
 
-impl Record {
-    pub fn new(record_size: usize, rid: RID, data: *mut u8) -> Self {
-        Self {
-            record_size,
-            rid,
-            data,
-        }
-    }
+//slot usage of a record page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageStats {
+    pub total_slots: usize,
+    pub used_slots: usize,
+    pub free_slots: usize
 }
 
 impl RecordPageHeader {
@@ -91,33 +111,222 @@ impl RecordPageHeader {
             next_free,
         }
     }
+
+    pub fn get_num_records(&self) -> usize {
+        self.num_records
+    }
+
+    pub fn get_next_free(&self) -> u32 {
+        self.next_free
+    }
 }
 
 impl RecordFileHandle {
     pub fn new(header_num: u32, header: RecordFileHeader, pfh: &mut PageFileHandle) -> Self {
         Self {
             header_num,
-            free: 0,
+            free: header.first_free_page,
             header,
-            pfh: pfh.clone()
+            pfh: pfh.clone(),
+            locks: pfh.get_locks()
         }
     }
 
+    pub fn scan(&mut self) -> RecordScan<'_> {
+        RecordScan {
+            rfh: self,
+            page_cursor: 1,
+            slot_cursor: 0
+        }
+    }
+
+    //scan all live records as T, the size of T must equal to the record size.
+    pub fn scan_typed<T: Pod>(&mut self) -> Result<RecordTypedScan<'_, T>, Error> {
+        if size_of::<T>() != self.header.record_size {
+            dbg!((size_of::<T>(), self.header.record_size));
+            return Err(Error::InvalidRecordSize);
+        }
+        Ok(RecordTypedScan {
+            inner: self.scan(),
+            _marker: PhantomData
+        })
+    }
+
+    /*
+     * Scan all live records that satisfy pred.
+     * As pred is called before the record is copied, records that 
+     * don't match cost no allocation.
+     */
+    pub fn scan_where<'a, F>(&'a mut self, pred: F) -> impl Iterator<Item = Result<(RID, Record), Error>> + 'a
+        where F: Fn(&[u8]) -> bool + 'a {
+        let mut scan = self.scan();
+        std::iter::from_fn(move || scan.next_matching(&pred))
+    }
+
+    /*
+     * Call f on every live record, with a slice directly into the 
+     * pinned page, so no record is copied.
+     * The scan stops early when f returns false.
+     * Every page is pinned once and unpinned after f returns for all 
+     * records of it.
+     * Returns the number of records visited.
+     */
+    pub fn for_each_record<F>(&mut self, mut f: F) -> Result<usize, Error>
+        where F: FnMut(RID, &[u8]) -> bool {
+        self.for_each_record_with_nulls(|rid, data, _| f(rid, data))
+    }
+
+    /*
+     * Same as for_each_record, but f also gets the null bitmap of 
+     * every record, which is empty if the file has no nullable columns.
+     */
+    pub fn for_each_record_with_nulls<F>(&mut self, mut f: F) -> Result<usize, Error>
+        where F: FnMut(RID, &[u8], &[u8]) -> bool {
+        let mut visited = 0;
+        for i in 1..=self.header.num_pages {
+            let page_num = self.pfh.get_page_num(i);
+            let _latch = LockManager::acquire_shared(&self.locks, page_num)?;
+            let ph = self.pfh.get_page(page_num)?;
+            let data = ph.get_data();
+
+            let mut stop = false;
+            //disposed pages are skipped.
+            if ph.get_page_type() == PAGE_TYPE_RECORD {
+                let mut slot = 0;
+                while let Some(slot_num) = self.next_occupied_slot(data, slot) {
+                    let offset = self.get_record_offset(slot_num) as usize;
+                    visited += 1;
+                    let null_bitmap = utils::get_arr::<u8>(data, self.get_null_bitmap_offset(slot_num), self.header.null_bitmap_size);
+                    if !f(RID::new(page_num, slot_num), utils::get_arr::<u8>(data, offset, self.header.record_size), null_bitmap) {
+                        stop = true;
+                        break;
+                    }
+                    slot = slot_num + 1;
+                }
+            }
+            self.pfh.unpin_page(page_num)?;
+
+            if stop {
+                break;
+            }
+        }
+        Ok(visited)
+    }
+
+    /*
+     * Copy all records into a new record file with record size new_size,
+     * for adding or dropping columns at the end of records.
+     * A record is cut at new_size if it shrinks, or padded with zeros 
+     * if it grows. Null bitmaps are copied as they are, so the new 
+     * file has as many nullable columns as this one.
+     * RIDs are not kept, as records are inserted anew.
+     * Returns the handle of the new file.
+     */
+    pub fn migrate_record_size(&mut self, new_size: usize, pfm: &mut PageFileManager, new_file_name: &str) -> Result<RecordFileHandle, Error> {
+        let mut new_rfh = RecordFileManager::create_file_nullable(&String::from(new_file_name), pfm, new_size, self.header.null_bitmap_size * 8)?;
+        let copy_size = self.header.record_size.min(new_size);
+        let mut buffer = utils::allocate_buffer_vec(new_size);
+        let mut res = Ok(());
+        self.for_each_record_with_nulls(|_, data, null_bitmap| {
+            buffer.fill(0);
+            buffer[..copy_size].copy_from_slice(&data[..copy_size]);
+            match new_rfh.insert_record_copy(&buffer, null_bitmap) {
+                Ok(_) => true,
+                Err(e) => {
+                    res = Err(e);
+                    false
+                }
+            }
+        })?;
+        res?;
+        Ok(new_rfh)
+    }
+
+    /*
+     * Copy all records into other, which must have the same record 
+     * size and null bitmap size, for cloning or backing up a table.
+     * Use migrate_record_size if the record size changes.
+     * Returns the number of records copied.
+     */
+    pub fn copy_to(&mut self, other: &mut RecordFileHandle) -> Result<usize, Error> {
+        if self.header.record_size != other.header.record_size || self.header.null_bitmap_size != other.header.null_bitmap_size {
+            dbg!((self.header.record_size, other.header.record_size));
+            dbg!((self.header.null_bitmap_size, other.header.null_bitmap_size));
+            return Err(Error::RecordSizeMismatch);
+        }
+        let mut copied = 0;
+        let mut res = Ok(());
+        self.for_each_record_with_nulls(|_, data, null_bitmap| {
+            match other.insert_record_copy(data, null_bitmap) {
+                Ok(_) => {
+                    copied += 1;
+                    true
+                },
+                Err(e) => {
+                    res = Err(e);
+                    false
+                }
+            }
+        })?;
+        res?;
+        Ok(copied)
+    }
+
+    /*
+     * insert a copy of data, with null_bitmap if the file has 
+     * nullable columns.
+     */
+    fn insert_record_copy(&mut self, data: &[u8], null_bitmap: &[u8]) -> Result<RID, Error> {
+        if null_bitmap.is_empty() {
+            self.insert_record_inner(data.as_ptr() as *mut u8, None)
+        } else {
+            self.insert_record_with_nulls(data, null_bitmap)
+        }
+    }
+
+    /*
+     * Insert an entry into ih for every record, keyed by the bytes at
+     * key_offset..key_offset+key_length of the record.
+     * This builds an index on a table which already has records.
+     * Returns the number of entries inserted.
+     */
+    pub fn rebuild_index(&mut self, key_offset: usize, key_length: usize, ih: &mut IndexHandle) -> Result<usize, Error> {
+        if key_offset + key_length > self.header.record_size || key_length != ih.get_attr_length() {
+            dbg!((key_offset, key_length));
+            return Err(Error::InvalidKeyRange);
+        }
+        let mut key = utils::allocate_buffer_vec(key_length);
+        let mut inserted = 0;
+        let mut res = Ok(());
+        self.for_each_record(|rid, data| {
+            key.copy_from_slice(&data[key_offset..key_offset+key_length]);
+            match ih.insert_entry(key.as_mut_ptr(), &rid) {
+                Ok(_) => {
+                    inserted += 1;
+                    true
+                },
+                Err(e) => {
+                    res = Err(e);
+                    false
+                }
+            }
+        })?;
+        res?;
+        Ok(inserted)
+    }
+
     pub fn get_record(&mut self, rid: &RID) -> Result<Record, Error> {
+        let _latch = LockManager::acquire_shared(&self.locks, rid.get_page_num())?;
         let ph = match self.pfh.get_page(rid.get_page_num()) {
             Err(e) => {
                 return Err(e);
             },
             Ok(v) => v
         };
-        let data = ph.get_data();
-        let record_ptr = unsafe {
-            data.offset(self.get_record_offset(rid.get_slot_num()))
-        };
-        let buffer = utils::allocate_buffer(self.header.record_size);
-        unsafe {
-            std::ptr::copy(record_ptr, buffer, self.header.record_size);
-        }
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        let mut buffer = utils::allocate_buffer_vec(self.header.record_size);
+        buffer.copy_from_slice(utils::get_arr::<u8>(ph.get_data(), offset, self.header.record_size));
 
         match self.pfh.unpin_page(ph.get_page_num()) {
             Ok(_) => Ok(Record::new(self.header.record_size, *rid, buffer)),
@@ -125,53 +334,199 @@ impl RecordFileHandle {
         }
     }
 
+    //read a record with its null bitmap.
+    pub fn get_record_with_nulls(&mut self, rid: &RID) -> Result<(Record, Vec<u8>), Error> {
+        let _latch = LockManager::acquire_shared(&self.locks, rid.get_page_num())?;
+        let ph = self.pfh.get_page(rid.get_page_num())?;
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        let mut buffer = utils::allocate_buffer_vec(self.header.record_size);
+        buffer.copy_from_slice(utils::get_arr::<u8>(ph.get_data(), offset, self.header.record_size));
+        let null_bitmap = utils::get_arr::<u8>(ph.get_data(), self.get_null_bitmap_offset(rid.get_slot_num()), self.header.null_bitmap_size).to_vec();
+
+        self.pfh.unpin_page(ph.get_page_num())?;
+        Ok((Record::new(self.header.record_size, *rid, buffer), null_bitmap))
+    }
+
+    /*
+     * Copy a record into buf instead of allocating a new buffer,
+     * buf is expected to be at least record_size long.
+     */
+    pub fn get_record_into(&mut self, rid: &RID, buf: &mut [u8]) -> Result<(), Error> {
+        if buf.len() < self.header.record_size {
+            dbg!(buf.len());
+            return Err(Error::BufferTooSmall);
+        }
+        let _latch = LockManager::acquire_shared(&self.locks, rid.get_page_num())?;
+        let ph = self.pfh.get_page(rid.get_page_num())?;
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        buf[..self.header.record_size].copy_from_slice(utils::get_arr::<u8>(ph.get_data(), offset, self.header.record_size));
+
+        self.pfh.unpin_page(ph.get_page_num())
+    }
+
     pub fn update_record(&mut self, rec: &Record) -> Result<(), Error> {
-        let rid = rec.rid;
+        if rec.get_data().len() != self.header.record_size {
+            dbg!(rec.get_data().len());
+            return Err(Error::InvalidRecordSize);
+        }
+        let rid = rec.get_rid();
+        let _latch = LockManager::acquire_exclusive(&self.locks, rid.get_page_num())?;
         let ph = match self.pfh.get_page(rid.get_page_num()) {
             Err(e) => {
                 return Err(e);
             },
             Ok(v) => v
         };
-        let data = ph.get_data();
-        let record_ptr = unsafe {
-            data.offset(self.get_record_offset(rid.get_slot_num()))
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        utils::get_arr_mut::<u8>(ph.get_data(), offset, self.header.record_size).copy_from_slice(rec.get_data());
+
+        match self.pfh.unpin_dirty_page(ph.get_page_num()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e)
+        }
+    }
+
+    /*
+     * Update a record and return its old data.
+     * The old data can be kept for undo.
+     */
+    pub fn update_record_ex(&mut self, rid: &RID, new_data: &[u8]) -> Result<Vec<u8>, Error> {
+        if new_data.len() != self.header.record_size {
+            dbg!(new_data.len());
+            return Err(Error::InvalidRecordSize);
+        }
+        if rid.get_slot_num() >= self.header.num_records_per_page {
+            dbg!(rid.get_slot_num());
+            return Err(Error::Record(RecordError::InvalidSlotNumber));
+        }
+        let _latch = LockManager::acquire_exclusive(&self.locks, rid.get_page_num())?;
+        let ph = match self.pfh.get_page(rid.get_page_num()) {
+            Err(e) => {
+                return Err(e);
+            },
+            Ok(v) => v
         };
-        unsafe {
-            std::ptr::copy(rec.data, record_ptr, rec.record_size);
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        if !self.slot_is_occupied(ph.get_data(), rid.get_slot_num()) {
+            self.pfh.unpin_page(ph.get_page_num())?;
+            return Err(Error::Record(RecordError::RecordDeleted));
         }
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        let record = utils::get_arr_mut::<u8>(ph.get_data(), offset, self.header.record_size);
+        let mut old_data = utils::allocate_buffer_vec(self.header.record_size);
+        old_data.copy_from_slice(record);
+        record.copy_from_slice(new_data);
 
         match self.pfh.unpin_dirty_page(ph.get_page_num()) {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(old_data),
             Err(e) => Err(e)
         }
     }
 
+    /*
+     * Replace a record with desired only if it still equals expected.
+     * The page is pinned and latched through the whole compare and 
+     * write, and it's dirtied only when the record is replaced.
+     * Returns whether the record is replaced.
+     */
+    pub fn compare_and_swap_record(&mut self, rid: &RID, expected: &[u8], desired: &[u8]) -> Result<bool, Error> {
+        if expected.len() != self.header.record_size || desired.len() != self.header.record_size {
+            dbg!((expected.len(), desired.len()));
+            return Err(Error::InvalidRecordSize);
+        }
+        if rid.get_slot_num() >= self.header.num_records_per_page {
+            dbg!(rid.get_slot_num());
+            return Err(Error::Record(RecordError::InvalidSlotNumber));
+        }
+        let _latch = LockManager::acquire_exclusive(&self.locks, rid.get_page_num())?;
+        let ph = self.pfh.get_page(rid.get_page_num())?;
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        //a deleted record can't be compared.
+        if !self.slot_is_occupied(ph.get_data(), rid.get_slot_num()) {
+            self.pfh.unpin_page(ph.get_page_num())?;
+            return Err(Error::Record(RecordError::RecordDeleted));
+        }
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        let record = utils::get_arr_mut::<u8>(ph.get_data(), offset, self.header.record_size);
+
+        if record != expected {
+            self.pfh.unpin_page(ph.get_page_num())?;
+            return Ok(false);
+        }
+        record.copy_from_slice(desired);
+        self.pfh.unpin_dirty_page(ph.get_page_num())?;
+        Ok(true)
+    }
+
+    /*
+     * Mutate a record directly in its page.
+     * f gets the bytes of the record inside the page, so the record
+     * is neither copied out nor copied back, and the page is pinned
+     * only once.
+     */
+    pub fn update_in_place<F>(&mut self, rid: &RID, f: F) -> Result<(), Error>
+        where F: FnOnce(&mut [u8]) {
+        if rid.get_slot_num() >= self.header.num_records_per_page {
+            dbg!(rid.get_slot_num());
+            return Err(Error::Record(RecordError::InvalidSlotNumber));
+        }
+        let _latch = LockManager::acquire_exclusive(&self.locks, rid.get_page_num())?;
+        let ph = match self.pfh.get_page(rid.get_page_num()) {
+            Err(e) => {
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+        let offset = self.get_record_offset(rid.get_slot_num()) as usize;
+        let record = utils::get_arr_mut::<u8>(ph.get_data(), offset, self.header.record_size);
+        debug_assert_eq!(record.len(), self.header.record_size);
+        f(record);
+
+        self.pfh.unpin_dirty_page(ph.get_page_num())
+    }
+
     pub fn delete_record(&mut self, rid: &RID) -> Result<(), Error> {
+        if rid.get_slot_num() >= self.header.num_records_per_page {
+            dbg!(rid.get_slot_num());
+            return Err(Error::Record(RecordError::InvalidSlotNumber));
+        }
+        let _latch = LockManager::acquire_exclusive(&self.locks, rid.get_page_num())?;
         let ph = match self.pfh.get_page(rid.get_page_num()) {
             Err(e) => {
                 return Err(e);
             },
             Ok(v) => v
         };
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
         let data = ph.get_data();
+        //the slot is checked to be occupied before the record is cleared.
+        if let Err(e) = self.set_bitmap(rid.get_slot_num(), data, false) {
+            dbg!(&e);
+            let _ = self.pfh.unpin_page(ph.get_page_num());
+            return Err(Error::SetBitmapError);
+        }
         let record_ptr = unsafe {
             data.offset(self.get_record_offset(rid.get_slot_num()))
         };
         unsafe {
             std::ptr::write_bytes(record_ptr, 0, self.header.record_size);
-        }
-        if let Err(e) = self.set_bitmap(rid.slot_num, data, false) {
-            dbg!(&e);
-            self.pfh.unpin_dirty_page(ph.get_page_num());
-            return Err(Error::SetBitmapError);
+            utils::page_data_slice(data, self.get_null_bitmap_offset(rid.get_slot_num()), self.header.null_bitmap_size).fill(0);
         }
         let rph = unsafe {
             &mut *(data as *mut RecordPageHeader)
         };
+        //a page is in the free list only when it has free slots,
+        //so it's linked when it's no longer full.
+        if rph.num_records == self.header.num_records_per_page {
+            rph.next_free = self.free;
+            self.set_free(rid.get_page_num());
+        }
         rph.num_records -= 1;
-        rph.next_free = self.free;
-        self.free = rid.get_page_num();
+        self.header.total_records -= 1;
 
         match self.pfh.unpin_dirty_page(ph.get_page_num()) {
             Ok(_) => Ok(()),
@@ -180,71 +535,348 @@ impl RecordFileHandle {
     }
 
     /*
-     * Insert a record and returns its rid.
-     * Choose a slot in the next_free page, if next_free = 0 or it's full,
-     * allocate a new page and let next_free = new page num;
+     * Give back the empty pages at the end of the file.
+     * Pages are checked from the last one backwards, and the check 
+     * stops at the first page still holding records. An empty page 
+     * is unlinked from the free list and disposed, then the page file
+     * is truncated so the file itself shrinks.
+     * Returns the number of pages reclaimed.
      */
-    pub fn insert_record(&mut self, data: *mut u8) -> Result<RID, Error> {
-        let mut slot_num: usize = 0;
-        let mut ph = PageHandle::new(0, std::ptr::null_mut());
-        let mut flag = true;
-        let mut new_page = false;
-        while self.free != 0 && flag {
-            ph = match self.pfh.get_page(self.free) {
-                Err(e) => {
-                    return Err(e);
-                },
-                Ok(v) => v
+    pub fn vacuum(&mut self) -> Result<usize, Error> {
+        let mut reclaimed = 0;
+        while self.header.num_pages > 0 {
+            let page_num = self.pfh.get_page_num(self.header.num_pages);
+            let _latch = LockManager::acquire_exclusive(&self.locks, page_num)?;
+            let ph = self.pfh.get_page(page_num)?;
+            let rph = unsafe {
+                & *(ph.get_data() as *const RecordPageHeader)
             };
+            let empty = ph.get_page_type() == PAGE_TYPE_RECORD && rph.num_records == 0;
+            let next_free = rph.next_free;
+            self.pfh.unpin_page(page_num)?;
+            if !empty {
+                break;
+            }
+
+            self.unlink_free_page(page_num, next_free)?;
+            self.pfh.dispose_page(page_num)?;
+            self.header.num_pages -= 1;
+            reclaimed += 1;
+        }
+
+        //the header page is the first page of the file.
+        if reclaimed > 0 {
+            self.pfh.truncate(self.header.num_pages + 1)?;
+        }
+        Ok(reclaimed)
+    }
+
+    //remove a page from the free list, next_free is the page after it.
+    fn unlink_free_page(&mut self, page_num: u32, next_free: u32) -> Result<(), Error> {
+        if self.free == page_num {
+            self.set_free(next_free);
+            return Ok(());
+        }
+        let mut curr = self.free;
+        while curr != 0 {
+            let _latch = LockManager::acquire_exclusive(&self.locks, curr)?;
+            let ph = self.pfh.get_page(curr)?;
             let rph = unsafe {
                 &mut *(ph.get_data() as *mut RecordPageHeader)
             };
-            if rph.num_records > self.header.num_records_per_page {
-                dbg!(&rph.num_records);
-                panic!(true);
+            if rph.next_free == page_num {
+                rph.next_free = next_free;
+                return self.pfh.unpin_dirty_page(curr);
             }
-            if rph.num_records == self.header.num_records_per_page {
-                self.free = rph.next_free;
-                rph.next_free = 0;
-                self.pfh.unpin_dirty_page(ph.get_page_num());
+            let next = rph.next_free;
+            self.pfh.unpin_page(curr)?;
+            curr = next;
+        }
+        Ok(())
+    }
+
+    pub fn get_header(&self) -> &RecordFileHeader {
+        &self.header
+    }
+
+    //the header mirrors the head of the free list, so it's 
+    //persisted when the header is written back.
+    fn set_free(&mut self, page_num: u32) {
+        self.free = page_num;
+        self.header.first_free_page = page_num;
+    }
+
+    /*
+     * Write the record file header back to the header page, 
+     * and the page file header back to the file.
+     */
+    pub fn close(&mut self) -> Result<(), Error> {
+        let _latch = LockManager::acquire_exclusive(&self.locks, self.header_num)?;
+        let ph = match self.pfh.get_page(self.header_num) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+        unsafe {
+            std::ptr::copy(&self.header as *const _ as *const u8, ph.get_data(), size_of::<RecordFileHeader>());
+        }
+        if let Err(e) = self.pfh.unpin_dirty_page(self.header_num) {
+            dbg!(&e);
+            return Err(e);
+        }
+        self.pfh.close()
+    }
+
+    //number of live records in the whole file.
+    pub fn record_count(&mut self) -> Result<usize, Error> {
+        Ok(self.header.total_records)
+    }
+
+    //number of live records in a specific page.
+    pub fn page_record_count(&mut self, page_num: u32) -> Result<usize, Error> {
+        let _latch = LockManager::acquire_shared(&self.locks, page_num)?;
+        let ph = match self.pfh.get_page(page_num) {
+            Err(e) => {
+                dbg!(&e);
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+        let rph = unsafe {
+            & *(ph.get_data() as *const RecordPageHeader)
+        };
+        let num_records = rph.num_records;
+
+        match self.pfh.unpin_page(page_num) {
+            Ok(_) => Ok(num_records),
+            Err(e) => Err(e)
+        }
+    }
+
+    /*
+     * Count used and free slots of a page from its bitmap.
+     * The page is only read, so it's unpinned clean.
+     */
+    pub fn page_stats(&mut self, page_num: u32) -> Result<PageStats, Error> {
+        let _latch = LockManager::acquire_shared(&self.locks, page_num)?;
+        let ph = self.pfh.get_page(page_num)?;
+        debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+
+        let mut used_slots = 0;
+        let mut slot = 0;
+        while let Some(slot_num) = self.next_occupied_slot(ph.get_data(), slot) {
+            used_slots += 1;
+            slot = slot_num + 1;
+        }
+        self.pfh.unpin_page(page_num)?;
+
+        let total_slots = self.header.num_records_per_page;
+        Ok(PageStats {
+            total_slots,
+            used_slots,
+            free_slots: total_slots - used_slots
+        })
+    }
+
+    /*
+     * Number of records that can be inserted before a new page is 
+     * allocated, summed over all record pages.
+     * Only num_records in the page header is read, not the bitmap.
+     */
+    pub fn count_free_slots_total(&mut self) -> Result<usize, Error> {
+        let mut free_slots = 0;
+        for i in 1..=self.header.num_pages {
+            let page_num = self.pfh.get_page_num(i);
+            let _latch = LockManager::acquire_shared(&self.locks, page_num)?;
+            let ph = self.pfh.get_page(page_num)?;
+            if ph.get_page_type() == PAGE_TYPE_RECORD {
+                let rph = unsafe {
+                    & *(ph.get_data() as *const RecordPageHeader)
+                };
+                free_slots += self.header.num_records_per_page - rph.num_records;
+            }
+            self.pfh.unpin_page(page_num)?;
+        }
+        Ok(free_slots)
+    }
+
+    /*
+     * Check that for every record page, the number of set bits in the 
+     * bitmap equals to num_records in the page header.
+     * Only meant for debugging and tests, as every page is read.
+     */
+    pub fn verify_bitmap_consistency(&mut self) -> Result<(), Error> {
+        let (bitmap_offset, bitmap_size) = (self.header.bitmap_offset, self.header.bitmap_size);
+        let num_slots = self.header.num_records_per_page;
+        let mut res = Ok(());
+        self.for_each_page(|page_num, data, rph, _| {
+            let bitmap = &data[bitmap_offset..bitmap_offset+bitmap_size];
+            let bitmap_count = (0..num_slots).filter(|&i| bitmap[i/8] & (1<<(7 - i%8)) != 0).count();
+            if bitmap_count != rph.num_records {
+                res = Err(Error::BitmapInconsistency { page_num, bitmap_count, header_count: rph.num_records });
+                return false;
+            }
+            true
+        })?;
+        res
+    }
+
+    /*
+     * Visit every record page with its page data and page header, 
+     * is_free tells if the page is in the free list.
+     * Pages are pinned one at a time and unpinned clean after f 
+     * returns, f returns false to stop early.
+     * Returns the number of pages visited.
+     */
+    pub fn for_each_page<F>(&mut self, mut f: F) -> Result<usize, Error>
+        where F: FnMut(u32, &[u8], &RecordPageHeader, bool) -> bool {
+        let mut free_pages = Vec::new();
+        let mut curr = self.free;
+        while curr != 0 {
+            let _latch = LockManager::acquire_shared(&self.locks, curr)?;
+            let ph = self.pfh.get_page(curr)?;
+            let next_free = unsafe {
+                (*(ph.get_data() as *const RecordPageHeader)).next_free
+            };
+            self.pfh.unpin_page(curr)?;
+            free_pages.push(curr);
+            curr = next_free;
+        }
+
+        let mut visited = 0;
+        for i in 1..=self.header.num_pages {
+            let page_num = self.pfh.get_page_num(i);
+            let _latch = LockManager::acquire_shared(&self.locks, page_num)?;
+            let ph = self.pfh.get_page(page_num)?;
+            if ph.get_page_type() != PAGE_TYPE_RECORD {
+                self.pfh.unpin_page(page_num)?;
                 continue;
             }
 
-            match self.find_free_slot(ph.get_data()) {
-                Ok(v) => {
-                    slot_num = v;
-                    flag = false;
-                },
-                Err(e) => {
-                    dbg!(&e);
-                    self.pfh.unpin_dirty_page(ph.get_page_num());
-                    return Err(Error::FindFreeSlotError);
-                }
+            let data = unsafe {
+                std::slice::from_raw_parts(ph.get_data() as *const u8, PAGE_SIZE)
+            };
+            let rph = unsafe {
+                & *(ph.get_data() as *const RecordPageHeader)
+            };
+            let go_on = f(page_num, data, rph, free_pages.contains(&page_num));
+            self.pfh.unpin_page(page_num)?;
+            visited += 1;
+            if !go_on {
+                break;
             }
         }
+        Ok(visited)
+    }
 
-        if self.free == 0 {
-            ph = match self.pfh.allocate_page() {
-                Ok(v) => v,
+    /*
+     * Delete a batch of records.
+     * RIDs are grouped by page, so every page is pinned and unpinned
+     * only once. RIDs of empty slots are skipped.
+     * Returns the number of records deleted.
+     */
+    pub fn batch_delete(&mut self, rids: &[RID]) -> Result<usize, Error> {
+        let mut pages: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for rid in rids.iter() {
+            pages.entry(rid.get_page_num()).or_default().push(rid.get_slot_num());
+        }
+
+        let mut total = 0;
+        for (page_num, slots) in pages.iter() {
+            let _latch = LockManager::acquire_exclusive(&self.locks, *page_num)?;
+            let ph = match self.pfh.get_page(*page_num) {
                 Err(e) => {
                     dbg!(&e);
                     return Err(e);
-                }
-            };
-            new_page = true;
-            self.free = ph.get_page_num();
-            //when we find a free slot, the bit corresponding to the slot is set.
-            //so we don't need to set bitmap again.
-            match self.find_free_slot(ph.get_data()) {
-                Ok(v) => {
-                    slot_num = v;
                 },
-                Err(e) => {
-                    self.pfh.unpin_dirty_page(ph.get_page_num());
-                    return Err(Error::FindFreeSlotError);
+                Ok(v) => v
+            };
+            let data = ph.get_data();
+            let bitmap = utils::get_arr_mut::<u8>(data, self.header.bitmap_offset, self.header.bitmap_size);
+
+            let mut cleared = 0;
+            for &slot in slots.iter() {
+                if slot >= self.header.num_records_per_page {
+                    continue;
+                }
+                let mask: u8 = 1<<(7 - slot%8);
+                if bitmap[slot/8] & mask == 0 {
+                    continue;
                 }
+                bitmap[slot/8] &= !mask;
+                unsafe {
+                    std::ptr::write_bytes(data.offset(self.get_record_offset(slot)), 0, self.header.record_size);
+                    utils::page_data_slice(data, self.get_null_bitmap_offset(slot), self.header.null_bitmap_size).fill(0);
+                }
+                cleared += 1;
+            }
+
+            if cleared == 0 {
+                self.pfh.unpin_page(*page_num)?;
+                continue;
             }
+
+            let rph = unsafe {
+                &mut *(data as *mut RecordPageHeader)
+            };
+            if rph.num_records == self.header.num_records_per_page {
+                rph.next_free = self.free;
+                self.set_free(*page_num);
+            }
+            rph.num_records -= cleared;
+            self.header.total_records -= cleared;
+            total += cleared;
+
+            self.pfh.unpin_dirty_page(*page_num)?;
         }
+        Ok(total)
+    }
+
+    /*
+     * Insert a record and returns its rid.
+     * Choose a slot in the next_free page, if next_free = 0 or it's full,
+     * allocate a new page and let next_free = new page num;
+     */
+    pub fn insert_record(&mut self, data: *mut u8) -> Result<RID, Error> {
+        self.insert_record_inner(data, None)
+    }
+
+    /*
+     * Insert a record with its null bitmap, the file must be created 
+     * with nullable columns. A record inserted by insert_record has 
+     * no null columns.
+     */
+    pub fn insert_record_with_nulls(&mut self, data: &[u8], null_bitmap: &[u8]) -> Result<RID, Error> {
+        if data.len() != self.header.record_size || null_bitmap.len() != self.header.null_bitmap_size || null_bitmap.is_empty() {
+            dbg!((data.len(), null_bitmap.len()));
+            return Err(Error::InvalidRecordSize);
+        }
+        self.insert_record_inner(data.as_ptr() as *mut u8, Some(null_bitmap))
+    }
+
+    fn insert_record_inner(&mut self, data: *mut u8, null_bitmap: Option<&[u8]>) -> Result<RID, Error> {
+        let ph = self.get_free_page().context("getting free page to insert record")?;
+        debug_assert!(matches!(self.pfh.page_is_disposed(ph.get_page_num()), Ok(false)));
+        let _latch = match LockManager::acquire_exclusive(&self.locks, ph.get_page_num()) {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = self.pfh.unpin_page(ph.get_page_num());
+                return Err(e.into());
+            }
+        };
+
+        //when we find a free slot, the bit corresponding to the slot is set.
+        //so we don't need to set bitmap again.
+        let slot_num = match self.find_free_slot(ph.get_data()) {
+            Ok(v) => v,
+            Err(e) => {
+                dbg!(&e);
+                let _ = self.pfh.unpin_dirty_page(ph.get_page_num());
+                return Err(Error::FindFreeSlotError);
+            }
+        };
 
         let record_ptr = unsafe {
             ph.get_data().offset(self.get_record_offset(slot_num))
@@ -253,24 +885,148 @@ impl RecordFileHandle {
         unsafe {
             std::ptr::copy(data, record_ptr, self.header.record_size);
         }
+        //null bitmaps are cleared on deletion, so a slot has no nulls unless they're given.
+        if let Some(null_bitmap) = null_bitmap {
+            unsafe {
+                utils::page_data_slice(ph.get_data(), self.get_null_bitmap_offset(slot_num), self.header.null_bitmap_size).copy_from_slice(null_bitmap);
+            }
+        }
 
         let rph = unsafe {
             &mut *(ph.get_data() as *mut RecordPageHeader)
         };
-        
-        if new_page {
-            rph.num_records = 1;
-        } else {
-            rph.num_records += 1;
+        rph.num_records += 1;
+        self.header.total_records += 1;
+        if rph.num_records == self.header.num_records_per_page {
+            self.set_free(rph.next_free);
+            rph.next_free = 0;
         }
 
-        match self.pfh.unpin_dirty_page(ph.get_page_num()) {
-            Ok(_) => Ok(RID {
-                page_num: ph.get_page_num(),
-                slot_num: slot_num
-            }),
-            Err(e) => Err(e)
+        self.pfh.unpin_dirty_page(ph.get_page_num()).context("unpinning page of inserted record")?;
+        Ok(RID::new(ph.get_page_num(), slot_num))
+    }
+
+    /*
+     * Insert a batch of records.
+     * Records are packed into the current free page until it fills, 
+     * so a page is pinned and unpinned only once for all records it 
+     * receives. The returned RIDs are in the same order as records.
+     */
+    pub fn bulk_insert(&mut self, records: &[&[u8]]) -> Result<Vec<RID>, Error> {
+        for rec in records.iter() {
+            if rec.len() != self.header.record_size {
+                dbg!(rec.len());
+                return Err(Error::InvalidRecordSize);
+            }
+        }
+
+        let mut rids: Vec<RID> = Vec::with_capacity(records.len());
+        let mut i = 0;
+        while i < records.len() {
+            let ph = match self.get_free_page() {
+                Ok(v) => v,
+                Err(e) => {
+                    dbg!(&e);
+                    return Err(e);
+                }
+            };
+            let _latch = match LockManager::acquire_exclusive(&self.locks, ph.get_page_num()) {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = self.pfh.unpin_page(ph.get_page_num());
+                    return Err(e.into());
+                }
+            };
+            let rph = unsafe {
+                &mut *(ph.get_data() as *mut RecordPageHeader)
+            };
+
+            while i < records.len() && rph.num_records < self.header.num_records_per_page {
+                let slot_num = match self.find_free_slot(ph.get_data()) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        dbg!(&e);
+                        let _ = self.pfh.unpin_dirty_page(ph.get_page_num());
+                        return Err(Error::FindFreeSlotError);
+                    }
+                };
+
+                unsafe {
+                    let record_ptr = ph.get_data().offset(self.get_record_offset(slot_num));
+                    std::ptr::copy(records[i].as_ptr(), record_ptr, self.header.record_size);
+                }
+                rph.num_records += 1;
+                self.header.total_records += 1;
+
+                rids.push(RID::new(ph.get_page_num(), slot_num));
+                i += 1;
+            }
+            if rph.num_records == self.header.num_records_per_page {
+                self.set_free(rph.next_free);
+                rph.next_free = 0;
+            }
+
+            if let Err(e) = self.pfh.unpin_dirty_page(ph.get_page_num()) {
+                dbg!(&e);
+                return Err(e);
+            }
+        }
+        Ok(rids)
+    }
+
+    /*
+     * Get a pinned page with at least one free slot.
+     * The page returned is always the head of the free list, 
+     * callers remove it from the list once it's full.
+     * Full pages met in the free list are removed from the list.
+     * If the free list is empty, a new page is allocated and becomes 
+     * the head of the free list.
+     */
+    fn get_free_page(&mut self) -> Result<PageHandle, Error> {
+        while self.free != 0 {
+            let _latch = LockManager::acquire_exclusive(&self.locks, self.free)?;
+            let ph = match self.pfh.get_page(self.free) {
+                Err(e) => {
+                    return Err(e);
+                },
+                Ok(v) => v
+            };
+            debug_assert_eq!(ph.get_page_type(), PAGE_TYPE_RECORD);
+            let rph = unsafe {
+                &mut *(ph.get_data() as *mut RecordPageHeader)
+            };
+            if rph.num_records > self.header.num_records_per_page {
+                dbg!(&rph.num_records);
+                panic!("more records in page 0x{:08X} than a page holds", ph.get_page_num());
+            }
+            if rph.num_records < self.header.num_records_per_page {
+                return Ok(ph);
+            }
+
+            self.set_free(rph.next_free);
+            rph.next_free = 0;
+            self.pfh.unpin_dirty_page(ph.get_page_num())?;
         }
+
+        let ph = match self.pfh.allocate_page() {
+            Ok(v) => v,
+            Err(e) => {
+                dbg!(&e);
+                return Err(e);
+            }
+        };
+        self.header.num_pages += 1;
+        self.set_free(ph.get_page_num());
+        ph.set_page_type(PAGE_TYPE_RECORD);
+
+        //allocate_page already zeroes the page, the header is reset 
+        //anyway to make the initial state explicit.
+        let rph = unsafe {
+            &mut *(ph.get_data() as *mut RecordPageHeader)
+        };
+        rph.num_records = 0;
+        rph.next_free = 0;
+        Ok(ph)
     }
 
     //set a bit in the bitmap accroding to a slot_num, 
@@ -278,15 +1034,14 @@ impl RecordFileHandle {
     //An error is returned if the bit is already set or unset.
     fn set_bitmap(&mut self, slot: usize, data: *mut u8, set: bool) -> Result<(), RecordError> {
         let bitmap = unsafe {
-            let p = data.offset(self.header.bitmap_offset as isize);
-            std::slice::from_raw_parts_mut(p, self.header.bitmap_size)
+            utils::page_data_slice(data, self.header.bitmap_offset, self.header.bitmap_size)
         };
-        let moder = slot/8;
-        let remainder = slot - moder * 8;
-        let num = &mut bitmap[moder];
-        let bit: u8 = *num & ((1 as u8)<<(7-remainder));
+        let bit_offset = slot % 8;
+        let mask: u8 = 1u8 << (7 - bit_offset);
+        let num = &mut bitmap[slot/8];
+        let bit: u8 = *num & mask;
         
-        if set && bit == 1 {
+        if set && bit != 0 {
             return Err(RecordError::BitSet);
         }
         if !set && bit == 0 {
@@ -294,11 +1049,9 @@ impl RecordFileHandle {
         }
 
         if set {
-            *num |= ((1 as u8)<<(7-remainder));
+            *num |= mask;
         } else {
-            let max: u8 = 0xff;
-            let temp: u8 = max ^ ((1 as u8)<<(7-remainder));
-            *num &= temp;
+            *num &= !mask;
         }
 
         Ok(())
@@ -306,23 +1059,461 @@ impl RecordFileHandle {
 
     fn find_free_slot(&self, data: *mut u8) -> Result<usize, RecordError> {
         let bitmap = unsafe {
-            let p = data.offset(self.header.bitmap_offset as isize);
-            std::slice::from_raw_parts_mut(p, self.header.bitmap_size)
+            utils::page_data_slice(data, self.header.bitmap_offset, self.header.bitmap_size)
         };
 
         for i in 0..(self.header.num_records_per_page) {
             let index: usize = i/8;
             let offset = (i - index*8) as u8;
             if bitmap[index] & (1<<(7-offset)) == 0 {
-                bitmap[index] += (1<<(7-offset));
+                bitmap[index] |= 1<<(7-offset);
                 return Ok(i);
             }
         }
         Err(RecordError::FullPage)
     }
 
+    //whether a slot in a page holds a record, slot must be less than num_records_per_page.
+    fn slot_is_occupied(&self, data: *mut u8, slot: usize) -> bool {
+        let bitmap = utils::get_arr::<u8>(data, self.header.bitmap_offset, self.header.bitmap_size);
+        bitmap[slot/8] & (1<<(7 - slot%8)) != 0
+    }
+
+    //find the first occupied slot in a page starting from the start slot.
+    fn next_occupied_slot(&self, data: *mut u8, start: usize) -> Option<usize> {
+        let bitmap = utils::get_arr::<u8>(data, self.header.bitmap_offset, self.header.bitmap_size);
+
+        (start..(self.header.num_records_per_page)).find(|&i| bitmap[i/8] & (1<<(7 - i%8)) != 0)
+    }
+
+    //the offset of the null bitmap of a slot in a page.
+    fn get_null_bitmap_offset(&self, slot: usize) -> usize {
+        self.header.null_bitmap_offset + slot * self.header.null_bitmap_size
+    }
+
     //the offset of a specific record in a page.
     fn get_record_offset(&self, slot: usize) -> isize {
         (self.header.records_offset + slot*self.header.record_size) as isize
     }
 }
+
+impl<'a> RecordScan<'a> {
+    /*
+     * Find the next live record that satisfies pred.
+     * pred is called with a slice directly into the pinned page, 
+     * only when it returns true, the record bytes are copied.
+     */
+    fn next_matching<F>(&mut self, pred: &F) -> Option<Result<(RID, Record), Error>> 
+        where F: Fn(&[u8]) -> bool {
+        let rfh = &mut *self.rfh;
+        while self.page_cursor <= rfh.header.num_pages {
+            let page_num = rfh.pfh.get_page_num(self.page_cursor);
+            let _latch = match LockManager::acquire_shared(&rfh.locks, page_num) {
+                Err(e) => {
+                    self.page_cursor = rfh.header.num_pages + 1;
+                    return Some(Err(e.into()));
+                },
+                Ok(v) => v
+            };
+            let ph = match rfh.pfh.get_page(page_num) {
+                Err(e) => {
+                    //stop the scan after the error is returned.
+                    self.page_cursor = rfh.header.num_pages + 1;
+                    return Some(Err(e));
+                },
+                Ok(v) => v
+            };
+            let data = ph.get_data();
+
+            let mut res = None;
+            //disposed pages are skipped.
+            if ph.get_page_type() == PAGE_TYPE_RECORD {
+                let mut slot = self.slot_cursor;
+                while let Some(slot_num) = rfh.next_occupied_slot(data, slot) {
+                    let offset = rfh.get_record_offset(slot_num) as usize;
+                    let record = utils::get_arr::<u8>(data, offset, rfh.header.record_size);
+                    if pred(record) {
+                        let mut buffer = utils::allocate_buffer_vec(rfh.header.record_size);
+                        buffer.copy_from_slice(record);
+                        let rid = RID::new(page_num, slot_num);
+                        res = Some((rid, Record::new(rfh.header.record_size, rid, buffer)));
+                        break;
+                    }
+                    slot = slot_num + 1;
+                }
+            }
+
+            if let Err(e) = rfh.pfh.unpin_page(page_num) {
+                self.page_cursor = rfh.header.num_pages + 1;
+                return Some(Err(e));
+            }
+
+            match res {
+                None => {
+                    self.page_cursor += 1;
+                    self.slot_cursor = 0;
+                },
+                Some((rid, rec)) => {
+                    self.slot_cursor = rid.get_slot_num() + 1;
+                    return Some(Ok((rid, rec)));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Drop for RecordFileHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("close record file error");
+            dbg!(&e);
+        }
+    }
+}
+
+impl<'a> Iterator for RecordScan<'a> {
+    type Item = Result<(RID, Record), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_matching(&|_: &[u8]| true)
+    }
+}
+
+impl<'a, T: Pod> Iterator for RecordTypedScan<'a, T> {
+    type Item = Result<(RID, T), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next()? {
+            Err(e) => Some(Err(e)),
+            Ok((rid, rec)) => {
+                let data_ptr = rec.get_data().as_ptr();
+                //T is Pod, so any bytes of the record are a valid T.
+                let val = unsafe {
+                    std::ptr::read_unaligned(data_ptr as *const T)
+                };
+                Some(Ok((rid, val)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::errors::{Error, LockError, RecordError};
+    use crate::lock_manager::LockManager;
+    use crate::test_utils::make_test_pfm;
+    use crate::page_management::page_file::PAGE_SIZE;
+    use crate::record_management::record_file_manager::RecordFileManager;
+    use super::{RecordFileHandle, RID};
+    use crate::utils;
+
+    //with every other slot occupied, find_free_slot has to set exactly the bit of the free slot.
+    #[test]
+    fn find_free_slot_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("find_free_slot").to_str().unwrap().to_string();
+        let rfh = RecordFileManager::create_file(&name, &mut pfm, 100).unwrap();
+        let header = *rfh.get_header();
+        assert!(header.num_records_per_page > 16);
+
+        let mut page = vec![0u8; PAGE_SIZE];
+        for &slot in [0usize, 7, 8, 15].iter() {
+            let bitmap_offset = header.bitmap_offset;
+            for b in page[bitmap_offset..bitmap_offset+header.bitmap_size].iter_mut() {
+                *b = 0xff;
+            }
+            page[bitmap_offset + slot/8] &= !(1<<(7 - slot%8));
+
+            assert_eq!(rfh.find_free_slot(page.as_mut_ptr()).unwrap(), slot);
+            assert!(page[bitmap_offset..bitmap_offset+header.bitmap_size].iter().all(|&b| b == 0xff));
+        }
+    }
+
+    #[test]
+    fn set_bitmap_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("set_bitmap").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 100).unwrap();
+        let header = *rfh.get_header();
+        assert!(header.num_records_per_page > 24);
+
+        let mut page = vec![0u8; PAGE_SIZE];
+        let bitmap_offset = header.bitmap_offset;
+        for slot in 0..24 {
+            rfh.set_bitmap(slot, page.as_mut_ptr(), true).unwrap();
+            for i in 0..header.bitmap_size {
+                let expected = if i == slot/8 { 1u8 << (7 - slot%8) } else { 0 };
+                assert_eq!(page[bitmap_offset + i], expected, "slot {}", slot);
+            }
+            assert!(matches!(rfh.set_bitmap(slot, page.as_mut_ptr(), true), Err(RecordError::BitSet)));
+
+            rfh.set_bitmap(slot, page.as_mut_ptr(), false).unwrap();
+            assert!(page[bitmap_offset..bitmap_offset+header.bitmap_size].iter().all(|&b| b == 0));
+            assert!(matches!(rfh.set_bitmap(slot, page.as_mut_ptr(), false), Err(RecordError::BitUnset)));
+        }
+    }
+
+    #[test]
+    fn compare_and_swap_record_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("compare_and_swap_record").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+
+        let mut old = [1u8; 8];
+        let new = [2u8; 8];
+        let rid = rfh.insert_record(old.as_mut_ptr()).unwrap();
+        assert!(rfh.compare_and_swap_record(&rid, &old, &new).unwrap());
+        assert_eq!(rfh.get_record(&rid).unwrap().get_data(), &new[..]);
+
+        //the record is no longer old, so it's left alone.
+        assert!(!rfh.compare_and_swap_record(&rid, &old, &[3u8; 8]).unwrap());
+        assert_eq!(rfh.get_record(&rid).unwrap().get_data(), &new[..]);
+        assert!(matches!(rfh.compare_and_swap_record(&rid, &new, &[3u8; 4]), Err(Error::InvalidRecordSize)));
+
+        //slots out of the page and deleted records are rejected.
+        let bad_rid = RID::new(rid.get_page_num(), 100000);
+        assert!(matches!(rfh.compare_and_swap_record(&bad_rid, &new, &old), Err(Error::Record(RecordError::InvalidSlotNumber))));
+        rfh.delete_record(&rid).unwrap();
+        assert!(matches!(rfh.compare_and_swap_record(&rid, &[0u8; 8], &old), Err(Error::Record(RecordError::RecordDeleted))));
+        assert_eq!(rfh.pfh.pin_count_of(rid.get_page_num()), Some(0));
+    }
+
+    //the old data is returned, and bad slots are rejected before the page is touched.
+    #[test]
+    fn update_record_ex_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("update_record_ex").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+
+        let mut data = [1u8; 8];
+        let rid = rfh.insert_record(data.as_mut_ptr()).unwrap();
+        assert_eq!(rfh.update_record_ex(&rid, &[2u8; 8]).unwrap(), vec![1u8; 8]);
+        assert_eq!(rfh.get_record(&rid).unwrap().get_data(), &[2u8; 8]);
+
+        let bad_rid = RID::new(rid.get_page_num(), 100000);
+        assert!(matches!(rfh.update_record_ex(&bad_rid, &[3u8; 8]), Err(Error::Record(RecordError::InvalidSlotNumber))));
+        let free_rid = RID::new(rid.get_page_num(), rid.get_slot_num() + 1);
+        assert!(matches!(rfh.update_record_ex(&free_rid, &[3u8; 8]), Err(Error::Record(RecordError::RecordDeleted))));
+        assert_eq!(rfh.pfh.pin_count_of(rid.get_page_num()), Some(0));
+        assert_eq!(rfh.record_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn migrate_record_size_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("migrate_record_size").to_str().unwrap().to_string();
+        let new_name = format!("{}_new", name);
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+
+        for i in 0..100u8 {
+            let mut data = [i; 8];
+            rfh.insert_record(data.as_mut_ptr()).unwrap();
+        }
+        let mut new_rfh = rfh.migrate_record_size(12, &mut pfm, &new_name).unwrap();
+        assert_eq!(new_rfh.get_header().record_size, 12);
+        assert_eq!(new_rfh.record_count().unwrap(), 100);
+        let mut seen: Vec<u8> = new_rfh.scan().map(|res| {
+            let (_, rec) = res.unwrap();
+            let data = rec.get_data();
+            assert!(data[..8].iter().all(|&b| b == data[0]));
+            assert!(data[8..].iter().all(|&b| b == 0));
+            data[0]
+        }).collect();
+        seen.sort();
+        assert_eq!(seen, (0..100u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn null_bitmap_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("null_bitmap").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file_nullable(&name, &mut pfm, 16, 10).unwrap();
+        let header = *rfh.get_header();
+        assert_eq!(header.null_bitmap_size, 2);
+        assert_eq!(header.bitmap_offset, header.null_bitmap_offset + header.num_records_per_page * 2);
+        assert!(header.records_offset >= header.bitmap_offset + header.bitmap_size);
+        assert!(header.records_offset + header.num_records_per_page * 16 <= PAGE_SIZE);
+
+        let data = [7u8; 16];
+        assert!(matches!(rfh.insert_record_with_nulls(&data, &[0u8; 1]), Err(Error::InvalidRecordSize)));
+        let mut rids = Vec::new();
+        for i in 0..100u8 {
+            rids.push(rfh.insert_record_with_nulls(&data, &[i, 0x80]).unwrap());
+        }
+        for (i, rid) in rids.iter().enumerate() {
+            let (rec, nulls) = rfh.get_record_with_nulls(rid).unwrap();
+            assert_eq!(rec.get_data(), &data);
+            assert_eq!(nulls, vec![i as u8, 0x80]);
+            assert!(utils::is_null(&nulls, 8));
+            assert!(!utils::is_null(&nulls, 9));
+            assert_eq!(utils::is_null(&nulls, 7), i % 2 == 1);
+        }
+
+        //the slot is reused without the nulls of the deleted record.
+        rfh.delete_record(&rids[3]).unwrap();
+        let mut data = data;
+        let rid = rfh.insert_record(data.as_mut_ptr()).unwrap();
+        assert_eq!(rid, rids[3]);
+        assert_eq!(rfh.get_record_with_nulls(&rid).unwrap().1, vec![0, 0]);
+
+        //bad rids are rejected before anything in the page is cleared.
+        let page_num = rids[0].get_page_num();
+        assert!(matches!(rfh.delete_record(&RID::new(page_num, 100000)), Err(Error::Record(RecordError::InvalidSlotNumber))));
+        let free_slot = header.num_records_per_page - 1;
+        assert!(matches!(rfh.delete_record(&RID::new(page_num, free_slot)), Err(Error::SetBitmapError)));
+        assert_eq!(rfh.get_record_with_nulls(&rids[4]).unwrap().1, vec![4, 0x80]);
+        assert_eq!(rfh.record_count().unwrap(), 100);
+        rfh.verify_bitmap_consistency().unwrap();
+    }
+
+    #[test]
+    fn batch_delete_null_bitmap_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("batch_delete_nulls").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file_nullable(&name, &mut pfm, 16, 10).unwrap();
+
+        let data = [7u8; 16];
+        let rids: Vec<RID> = (0..10).map(|_| rfh.insert_record_with_nulls(&data, &[0xff, 0xc0]).unwrap()).collect();
+        assert_eq!(rfh.batch_delete(&rids).unwrap(), 10);
+
+        //the slots are reused without the nulls of the deleted records.
+        let mut data = data;
+        for _ in 0..10 {
+            let rid = rfh.insert_record(data.as_mut_ptr()).unwrap();
+            assert!(rids.contains(&rid));
+            assert_eq!(rfh.get_record_with_nulls(&rid).unwrap().1, vec![0, 0]);
+        }
+        rfh.verify_bitmap_consistency().unwrap();
+    }
+
+    #[test]
+    fn copy_nullable_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name_of = |n: &str| dir.path().join(n).to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file_nullable(&name_of("nullable"), &mut pfm, 8, 10).unwrap();
+        let mut copy_rfh = RecordFileManager::create_file_nullable(&name_of("nullable_copy"), &mut pfm, 8, 10).unwrap();
+        let mut plain_rfh = RecordFileManager::create_file(&name_of("plain"), &mut pfm, 8).unwrap();
+
+        for i in 0..100u8 {
+            rfh.insert_record_with_nulls(&[i; 8], &[i, 0x40]).unwrap();
+        }
+        //a file without null bitmaps can't hold the nulls.
+        assert!(matches!(rfh.copy_to(&mut plain_rfh), Err(Error::RecordSizeMismatch)));
+        assert_eq!(plain_rfh.record_count().unwrap(), 0);
+
+        let check = |rfh: &mut RecordFileHandle, record_size: usize| {
+            let rids: Vec<RID> = rfh.scan().map(|res| res.unwrap().0).collect();
+            assert_eq!(rids.len(), 100);
+            for rid in rids.iter() {
+                let (rec, nulls) = rfh.get_record_with_nulls(rid).unwrap();
+                assert_eq!(rec.get_data().len(), record_size);
+                assert_eq!(nulls, vec![rec.get_data()[0], 0x40]);
+            }
+        };
+        assert_eq!(rfh.copy_to(&mut copy_rfh).unwrap(), 100);
+        check(&mut copy_rfh, 8);
+
+        let mut new_rfh = rfh.migrate_record_size(12, &mut pfm, &name_of("nullable_new")).unwrap();
+        assert_eq!(new_rfh.get_header().null_bitmap_size, 2);
+        check(&mut new_rfh, 12);
+    }
+
+    #[test]
+    fn copy_to_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("copy_to").to_str().unwrap().to_string();
+        let (copy_name, bad_name) = (format!("{}_copy", name), format!("{}_bad", name));
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+        let mut copy_rfh = RecordFileManager::create_file(&copy_name, &mut pfm, 8).unwrap();
+        let mut bad_rfh = RecordFileManager::create_file(&bad_name, &mut pfm, 12).unwrap();
+
+        for i in 0..100u8 {
+            let mut data = [i; 8];
+            rfh.insert_record(data.as_mut_ptr()).unwrap();
+        }
+        assert!(matches!(rfh.copy_to(&mut bad_rfh), Err(Error::RecordSizeMismatch)));
+        assert_eq!(bad_rfh.record_count().unwrap(), 0);
+
+        assert_eq!(rfh.copy_to(&mut copy_rfh).unwrap(), 100);
+        let mut seen: Vec<u8> = copy_rfh.scan().map(|res| {
+            let (_, rec) = res.unwrap();
+            let data = rec.get_data();
+            assert!(data.iter().all(|&b| b == data[0]));
+            data[0]
+        }).collect();
+        seen.sort();
+        assert_eq!(seen, (0..100u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rebuild_index_test() {
+        use crate::indexing::{AttrType, file_manager::IndexFileManager};
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("rebuild_index").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 12).unwrap();
+
+        //records are id | key | padding, keys repeat every 10 records.
+        let mut rids = Vec::new();
+        for i in 0..100i32 {
+            let mut data = [0u8; 12];
+            data[..4].copy_from_slice(&i.to_ne_bytes());
+            data[4..8].copy_from_slice(&(i % 10).to_ne_bytes());
+            rids.push(rfh.insert_record(data.as_mut_ptr()).unwrap());
+        }
+
+        let mut ih = IndexFileManager::create_file(&format!("{}.idx", name), 0, &mut pfm, AttrType::INT, 4).unwrap();
+        assert!(matches!(rfh.rebuild_index(10, 4, &mut ih), Err(Error::InvalidKeyRange)));
+        assert_eq!(rfh.rebuild_index(4, 4, &mut ih).unwrap(), 100);
+        let key = 3i32;
+        let mut found = ih.get_all_rids_for_key(&key as *const i32 as *const u8).unwrap();
+        found.sort();
+        let expected: Vec<_> = rids.iter().enumerate().filter(|(i, _)| i % 10 == 3).map(|(_, rid)| *rid).collect();
+        assert_eq!(found, expected);
+    }
+
+    //handles of the same file latch through one lock table.
+    #[test]
+    fn shared_latch_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("shared_latch").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+        let mut data = [7u8; 8];
+        let rid = rfh.insert_record(data.as_mut_ptr()).unwrap();
+        rfh.close().unwrap();
+
+        let rfh1 = RecordFileManager::open_file(&name, &mut pfm, 8).unwrap();
+        let mut rfh2 = RecordFileManager::open_file(&name, &mut pfm, 8).unwrap();
+        assert!(Rc::ptr_eq(&rfh1.locks, &rfh2.locks));
+
+        {
+            let _latch = LockManager::acquire_exclusive(&rfh1.locks, rid.get_page_num()).unwrap();
+            assert!(matches!(rfh2.get_record(&rid), Err(Error::Lock(LockError::Conflict))));
+            assert!(matches!(rfh2.batch_delete(&[rid]), Err(Error::Lock(LockError::Conflict))));
+            assert!(matches!(rfh2.scan().next(), Some(Err(Error::Lock(LockError::Conflict)))));
+        }
+        {
+            let _latch = LockManager::acquire_shared(&rfh1.locks, rid.get_page_num()).unwrap();
+            assert_eq!(rfh2.get_record(&rid).unwrap().get_data(), &data[..]);
+            assert!(matches!(rfh2.batch_delete(&[rid]), Err(Error::Lock(LockError::Conflict))));
+        }
+        assert_eq!(rfh2.batch_delete(&[rid]).unwrap(), 1);
+    }
+
+    //records are read as arrays of integers, a type of another size is rejected.
+    #[test]
+    fn scan_typed_test() {
+        let (mut pfm, dir) = make_test_pfm();
+        let name = dir.path().join("scan_typed").to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&name, &mut pfm, 8).unwrap();
+        for i in 0..10u32 {
+            let mut row = [i, i * 2];
+            rfh.insert_record(row.as_mut_ptr() as *mut u8).unwrap();
+        }
+        let rows: Vec<[u32; 2]> = rfh.scan_typed::<[u32; 2]>().unwrap().map(|r| r.unwrap().1).collect();
+        assert_eq!(rows.len(), 10);
+        assert!(rows.iter().all(|r| r[1] == r[0] * 2));
+        assert!(matches!(rfh.scan_typed::<u32>(), Err(Error::InvalidRecordSize)));
+    }
+}
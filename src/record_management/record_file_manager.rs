@@ -7,15 +7,11 @@
   > Copyright@ https://github.com/xiaoqixian
  **********************************************/
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::fs::OpenOptions;
 use std::mem::size_of;
-use std::{println as info, println as debug, println as warn, println as error};
-use std::os::unix::fs::FileExt;
 
-use crate::page_management::page_file::{PageFileHandle, PageHandle, PageFileManager, PAGE_SIZE};
-use crate::errors::{RecordError, Error};
+use crate::page_management::page_file::{PageFileManager, PAGE_SIZE};
+use crate::errors::Error;
+use crate::utils;
 use super::record_file_handle::{RecordFileHeader, RecordFileHandle, RecordPageHeader};
 
 
@@ -31,8 +27,28 @@ pub struct RecordFileManager {
 }
 
 impl RecordFileManager {
-    fn calc_num_records_per_page(record_size: usize) -> usize {
-        8*PAGE_SIZE/(8*record_size + 1)
+    /*
+     * Each record takes record_size bytes, its null bitmap and a bit 
+     * in the bitmap, which gives the first guess. Then the page header
+     * and the padding before records may take a few more slots off.
+     */
+    fn calc_num_records_per_page(record_size: usize, null_bitmap_size: usize) -> usize {
+        let mut num_records = 8*PAGE_SIZE/(8*(record_size + null_bitmap_size) + 1);
+        while num_records > 0 && Self::calc_records_offset(num_records, null_bitmap_size) + num_records * record_size > PAGE_SIZE {
+            num_records -= 1;
+        }
+        num_records
+    }
+
+    /*
+     * Records start right after the bitmap, aligned to usize so that 
+     * fields of a record are aligned as long as the record size is a 
+     * multiple of their alignment.
+     * The offset is relative to the page data.
+     */
+    fn calc_records_offset(num_records_per_page: usize, null_bitmap_size: usize) -> usize {
+        let bitmap_offset = size_of::<RecordPageHeader>() + num_records_per_page * null_bitmap_size;
+        utils::align_up(bitmap_offset + Self::calc_bitmap_size(num_records_per_page), size_of::<usize>())
     }
 
     fn calc_bitmap_size(size: usize) -> usize {
@@ -43,7 +59,20 @@ impl RecordFileManager {
         bitmap_size
     }
 
+    pub fn file_exists(file_name: &str) -> bool {
+        PageFileManager::file_exists(file_name)
+    }
+
     pub fn create_file(file_name: &String, pfm: &mut PageFileManager, record_size: usize) -> Result<RecordFileHandle, Error> {
+        Self::create_file_nullable(file_name, pfm, record_size, 0)
+    }
+
+    /*
+     * Create a record file whose records have num_columns nullable 
+     * columns, every record gets a null bitmap with a bit per column.
+     * With 0 columns, records have no null bitmaps.
+     */
+    pub fn create_file_nullable(file_name: &String, pfm: &mut PageFileManager, record_size: usize, num_columns: usize) -> Result<RecordFileHandle, Error> {
         let mut pfh = match pfm.create_file(file_name) {
             Err(e) => {
                 return Err(e);
@@ -61,22 +90,24 @@ impl RecordFileManager {
         let header = unsafe {
             &mut *(data as *mut RecordFileHeader)
         };
-        header.bitmap_offset = size_of::<RecordPageHeader>();
-        header.num_records_per_page = Self::calc_num_records_per_page(record_size);
+        header.null_bitmap_size = num_columns.div_ceil(8);
+        header.null_bitmap_offset = size_of::<RecordPageHeader>();
+        header.num_records_per_page = Self::calc_num_records_per_page(record_size, header.null_bitmap_size);
+        header.bitmap_offset = header.null_bitmap_offset + header.num_records_per_page * header.null_bitmap_size;
         header.bitmap_size = Self::calc_bitmap_size(header.num_records_per_page);
-        header.records_offset = header.bitmap_offset + header.bitmap_size;
+        header.records_offset = Self::calc_records_offset(header.num_records_per_page, header.null_bitmap_size);
         header.num_pages = 0;
+        header.total_records = 0;
+        header.first_free_page = 0;
         header.record_size = record_size;
         dbg!(&header);
 
-        if let Err(e) = pfh.unpin_dirty_page(ph.get_page_num()) {
-            return Err(e);
-        }
+        pfh.unpin_dirty_page(ph.get_page_num())?;
 
         Ok(RecordFileHandle::new(ph.get_page_num(), *header, &mut pfh))
     }
 
-    pub fn open_file(file_name: &String, pfm: &mut PageFileManager, record_size: usize) -> Result<RecordFileHandle, Error> {
+    pub fn open_file(file_name: &String, pfm: &mut PageFileManager, _record_size: usize) -> Result<RecordFileHandle, Error> {
         let mut pfh = match pfm.open_file(file_name) {
             Err(e) => {
                 return Err(e);
@@ -94,10 +125,44 @@ impl RecordFileManager {
             &mut *(data as *mut RecordFileHeader)
         };
         
-        if let Err(e) = pfh.unpin_page(ph.get_page_num()) {
-            return Err(e);
-        }
+        pfh.unpin_page(ph.get_page_num())?;
 
         Ok(RecordFileHandle::new(ph.get_page_num(), *header, &mut pfh))
     }
+
+    /*
+     * Open the record file if it exists, otherwise create it.
+     * The record size of an existing file must equal to record_size.
+     */
+    pub fn open_or_create_file(file_name: &str, pfm: &mut PageFileManager, record_size: usize) -> Result<RecordFileHandle, Error> {
+        let file_name = String::from(file_name);
+        if !Self::file_exists(&file_name) {
+            return Self::create_file(&file_name, pfm, record_size);
+        }
+
+        let rfh = match Self::open_file(&file_name, pfm, record_size) {
+            Err(e) => {
+                return Err(e);
+            },
+            Ok(v) => v
+        };
+        if rfh.get_header().record_size != record_size {
+            dbg!(rfh.get_header().record_size);
+            return Err(Error::RecordSizeMismatch);
+        }
+        Ok(rfh)
+    }
+
+    /*
+     * Remove a record file.
+     * Pages of the file in the buffer are evicted, so no handle of the 
+     * file should be used after the file is dropped.
+     */
+    pub fn drop_file(file_name: &str, pfm: &mut PageFileManager) -> Result<(), Error> {
+        if !Self::file_exists(file_name) {
+            dbg!(file_name);
+            return Err(Error::FileNotFound);
+        }
+        pfm.destroy_file(file_name)
+    }
 }
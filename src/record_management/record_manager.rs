@@ -25,75 +25,12 @@ use crate::errors::RecordError;
 use std::fs::File;
 use std::ptr::NonNull;
 use std::mem::size_of;
+use crate::types::{RID, Record};
 
 /*
  * We plan to represent a table using at lease one file.
  */
 
-#[derive(Debug, Copy, Clone)]
-pub struct RID {
-    page_num: u32,
-    slot_num: u32 //slot_num represents the location of a record in a page.
-}
-
-impl RID {
-    pub fn new(page_num: u32, slot_num: u32) -> Self {
-        RID {
-            page_num,
-            slot_num
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Record {
-    record_size: usize,
-    rid: RID,
-    data: *mut u8
-}
-
-impl Clone for Record {
-    fn clone(&self) -> Self {
-        Record {
-            record_size: self.record_size,
-            rid: self.rid,
-            data: {
-                let p = BufferManager::allocate_buffer(self.record_size);
-                unsafe {
-                    std::ptr::copy(self.data, p, self.record_size);
-                }
-                p
-            }
-        }
-    }
-}
-
-impl Record {
-    fn new(record_size: usize, rid: RID) -> Self {
-        Record {
-            record_size,
-            rid,
-            data: BufferManager::allocate_buffer(record_size)
-        }
-    }
-
-    fn new_with_data(record_size: usize, rid: RID, data: *mut u8) -> Self {
-        Record {
-            record_size,
-            rid,
-            data,
-        }
-    }
-
-    fn get_page_num(&self) -> u32 {
-        self.rid.page_num
-    }
-
-    fn get_slot_num(&self) -> u32 {
-        self.rid.slot_num
-    }
-}
-
 /*
  * RecordManager is in charge of inserting, deleting, getting a record 
  * from a page.
@@ -124,8 +61,8 @@ impl RecordManager {
         if let None = self.fp {
             return Err(RecordError::NoFilePointer);
         }
-        let mut res = Box::new(Record::new(self.record_size, *rid));
-        let page_pointer = self.page_file_manager.get_page(rid.page_num);
+        let mut res = Box::new(Record::new(self.record_size, *rid, vec![0; self.record_size]));
+        let page_pointer = self.page_file_manager.get_page(rid.get_page_num());
         if let None = page_pointer {
             //when the None is returned, the specific page is not pinned, so no need to
             //unpin the page.
@@ -138,7 +75,7 @@ impl RecordManager {
             return Err(e);
         }
 
-        self.page_file_manager.unpin_page(rid.page_num);//important.
+        self.page_file_manager.unpin_page(rid.get_page_num());//important.
         Ok(NonNull::new(Box::into_raw(res)).unwrap())
     }
 
@@ -155,7 +92,7 @@ impl RecordManager {
             record.as_ref()
         };
 
-        let page_pointer = self.page_file_manager.get_page(rec.rid.page_num);
+        let page_pointer = self.page_file_manager.get_page(rec.get_rid().get_page_num());
         if let None = page_pointer {
             return Err(RecordError::GetPageError);
         }
@@ -170,7 +107,7 @@ impl RecordManager {
             Ok(()) => {}
         }
         page.mark_dirty();//important
-        self.page_file_manager.unpin_page(rec.get_page_num());//important
+        self.page_file_manager.unpin_page(rec.get_rid().get_page_num());//important
         Ok(())
     }
 
@@ -226,9 +163,9 @@ impl RecordManager {
             slot_num = res.unwrap();
         }
         dbg!(&slot_num);
-        let rid = RID::new(page_num, slot_num);
+        let rid = RID::new(page_num, slot_num as usize);
         //copy data into page.
-        let record_offset = self.get_record_offset(rid.slot_num).expect("Get Record Offset Error");
+        let record_offset = self.get_record_offset(rid.get_slot_num() as u32).expect("Get Record Offset Error");
         dbg!(&record_offset);
         let record_ptr = unsafe {
             page.data.offset(record_offset as isize)
@@ -247,8 +184,8 @@ impl RecordManager {
      * Delete a record.
      */
     pub fn delete_record(&mut self, rid: RID) -> Result<(), RecordError> {
-        let page_num = rid.page_num;
-        let slot_num = rid.slot_num;
+        let page_num = rid.get_page_num();
+        let slot_num = rid.get_slot_num() as u32;
         dbg!(&page_num);
         let res = self.page_file_manager.get_page(page_num);
         if let None = res {
@@ -284,14 +221,14 @@ impl RecordManager {
      */
     fn write_record(&self, record: &mut Box<Record>, page: &mut BufferPage) -> Result<(), RecordError> {
         unsafe {
-            let res = self.get_record_offset(record.get_slot_num());
+            let res = self.get_record_offset(record.get_rid().get_slot_num() as u32);
             if let Err(e) = res {
                 dbg!(&e);
                 return Err(e);
             }
             let record_offset = res.unwrap();
             let record_slot = page.data.offset(record_offset as isize);
-            std::ptr::copy(record_slot, record.data, self.record_size);
+            std::ptr::copy(record_slot, record.get_data_mut().as_mut_ptr(), self.record_size);
         }
         Ok(())
     }
@@ -303,17 +240,17 @@ impl RecordManager {
         let record = unsafe {
             &mut *record_p.as_ptr()
         };
-        if record.data.is_null() || page.data.is_null() {
+        if record.get_data().len() != self.record_size || page.data.is_null() {
             return Err(RecordError::NullPointerError);
         }
-        let res = self.get_record_offset(record.get_slot_num());
+        let res = self.get_record_offset(record.get_rid().get_slot_num() as u32);
         if let Err(e) = res {
             return Err(e);
         }
         let record_offset = res.unwrap();
         
         unsafe {
-            std::ptr::copy(record.data, page.data.offset(record_offset as isize), self.record_size);
+            std::ptr::copy(record.get_data().as_ptr(), page.data.offset(record_offset as isize), self.record_size);
         }
         Ok(())
     }
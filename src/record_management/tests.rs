@@ -7,62 +7,53 @@
   > Copyright@ https://github.com/xiaoqixian
  **********************************************/
 
-const record_size: usize = 128;
+const RECORD_SIZE: usize = 128;
 
 use crate::page_management::page_file;
-use std::io;
-use std::io::prelude::*;
-
-fn get_data() -> *mut u8 {
-    use std::fs::OpenOptions;
-    let mut fp = OpenOptions::new().read(true).write(false).open("/home/lunar/Documents/w").expect("Open file failed");
-    let buffer = crate::utils::allocate_buffer(record_size);
-    let sli = unsafe {
-        std::slice::from_raw_parts_mut(buffer, record_size)
-    };
-    let res = fp.read(sli);
-    if let Err(e) = res {
-        panic!("read error");
-    }
-    let read_bytes = res.unwrap();
-    if read_bytes < record_size {
-        panic!("Incomplete read");
+use crate::test_utils::make_test_pfm;
+
+fn get_data() -> Vec<u8> {
+    let mut buffer = crate::utils::allocate_buffer_vec(RECORD_SIZE);
+    for (i, b) in buffer.iter_mut().enumerate() {
+        *b = i as u8;
     }
     buffer
 }
 
 #[test]
 fn record_manager_test1() {
-    let mut pfm = page_file::PageFileManager::new();
-    let mut rfh = super::record_file_manager::RecordFileManager::create_file(&String::from("Table1"), &mut pfm, record_size).expect("create rfh failed");
-    let data = get_data();
+    let _ = env_logger::try_init();
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("record_manager_table").to_str().unwrap().to_string();
+    let mut rfh = super::record_file_manager::RecordFileManager::create_file(&file_name, &mut pfm, RECORD_SIZE).expect("create rfh failed");
+    let mut data = get_data();
     use crate::record_management::record_file_handle::RID;
 
     let mut recs: Vec<RID> = Vec::new();
 
     for i in 0..40 {
-        match rfh.insert_record(data) {
+        match rfh.insert_record(data.as_mut_ptr()) {
             Ok(v) => {
                 dbg!(v);
                 recs.push(v);
             },
             Err(e) => {
                 dbg!(e);
-                panic!(format!("Insert {}th record error!", i));
+                panic!("Insert {}th record error!", i);
             }
         }
     }
 
     println!("\n----------Inserting New Records----------\n");
     for i in 0..40 {
-        match rfh.insert_record(data) {
+        match rfh.insert_record(data.as_mut_ptr()) {
             Ok(v) => {
                 dbg!(v);
                 recs.push(v);
             },
             Err(e) => {
                 dbg!(e);
-                panic!(format!("Insert {}th record error!", i));
+                panic!("Insert {}th record error!", i);
             }
         }
     }
@@ -70,7 +61,7 @@ fn record_manager_test1() {
     println!("\n--------Deleting Records------------\n");
     for rec in &recs {
         dbg!(&rec);
-        rfh.delete_record(rec).expect(format!("delete record {:?}  error", rec).as_str());
+        rfh.delete_record(rec).unwrap_or_else(|_| panic!("delete record {:?}  error", rec));
     }
     
     //rfh.delete_record(recs[39]).expect(format!("delete record {:?} error", recs[39]).as_str());
@@ -82,11 +73,219 @@ fn record_manager_test1() {
             //},
             //Err(e) => {
                 //dbg!(e);
-                //panic!(format!("Insert {}th record error!", i));
+                //panic!("Insert {}th record error!", i);
             //}
         //}
     //}
    
+    rfh.verify_bitmap_consistency().expect("inconsistent bitmap");
+}
+
+#[test]
+fn free_page_list_persist_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+    use crate::record_management::record_file_handle::RID;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("free_page_list_persist_test").to_str().unwrap().to_string();
+    let rec_size: usize = 200;//20 records a page.
+    let mut data = vec![1u8; rec_size];
+
+    let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+    let num_records_per_page = rfh.get_header().num_records_per_page;
+    assert_eq!(num_records_per_page, 20);
+
+    let mut recs: Vec<RID> = Vec::new();
+    for _ in 0..num_records_per_page {
+        recs.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error"));
+    }
+    let num_pages = rfh.get_header().num_pages;
+    for rec in &recs {
+        rfh.delete_record(rec).expect("delete record error");
+    }
+    rfh.close().expect("close rfh failed");
+    drop(rfh);
+
+    let mut rfh = RecordFileManager::open_file(&file_name, &mut pfm, rec_size).expect("open rfh failed");
+    for _ in 0..num_records_per_page {
+        rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    }
+    assert_eq!(rfh.get_header().num_pages, num_pages);
+    rfh.verify_bitmap_consistency().expect("inconsistent bitmap");
+}
+
+#[test]
+fn vacuum_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("vacuum_test_table").to_str().unwrap().to_string();
+    let rec_size: usize = 200;//20 records a page.
+    let mut data = vec![1u8; rec_size];
+
+    let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+    let mut recs = Vec::new();
+    for _ in 0..100 {
+        recs.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error"));
+    }
+    assert_eq!(rfh.get_header().num_pages, 5);
+
+    //empty the last 2 pages, and the first page which is kept.
+    for rec in recs[..20].iter().chain(recs[45..].iter()) {
+        rfh.delete_record(rec).expect("delete record error");
+    }
+    assert_eq!(rfh.vacuum().expect("vacuum failed"), 2);
+    assert_eq!(rfh.get_header().num_pages, 3);
+    //the header page and 3 record pages are left.
+    let page_size = std::mem::size_of::<page_file::PageHeader>() + page_file::PAGE_SIZE;
+    let file_size = page_file::PAGE_FILE_HEADER_SIZE + 4 * page_size;
+    assert_eq!(std::fs::metadata(&file_name).unwrap().len(), file_size as u64);
+    assert_eq!(rfh.vacuum().expect("vacuum failed"), 0);
+
+    //freed slots are still reused before new pages are allocated.
+    for _ in 0..35 {
+        rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    }
+    assert_eq!(rfh.get_header().num_pages, 3);
+    rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    assert_eq!(rfh.get_header().num_pages, 4);
+    rfh.verify_bitmap_consistency().expect("inconsistent bitmap");
+}
+
+//records start at an aligned offset after the bitmap, and the last slot still fits in the page.
+#[test]
+fn records_offset_alignment_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+    use std::mem::{align_of, size_of};
+
+    let (mut pfm, dir) = make_test_pfm();
+    for rec_size in [1usize, 3, 7, 12, 13, 100, 200, 204, 1000, 4000] {
+        let file_name = dir.path().join(format!("records_offset_alignment_test_{}", rec_size)).to_str().unwrap().to_string();
+        let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+        let header = *rfh.get_header();
+        assert_eq!(header.records_offset % size_of::<usize>(), 0);
+        //records are aligned in memory only if the page frame is.
+        let mut data = vec![0u8; rec_size];
+        let rid = rfh.insert_record(data.as_mut_ptr()).expect("insert record failed");
+        let mut pfh = pfm.open_file(&file_name).expect("open file failed");
+        let ph = pfh.get_page(rid.get_page_num()).expect("get page failed");
+        assert_eq!(ph.get_data() as usize % align_of::<page_file::PageHeader>(), 0);
+        assert_eq!((ph.get_data() as usize + header.records_offset) % size_of::<usize>(), 0);
+        pfh.unpin_page(rid.get_page_num()).expect("unpin page failed");
+        drop(pfh);
+        assert!(header.records_offset >= header.bitmap_offset + header.bitmap_size);
+        assert!(header.bitmap_size * 8 >= header.num_records_per_page);
+        assert!(header.num_records_per_page > 0);
+        assert!(header.records_offset + header.num_records_per_page * rec_size <= page_file::PAGE_SIZE, "record size {}", rec_size);
+    }
+}
+
+//free slots of all pages are exactly the records that fit before a new page is allocated.
+#[test]
+fn count_free_slots_total_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("count_free_slots_total_test").to_str().unwrap().to_string();
+    let rec_size: usize = 200;//20 records a page.
+    let mut data = vec![1u8; rec_size];
+
+    let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+    assert_eq!(rfh.count_free_slots_total().unwrap(), 0);
+    let mut recs = Vec::new();
+    for _ in 0..50 {
+        recs.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error"));
+    }
+    assert_eq!(rfh.count_free_slots_total().unwrap(), 10);
+    for rec in recs[5..12].iter() {
+        rfh.delete_record(rec).expect("delete record error");
+    }
+    let free_slots = rfh.count_free_slots_total().unwrap();
+    assert_eq!(free_slots, 17);
+
+    for _ in 0..free_slots {
+        rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    }
+    assert_eq!(rfh.get_header().num_pages, 3);
+    assert_eq!(rfh.count_free_slots_total().unwrap(), 0);
+    rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    assert_eq!(rfh.get_header().num_pages, 4);
+}
+
+//every record page is visited once, with the pages having free slots marked free.
+#[test]
+fn for_each_page_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("for_each_page_test").to_str().unwrap().to_string();
+    let rec_size: usize = 200;//20 records a page.
+    let mut data = vec![1u8; rec_size];
+
+    let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+    let mut recs = Vec::new();
+    for _ in 0..60 {
+        recs.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error"));
+    }
+    for rec in recs[..3].iter() {
+        rfh.delete_record(rec).expect("delete record error");
+    }
+
+    let mut pages = Vec::new();
+    let visited = rfh.for_each_page(|page_num, _, rph, is_free| {
+        pages.push((page_num, rph.get_num_records(), is_free));
+        true
+    }).expect("visit pages failed");
+    assert_eq!(visited, 3);
+    let first_page = recs[0].get_page_num();
+    for (page_num, num_records, is_free) in pages {
+        if page_num == first_page {
+            assert_eq!((num_records, is_free), (17, true));
+        } else {
+            assert_eq!((num_records, is_free), (20, false));
+        }
+    }
+
+    //stop at the first page.
+    assert_eq!(rfh.for_each_page(|_, _, _, _| false).unwrap(), 1);
+    rfh.verify_bitmap_consistency().expect("inconsistent bitmap");
+}
+
+//full pages are skipped in the free list, no page is allocated while another has free slots.
+#[test]
+fn free_list_skips_full_pages_test() {
+    let _ = env_logger::try_init();
+    use super::record_file_manager::RecordFileManager;
+
+    let (mut pfm, dir) = make_test_pfm();
+    let file_name = dir.path().join("free_list_skips_full_pages_test").to_str().unwrap().to_string();
+    let rec_size: usize = 200;//20 records a page.
+    let mut data = vec![1u8; rec_size];
+
+    let mut rfh = RecordFileManager::create_file(&file_name, &mut pfm, rec_size).expect("create rfh failed");
+    let mut recs = Vec::new();
+    for _ in 0..60 {
+        recs.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error"));
+    }
+    assert_eq!(rfh.get_header().num_pages, 3);
+
+    //one free slot in each of the first two pages.
+    rfh.delete_record(&recs[0]).expect("delete record error");
+    rfh.delete_record(&recs[20]).expect("delete record error");
+    let mut pages = Vec::new();
+    for _ in 0..2 {
+        pages.push(rfh.insert_record(data.as_mut_ptr()).expect("insert record error").get_page_num());
+    }
+    pages.sort();
+    assert_eq!(pages, vec![recs[0].get_page_num(), recs[20].get_page_num()]);
+    assert_eq!(rfh.get_header().num_pages, 3);
 
-    std::fs::remove_file("~/pros/arcturus/Table1");
+    rfh.insert_record(data.as_mut_ptr()).expect("insert record error");
+    assert_eq!(rfh.get_header().num_pages, 4);
+    rfh.verify_bitmap_consistency().expect("inconsistent bitmap");
 }
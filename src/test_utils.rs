@@ -0,0 +1,45 @@
+/*
+ * Helpers shared by unit tests.
+ *
+ * Test files live in the system temp directory and are removed
+ * when the returned guards are dropped, so tests do not depend
+ * on any path of the developer's machine.
+ */
+
+use std::io::Write;
+use tempfile::{NamedTempFile, TempDir};
+use crate::page_management::page_file::PageFileManager;
+
+/*
+ * create a temp file filled with size_bytes of known data,
+ * byte i of the file is (i % 256).
+ */
+pub fn create_test_file(size_bytes: u64) -> (NamedTempFile, ()) {
+    let mut f = NamedTempFile::new().expect("create temp file failed");
+    let chunk: Vec<u8> = (0..=255u8).collect();
+    let mut remain = size_bytes;
+    while remain > 0 {
+        let len = std::cmp::min(remain, chunk.len() as u64) as usize;
+        f.write_all(&chunk[..len]).expect("fill temp file failed");
+        remain -= len as u64;
+    }
+    f.flush().expect("flush temp file failed");
+    (f, ())
+}
+
+/*
+ * a PageFileManager together with the temp dir its files should
+ * be created in, name the files with dir.path().join(..).
+ * The dir is removed when the TempDir is dropped, so keep it alive
+ * as long as the manager.
+ */
+pub fn make_test_pfm() -> (PageFileManager, TempDir) {
+    let dir = tempfile::tempdir().expect("create temp dir failed");
+    (PageFileManager::new(), dir)
+}
+
+//same as make_test_pfm, with a buffer pool of num_pages pages.
+pub fn make_test_pfm_with_buffer_size(num_pages: usize) -> (PageFileManager, TempDir) {
+    let dir = tempfile::tempdir().expect("create temp dir failed");
+    (PageFileManager::with_buffer_size(num_pages), dir)
+}
@@ -0,0 +1,318 @@
+/*
+ * Types shared by multiple modules.
+ */
+
+use std::fmt;
+
+use crate::errors::Error;
+
+pub type PageNum = u32;//file_num << 16 | page index in the file.
+pub type SlotNum = u32;
+pub type FileNum = u16;
+
+/// Plain data types that any bytes of their size are a valid value 
+/// of, so they can be read straight out of a record.
+///
+/// # Safety
+/// Implementors must have no padding, no pointers and no invalid 
+/// bit patterns, e.g. a #[repr(C)] struct of integers laid out 
+/// without gaps.
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/*
+ * RID identifies a record by the page it's in and the slot 
+ * in the page.
+ * RIDs are ordered by page_num first, then slot_num.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RID {
+    page_num: PageNum,
+    slot_num: SlotNum //slot_num represents the location of a record in a page.
+}
+
+impl RID {
+    //0 is an invalid page number, and no page has u32::MAX slots.
+    pub const INVALID: RID = RID { page_num: 0, slot_num: u32::MAX };
+
+    pub fn new(page_num: u32, slot_num: usize) -> Self {
+        Self {
+            page_num,
+            slot_num: slot_num as u32,
+        }
+    }
+
+    pub fn get_page_num(&self) -> u32 {
+        self.page_num
+    }
+
+    pub fn get_slot_num(&self) -> usize {
+        self.slot_num as usize
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.page_num != 0 && self.slot_num != u32::MAX
+    }
+
+    //page_num in the high 32 bits, slot_num in the low 32 bits.
+    pub fn to_u64(self) -> u64 {
+        ((self.page_num as u64) << 32) | self.slot_num as u64
+    }
+
+    pub fn from_u64(v: u64) -> RID {
+        RID {
+            page_num: (v >> 32) as u32,
+            slot_num: v as u32
+        }
+    }
+}
+
+impl fmt::Display for RID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(page=0x{:08X}, slot={})", self.page_num, self.slot_num)
+    }
+}
+
+/*
+ * A record read from a record file, the data is a copy of the 
+ * record in the page.
+ */
+#[derive(Debug, Clone)]
+pub struct Record {
+    rid: RID,
+    record_size: usize,
+    data: Vec<u8>
+}
+
+impl Record {
+    pub fn new(record_size: usize, rid: RID, data: Vec<u8>) -> Self {
+        Self {
+            record_size,
+            rid,
+            data,
+        }
+    }
+
+    pub fn get_rid(&self) -> RID {
+        self.rid
+    }
+
+    pub fn get_record_size(&self) -> usize {
+        self.record_size
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+pub const MAX_COLUMN_NAME_LEN: usize = 32;
+
+//type tags of columns in a serialized schema.
+const TAG_INT: u8 = 1;
+const TAG_BIG_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_DOUBLE: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_BOOL: u8 = 6;
+
+//name, type tag, char length and nullable of a serialized column.
+const COLUMN_DEF_SIZE: usize = MAX_COLUMN_NAME_LEN + 1 + 4 + 1;
+
+/*
+ * Types of table columns, a Char column holds a fixed number 
+ * of bytes.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    BigInt,
+    Float,
+    Double,
+    Char(usize),
+    Bool
+}
+
+impl ColumnType {
+    pub fn get_size(&self) -> usize {
+        match self {
+            ColumnType::Int => 4,
+            ColumnType::BigInt => 8,
+            ColumnType::Float => 4,
+            ColumnType::Double => 8,
+            ColumnType::Char(len) => *len,
+            ColumnType::Bool => 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ColumnDef {
+    pub name: [u8; MAX_COLUMN_NAME_LEN],//null-padded
+    pub col_type: ColumnType,
+    pub nullable: bool
+}
+
+impl ColumnDef {
+    //a name longer than MAX_COLUMN_NAME_LEN is cut.
+    pub fn new(name: &str, col_type: ColumnType, nullable: bool) -> Self {
+        let mut name_bytes = [0u8; MAX_COLUMN_NAME_LEN];
+        let len = name.len().min(MAX_COLUMN_NAME_LEN);
+        name_bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            name: name_bytes,
+            col_type,
+            nullable
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(MAX_COLUMN_NAME_LEN);
+        String::from_utf8_lossy(&self.name[..len]).into_owned()
+    }
+}
+
+/*
+ * Columns of a table.
+ * A record starts with a null bitmap, which has one bit for every
+ * nullable column, followed by the columns in order.
+ *
+ * A serialized schema:
+ *  |num_columns: u16|column|column|...
+ * and a column:
+ *  |name: 32 bytes|type tag: u8|char length: u32|nullable: u8|
+ * All integers are little endian.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableSchema {
+    pub columns: Vec<ColumnDef>
+}
+
+impl TableSchema {
+    pub fn new(columns: Vec<ColumnDef>) -> Self {
+        Self {
+            columns
+        }
+    }
+
+    pub fn record_size(&self) -> usize {
+        let num_nullable = self.columns.iter().filter(|c| c.nullable).count();
+        let bitmap_size = num_nullable.div_ceil(8);
+        bitmap_size + self.columns.iter().map(|c| c.col_type.get_size()).sum::<usize>()
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(2 + self.columns.len() * COLUMN_DEF_SIZE);
+        buf.extend_from_slice(&(self.columns.len() as u16).to_le_bytes());
+        for col in self.columns.iter() {
+            buf.extend_from_slice(&col.name);
+            let (tag, char_len) = match col.col_type {
+                ColumnType::Int => (TAG_INT, 0),
+                ColumnType::BigInt => (TAG_BIG_INT, 0),
+                ColumnType::Float => (TAG_FLOAT, 0),
+                ColumnType::Double => (TAG_DOUBLE, 0),
+                ColumnType::Char(len) => (TAG_CHAR, len as u32),
+                ColumnType::Bool => (TAG_BOOL, 0),
+            };
+            buf.push(tag);
+            buf.extend_from_slice(&char_len.to_le_bytes());
+            buf.push(col.nullable as u8);
+        }
+        buf
+    }
+
+    /*
+     * Bytes after the last column are ignored, so a schema can 
+     * be read from a zero padded page.
+     */
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            dbg!(bytes.len());
+            return Err(Error::InvalidSchema);
+        }
+        let num_columns = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        if bytes.len() < 2 + num_columns * COLUMN_DEF_SIZE {
+            dbg!((num_columns, bytes.len()));
+            return Err(Error::InvalidSchema);
+        }
+
+        let mut columns = Vec::with_capacity(num_columns);
+        for col_bytes in bytes[2..].chunks_exact(COLUMN_DEF_SIZE).take(num_columns) {
+            let mut name = [0u8; MAX_COLUMN_NAME_LEN];
+            name.copy_from_slice(&col_bytes[..MAX_COLUMN_NAME_LEN]);
+            let tag = col_bytes[MAX_COLUMN_NAME_LEN];
+            let mut len_bytes = [0u8; 4];
+            len_bytes.copy_from_slice(&col_bytes[MAX_COLUMN_NAME_LEN+1..MAX_COLUMN_NAME_LEN+5]);
+            let col_type = match tag {
+                TAG_INT => ColumnType::Int,
+                TAG_BIG_INT => ColumnType::BigInt,
+                TAG_FLOAT => ColumnType::Float,
+                TAG_DOUBLE => ColumnType::Double,
+                TAG_CHAR => ColumnType::Char(u32::from_le_bytes(len_bytes) as usize),
+                TAG_BOOL => ColumnType::Bool,
+                _ => {
+                    dbg!(tag);
+                    return Err(Error::InvalidSchema);
+                }
+            };
+            let nullable = match col_bytes[MAX_COLUMN_NAME_LEN+5] {
+                0 => false,
+                1 => true,
+                other => {
+                    dbg!(other);
+                    return Err(Error::InvalidSchema);
+                }
+            };
+            columns.push(ColumnDef {
+                name,
+                col_type,
+                nullable
+            });
+        }
+        Ok(Self {
+            columns
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColumnDef, ColumnType, TableSchema};
+    use crate::errors::Error;
+
+    #[test]
+    fn table_schema_test() {
+        let schema = TableSchema::new(vec![
+            ColumnDef::new("id", ColumnType::BigInt, false),
+            ColumnDef::new("name", ColumnType::Char(20), true),
+            ColumnDef::new("score", ColumnType::Double, true),
+            ColumnDef::new("a_column_name_longer_than_32_bytes", ColumnType::Bool, false),
+        ]);
+        //one bitmap byte for the two nullable columns.
+        assert_eq!(schema.record_size(), 1 + 8 + 20 + 8 + 1);
+        assert_eq!(schema.columns[3].get_name(), "a_column_name_longer_than_32_byt");
+
+        let mut bytes = schema.serialize();
+        assert_eq!(TableSchema::deserialize(&bytes).unwrap(), schema);
+        //padding after the schema is ignored.
+        bytes.resize(4096, 0);
+        assert_eq!(TableSchema::deserialize(&bytes).unwrap(), schema);
+
+        assert!(matches!(TableSchema::deserialize(&bytes[..40]), Err(Error::InvalidSchema)));
+        bytes[2 + 32] = 0xff;
+        assert!(matches!(TableSchema::deserialize(&bytes), Err(Error::InvalidSchema)));
+    }
+}
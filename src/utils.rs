@@ -12,41 +12,80 @@
  */
 
 
-pub fn allocate_buffer(size: usize) -> *mut u8 {
+/// The buffer is not freed automatically, it's aligned as a PageHeader
+/// so a page can be laid out in it.
+///
+/// # Safety
+/// The buffer must be freed by deallocate_buffer with the same size.
+#[deprecated(note = "the buffer leaks if not deallocated, use allocate_buffer_vec instead")]
+pub unsafe fn allocate_buffer(size: usize) -> *mut u8 {
     use std::alloc::{self, Layout};
-    use std::mem::size_of;
-    let layout = Layout::from_size_align(size, size_of::<u8>()).expect("create layout error");
-    unsafe {
-        alloc::alloc(layout)
-    }
+    use std::mem::align_of;
+    use crate::page_management::page_file::PageHeader;
+    let layout = Layout::from_size_align(size, align_of::<PageHeader>()).expect("create layout error");
+    alloc::alloc(layout)
+}
+
+//allocate a zeroed buffer of size bytes.
+//the memory is freed when the Vec is dropped.
+pub fn allocate_buffer_vec(size: usize) -> Vec<u8> {
+    vec![0u8; size]
 }
 
-pub fn deallocate_buffer(ptr: *mut u8, size: usize) {
+/// # Safety
+/// ptr must come from allocate_buffer with the same size.
+#[deprecated(note = "only for buffers from allocate_buffer, use allocate_buffer_vec instead")]
+pub unsafe fn deallocate_buffer(ptr: *mut u8, size: usize) {
     use std::alloc::{self, Layout};
-    use std::mem::size_of;
-    let layout = Layout::from_size_align(size, size_of::<u8>()).expect("create layout error");
-    unsafe {
-        alloc::dealloc(ptr, layout);
-    }
+    use std::mem::align_of;
+    use crate::page_management::page_file::PageHeader;
+    let layout = Layout::from_size_align(size, align_of::<PageHeader>()).expect("create layout error");
+    alloc::dealloc(ptr, layout);
+}
+
+
+//round val up to a multiple of align, align must be a power of 2.
+pub fn align_up(val: usize, align: usize) -> usize {
+    debug_assert!(align.is_power_of_two());
+    (val + align - 1) & !(align - 1)
 }
 
+//bit col of a null bitmap, bits are ordered from the most significant bit of a byte.
+pub fn is_null(null_bitmap: &[u8], col: usize) -> bool {
+    null_bitmap[col/8] & (1<<(7 - col%8)) != 0
+}
 
 //provide a raw pointer, offset, and array length. 
 //return a reference to an array.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn get_arr<T>(p: *const u8, offset: usize, len: usize) -> &'static [T] {
     unsafe {
-        let ap = p.offset(offset as isize) as *const T;
+        let ap = p.add(offset) as *const T;
         std::slice::from_raw_parts(ap, len)
     }
 }
 
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub fn get_arr_mut<T>(p: *mut u8, offset: usize, len: usize) -> &'static mut [T] {
     unsafe {
-        let ap = p.offset(offset as isize) as *mut T;
+        let ap = p.add(offset) as *mut T;
         std::slice::from_raw_parts_mut(ap, len)
     }
 }
 
+/// A slice of len bytes at offset of a page, data is the start of 
+/// the page including the page header.
+///
+/// # Safety
+/// The caller must make sure data points to a pinned page.
+pub unsafe fn page_data_slice<'a>(data: *mut u8, offset: usize, len: usize) -> &'a mut [u8] {
+    use crate::page_management::page_file::{PageHeader, PAGE_SIZE};
+    use std::mem::size_of;
+    debug_assert!(offset + len <= PAGE_SIZE + size_of::<PageHeader>(), 
+        "page data slice out of bounds: offset {} + len {} > page size {}", offset, len, PAGE_SIZE + size_of::<PageHeader>());
+    std::slice::from_raw_parts_mut(data.add(offset), len)
+}
+
 //get header from a raw pointer. offset is 0 by default
 //this is generic function.
 pub fn get_header<T>(data: *mut u8) -> &'static T {
@@ -55,8 +94,33 @@ pub fn get_header<T>(data: *mut u8) -> &'static T {
     }
 }
 
-pub fn get_header_mut<T>(data: *mut u8) -> &'static mut T {
-    unsafe {
-        &mut *(data as *mut T)
+/// # Safety
+/// The caller must make sure data points to a pinned page, and the 
+/// reference is not used after the page is unpinned.
+pub unsafe fn get_header_mut<'a, T>(data: *mut u8) -> &'a mut T {
+    &mut *(data as *mut T)
+}
+
+#[cfg(test)]
+mod tests {
+    //memory errors of buffers can be checked with: cargo miri test utils
+    #[test]
+    fn allocate_buffer_vec_test() {
+        let mut buffer = super::allocate_buffer_vec(4096);
+        assert_eq!(buffer.len(), 4096);
+        assert!(buffer.iter().all(|&b| b == 0));
+        buffer[4095] = 1;
+        let copied = buffer.clone();
+        drop(buffer);
+        assert_eq!(copied[4095], 1);
+    }
+
+    #[test]
+    fn align_up_test() {
+        assert_eq!(super::align_up(0, 8), 0);
+        assert_eq!(super::align_up(1, 8), 8);
+        assert_eq!(super::align_up(8, 8), 8);
+        assert_eq!(super::align_up(19, 8), 24);
+        assert_eq!(super::align_up(19, 1), 19);
     }
 }
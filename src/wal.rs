@@ -0,0 +1,396 @@
+/*
+ * Write ahead log.
+ * Every change to a page is logged before the page is written
+ * back, so the changes can be redone or undone after a crash.
+ *
+ * A record in the log file:
+ *  |length: u32|crc32: u32|payload|
+ * length is the length of the payload, and the crc32 is computed
+ * over the payload. Both are little endian.
+ *
+ * The payload starts with a one byte tag telling the kind of the
+ * record, followed by the txn_id. A PageWrite record also carries
+ * the page_num and the page images before and after the write, each
+ * image is prefixed by its length.
+ */
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write, ErrorKind};
+
+use crate::types::PageNum;
+
+const TAG_BEGIN: u8 = 1;
+const TAG_PAGE_WRITE: u8 = 2;
+const TAG_COMMIT: u8 = 3;
+const TAG_ROLLBACK: u8 = 4;
+
+//length and crc32 of a record.
+const RECORD_HEADER_SIZE: usize = 8;
+//a PageWrite record carries two page images, which is far less than this.
+//a longer length in a record header is garbage, so it's not allocated.
+const MAX_PAYLOAD_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalRecord {
+    Begin { txn_id: u64 },
+    PageWrite { txn_id: u64, page_num: PageNum, before: Vec<u8>, after: Vec<u8> },
+    Commit { txn_id: u64 },
+    Rollback { txn_id: u64 },
+}
+
+impl WalRecord {
+    pub fn get_txn_id(&self) -> u64 {
+        match self {
+            WalRecord::Begin { txn_id } => *txn_id,
+            WalRecord::PageWrite { txn_id, .. } => *txn_id,
+            WalRecord::Commit { txn_id } => *txn_id,
+            WalRecord::Rollback { txn_id } => *txn_id,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            WalRecord::Begin { txn_id } => {
+                buf.push(TAG_BEGIN);
+                buf.extend_from_slice(&txn_id.to_le_bytes());
+            },
+            WalRecord::PageWrite { txn_id, page_num, before, after } => {
+                buf.push(TAG_PAGE_WRITE);
+                buf.extend_from_slice(&txn_id.to_le_bytes());
+                buf.extend_from_slice(&page_num.to_le_bytes());
+                buf.extend_from_slice(&(before.len() as u32).to_le_bytes());
+                buf.extend_from_slice(before);
+                buf.extend_from_slice(&(after.len() as u32).to_le_bytes());
+                buf.extend_from_slice(after);
+            },
+            WalRecord::Commit { txn_id } => {
+                buf.push(TAG_COMMIT);
+                buf.extend_from_slice(&txn_id.to_le_bytes());
+            },
+            WalRecord::Rollback { txn_id } => {
+                buf.push(TAG_ROLLBACK);
+                buf.extend_from_slice(&txn_id.to_le_bytes());
+            },
+        }
+    }
+
+    fn decode(payload: &[u8]) -> io::Result<Self> {
+        let mut cursor = PayloadCursor { payload, pos: 0 };
+        let tag = cursor.take(1)?[0];
+        let txn_id = cursor.take_u64()?;
+        let rec = match tag {
+            TAG_BEGIN => WalRecord::Begin { txn_id },
+            TAG_PAGE_WRITE => {
+                let page_num = cursor.take_u32()?;
+                let before_len = cursor.take_u32()? as usize;
+                let before = cursor.take(before_len)?.to_vec();
+                let after_len = cursor.take_u32()? as usize;
+                let after = cursor.take(after_len)?.to_vec();
+                WalRecord::PageWrite { txn_id, page_num, before, after }
+            },
+            TAG_COMMIT => WalRecord::Commit { txn_id },
+            TAG_ROLLBACK => WalRecord::Rollback { txn_id },
+            _ => {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!("unknown wal record tag {}", tag)));
+            }
+        };
+        if cursor.pos != payload.len() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "trailing bytes in wal record"));
+        }
+        Ok(rec)
+    }
+}
+
+struct PayloadCursor<'a> {
+    payload: &'a [u8],
+    pos: usize
+}
+
+impl<'a> PayloadCursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.payload.len() {
+            return Err(io::Error::new(ErrorKind::InvalidData, "wal record payload too short"));
+        }
+        let res = &self.payload[self.pos..self.pos+len];
+        self.pos += len;
+        Ok(res)
+    }
+
+    fn take_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn take_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+/*
+ * WalWriter appends records to the end of a log file.
+ * buf is reused for encoding records, so that a record is
+ * written to the file with a single write.
+ * A torn record at the end of the log is cut off when the log is
+ * opened, or the records appended after it could never be read.
+ */
+#[derive(Debug)]
+pub struct WalWriter {
+    file: File,
+    buf: Vec<u8>
+}
+
+impl WalWriter {
+    //open a log file for appending, the file is created if not exists.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let valid_len = WalReader::valid_len(&mut file)?;
+        if valid_len < file.metadata()?.len() {
+            file.set_len(valid_len)?;
+        }
+        Ok(Self {
+            file,
+            buf: Vec::new()
+        })
+    }
+
+    pub fn write(&mut self, rec: &WalRecord) -> io::Result<()> {
+        self.buf.clear();
+        self.buf.extend_from_slice(&[0u8; RECORD_HEADER_SIZE]);
+        rec.encode(&mut self.buf);
+
+        if self.buf.len() - RECORD_HEADER_SIZE > MAX_PAYLOAD_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "wal record too long"));
+        }
+        let payload_len = (self.buf.len() - RECORD_HEADER_SIZE) as u32;
+        let crc = crc32fast::hash(&self.buf[RECORD_HEADER_SIZE..]);
+        self.buf[0..4].copy_from_slice(&payload_len.to_le_bytes());
+        self.buf[4..8].copy_from_slice(&crc.to_le_bytes());
+
+        self.file.write_all(&self.buf)
+    }
+
+    //make sure all written records reach the disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/*
+ * WalReader reads records from the start of a log file.
+ * A record cut off at the end of the file is the one being
+ * written when the system crashed, it's ignored and the
+ * iteration just stops there.
+ * A record with a wrong checksum is returned as an InvalidData
+ * error, and the iteration stops after it.
+ */
+#[derive(Debug)]
+pub struct WalReader {
+    file: File
+}
+
+impl WalReader {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        Ok(Self {
+            file
+        })
+    }
+
+    pub fn iter(&mut self) -> impl Iterator<Item = io::Result<WalRecord>> + '_ {
+        let mut done = self.file.seek(SeekFrom::Start(0)).is_err();
+        let file = &mut self.file;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let res = Self::read_record(file);
+            match res {
+                Ok(Some(rec)) => Some(Ok(rec)),
+                Ok(None) => {
+                    done = true;
+                    None
+                },
+                Err(e) => {
+                    done = true;
+                    Some(Err(e))
+                }
+            }
+        })
+    }
+
+    //the largest transaction id in the log, 0 if the log is empty.
+    pub fn max_txn_id(&mut self) -> io::Result<u64> {
+        let mut max_id = 0;
+        for res in self.iter() {
+            max_id = max_id.max(res?.get_txn_id());
+        }
+        Ok(max_id)
+    }
+
+    /*
+     * length of the log up to the end of the last record that can be 
+     * read, a torn or broken record and everything after it are beyond.
+     */
+    fn valid_len(file: &mut File) -> io::Result<u64> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut valid_len = 0;
+        loop {
+            match Self::read_record(file) {
+                Ok(Some(_)) => {
+                    valid_len = file.stream_position()?;
+                },
+                Ok(None) => break,
+                Err(e) if e.kind() == ErrorKind::InvalidData => break,
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(valid_len)
+    }
+
+    //Ok(None) is returned at the end of the log.
+    fn read_record(file: &mut File) -> io::Result<Option<WalRecord>> {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !Self::read_full(file, &mut header)? {
+            return Ok(None);
+        }
+        let mut len_bytes = [0u8; 4];
+        let mut crc_bytes = [0u8; 4];
+        len_bytes.copy_from_slice(&header[0..4]);
+        crc_bytes.copy_from_slice(&header[4..8]);
+        let payload_len = u32::from_le_bytes(len_bytes) as usize;
+        let crc = u32::from_le_bytes(crc_bytes);
+        if payload_len > MAX_PAYLOAD_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidData, "wal record length too large"));
+        }
+
+        let mut payload = vec![0u8; payload_len];
+        if !Self::read_full(file, &mut payload)? {
+            return Ok(None);
+        }
+        if crc32fast::hash(&payload) != crc {
+            return Err(io::Error::new(ErrorKind::InvalidData, "wal record checksum mismatch"));
+        }
+        WalRecord::decode(&payload).map(Some)
+    }
+
+    //returns false if the file ends before buf is filled.
+    fn read_full(file: &mut File, buf: &mut [u8]) -> io::Result<bool> {
+        match file.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wal_roundtrip_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal_roundtrip").to_str().unwrap().to_string();
+        let records = vec![
+            WalRecord::Begin { txn_id: 1 },
+            WalRecord::PageWrite { txn_id: 1, page_num: (1<<16) | 3, before: vec![0u8; 16], after: vec![7u8; 16] },
+            WalRecord::Commit { txn_id: 1 },
+            WalRecord::Begin { txn_id: 2 },
+            WalRecord::Rollback { txn_id: 2 },
+        ];
+        {
+            let mut writer = WalWriter::open(&path).unwrap();
+            for rec in records.iter() {
+                writer.write(rec).unwrap();
+            }
+            writer.sync().unwrap();
+        }
+
+        let mut reader = WalReader::open(&path).unwrap();
+        let read: Vec<WalRecord> = reader.iter().map(|r| r.unwrap()).collect();
+        assert_eq!(read, records);
+        assert_eq!(reader.max_txn_id().unwrap(), 2);
+
+        //a record cut off by a crash is ignored.
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+        let read: Vec<WalRecord> = reader.iter().map(|r| r.unwrap()).collect();
+        assert_eq!(read, records[..records.len()-1]);
+    }
+
+    #[test]
+    fn wal_checksum_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal_checksum").to_str().unwrap().to_string();
+        {
+            let mut writer = WalWriter::open(&path).unwrap();
+            writer.write(&WalRecord::Commit { txn_id: 42 }).unwrap();
+        }
+        //flip the last byte of the txn_id.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut reader = WalReader::open(&path).unwrap();
+        let mut iter = reader.iter();
+        assert_eq!(iter.next().unwrap().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(iter.next().is_none());
+        drop(iter);
+    }
+
+    //records appended after a torn record are read after reopening.
+    #[test]
+    fn wal_append_after_torn_tail_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal_torn_tail").to_str().unwrap().to_string();
+        let records = [
+            WalRecord::Begin { txn_id: 1 },
+            WalRecord::PageWrite { txn_id: 1, page_num: (1<<16) | 3, before: vec![0u8; 16], after: vec![7u8; 16] },
+            WalRecord::Commit { txn_id: 1 },
+            WalRecord::Begin { txn_id: 2 },
+            WalRecord::Commit { txn_id: 2 },
+        ];
+        {
+            let mut writer = WalWriter::open(&path).unwrap();
+            for rec in records[..3].iter() {
+                writer.write(rec).unwrap();
+            }
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+        {
+            let mut writer = WalWriter::open(&path).unwrap();
+            for rec in records[3..].iter() {
+                writer.write(rec).unwrap();
+            }
+        }
+        let expected: Vec<WalRecord> = records[..2].iter().chain(records[3..].iter()).cloned().collect();
+        let mut reader = WalReader::open(&path).unwrap();
+        let read: Vec<WalRecord> = reader.iter().map(|r| r.unwrap()).collect();
+        assert_eq!(read, expected);
+        assert_eq!(reader.max_txn_id().unwrap(), 2);
+    }
+
+    //a garbage length is rejected before the payload is allocated.
+    #[test]
+    fn wal_payload_len_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal_payload_len").to_str().unwrap().to_string();
+        let mut bytes = u32::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0u8; 4]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut reader = WalReader::open(&path).unwrap();
+        assert_eq!(reader.iter().next().unwrap().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let mut writer = WalWriter::open(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        let rec = WalRecord::PageWrite { txn_id: 1, page_num: 1, before: vec![0u8; MAX_PAYLOAD_SIZE], after: Vec::new() };
+        assert_eq!(writer.write(&rec).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * Property tests of B+ tree invariants.
+ *
+ * Random sequences of insertions and deletions are applied to an
+ * index and to a model of the live entries, after each operation
+ * the index is checked against the model.
+ *
+ * Keys come from a narrow range, so most keys have several rids and
+ * go to buckets, or from a wide range of long string keys. A node
+ * holds only a few keys of STRING_KEY_LEN bytes, so the wide range
+ * splits and merges nodes within a few hundred operations.
+ *
+ * MemoryBackend only backs the reads and writes of a BufferManager,
+ * PageFileManager creates, opens and removes page files by name, so
+ * the index works on a real file in a temp dir of each case.
+ */
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use rust::errors::{Error, IndexingError};
+use rust::indexing::file_manager::IndexFileManager;
+use rust::indexing::AttrType;
+use rust::page_management::page_file::PageFileManager;
+use rust::record_management::record_file_handle::RID;
+
+const NARROW_KEY_SPACE: i32 = 20;
+const WIDE_KEY_SPACE: i32 = 5000;
+const STRING_KEY_LEN: usize = 255;
+const MAX_OPS: usize = 200;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(i32, u32, u32),
+    Delete(i32, u32, u32),
+    //delete a live entry picked from the model.
+    Remove(Index)
+}
+
+//page nums and slot nums are kept small so deletions hit live entries.
+fn op_strategy(key_space: i32) -> impl Strategy<Value = Op> {
+    let entry = (0..key_space, 1..4u32, 0..4u32);
+    prop_oneof![
+        3 => entry.clone().prop_map(|(k, p, s)| Op::Insert(k, p, s)),
+        1 => entry.prop_map(|(k, p, s)| Op::Delete(k, p, s)),
+        1 => any::<Index>().prop_map(Op::Remove)
+    ]
+}
+
+/*
+ * INT keys are the key itself, STRING keys are the key in decimal
+ * padded with zeros, so both sort as the key does.
+ */
+fn encode_key(attr_type: AttrType, key: i32) -> Vec<u8> {
+    match attr_type {
+        AttrType::STRING => {
+            let mut buf = vec![0u8; STRING_KEY_LEN];
+            let digits = format!("{:08}", key);
+            buf[..digits.len()].copy_from_slice(digits.as_bytes());
+            buf
+        },
+        _ => key.to_ne_bytes().to_vec()
+    }
+}
+
+fn rid_of(page_num: u32, slot_num: u32) -> RID {
+    RID::new(page_num, slot_num as usize)
+}
+
+fn rid_tuple(rid: &RID) -> (u32, usize) {
+    (rid.get_page_num(), rid.get_slot_num())
+}
+
+/*
+ * a full scan is sorted when it splits into runs of each live key
+ * in ascending order, and every run holds exactly the rids of its key.
+ */
+fn check_scan(scanned: &[RID], model: &[(i32, u32, u32)]) -> Result<(), TestCaseError> {
+    prop_assert_eq!(scanned.len(), model.len());
+    let mut keys: Vec<i32> = model.iter().map(|e| e.0).collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut start = 0;
+    for key in keys {
+        let mut expected: Vec<(u32, usize)> = model.iter()
+            .filter(|e| e.0 == key)
+            .map(|e| (e.1, e.2 as usize))
+            .collect();
+        let end = start + expected.len();
+        let mut run: Vec<(u32, usize)> = scanned[start..end].iter().map(rid_tuple).collect();
+        expected.sort_unstable();
+        run.sort_unstable();
+        prop_assert_eq!(run, expected, "entries of key {} out of order", key);
+        start = end;
+    }
+    Ok(())
+}
+
+fn run_ops(ops: &[Op], attr_type: AttrType, attr_length: usize) -> Result<(), TestCaseError> {
+    let dir = tempfile::tempdir().unwrap();
+    let file_name = dir.path().join("btree_props").to_str().unwrap().to_string();
+    let mut pfm = PageFileManager::new();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, attr_type, attr_length).expect("create index failed");
+    let mut model: Vec<(i32, u32, u32)> = Vec::new();
+
+    for op in ops.iter() {
+        match *op {
+            Op::Insert(key, page_num, slot_num) => {
+                let mut key_val = encode_key(attr_type, key);
+                let res = ih.insert_entry(key_val.as_mut_ptr(), &rid_of(page_num, slot_num));
+                //an entry already in the index is rejected.
+                if model.contains(&(key, page_num, slot_num)) {
+                    prop_assert!(matches!(res, Err(Error::Indexing(IndexingError::DuplicateRid))), "{:?} accepted twice", op);
+                } else {
+                    res.expect("insert entry failed");
+                    model.push((key, page_num, slot_num));
+                }
+            },
+            Op::Delete(key, page_num, slot_num) => {
+                //only live entries are deleted.
+                if let Some(pos) = model.iter().position(|e| *e == (key, page_num, slot_num)) {
+                    let mut key_val = encode_key(attr_type, key);
+                    ih.delete_entry(key_val.as_mut_ptr(), &rid_of(page_num, slot_num)).expect("delete entry failed");
+                    model.swap_remove(pos);
+                }
+            },
+            Op::Remove(ref index) => {
+                if !model.is_empty() {
+                    let (key, page_num, slot_num) = model.swap_remove(index.index(model.len()));
+                    let mut key_val = encode_key(attr_type, key);
+                    ih.delete_entry(key_val.as_mut_ptr(), &rid_of(page_num, slot_num)).expect("delete entry failed");
+                }
+            }
+        }
+
+        prop_assert!(ih.validate_leaf_chain().is_ok(), "leaf chain broken after {:?}", op);
+        prop_assert_eq!(ih.entry_count(), model.len());
+        let scanned: Vec<RID> = ih.sequential_scan().unwrap().map(|r| r.unwrap()).collect();
+        check_scan(&scanned, &model)?;
+    }
+    ih.close().expect("close index failed");
+    Ok(())
+}
+
+//the wide keys are only worth running if a few dozen of them split the root.
+#[test]
+fn wide_keys_split_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_name = dir.path().join("wide_keys").to_str().unwrap().to_string();
+    let mut pfm = PageFileManager::new();
+    let mut ih = IndexFileManager::create_file(&file_name, 0, &mut pfm, AttrType::STRING, STRING_KEY_LEN).expect("create index failed");
+    for i in 0..50 {
+        let mut key_val = encode_key(AttrType::STRING, i);
+        ih.insert_entry(key_val.as_mut_ptr(), &rid_of(1, i as u32)).expect("insert entry failed");
+    }
+    assert!(ih.tree_height().unwrap() > 1);
+    ih.close().expect("close index failed");
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn btree_invariants_narrow_keys(ops in prop::collection::vec(op_strategy(NARROW_KEY_SPACE), 1..=MAX_OPS)) {
+        run_ops(&ops, AttrType::INT, 4)?;
+    }
+
+    #[test]
+    fn btree_invariants_wide_keys(ops in prop::collection::vec(op_strategy(WIDE_KEY_SPACE), 1..=MAX_OPS)) {
+        run_ops(&ops, AttrType::STRING, STRING_KEY_LEN)?;
+    }
+}